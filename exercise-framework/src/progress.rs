@@ -164,7 +164,17 @@ impl ProgressTracker {
     /// Load progress from file
     fn load_progress(path: &Path) -> Result<UserProgress> {
         let content = std::fs::read_to_string(path)?;
-        let progress: UserProgress = serde_json::from_str(&content)?;
+        let value: serde_json::Value = serde_json::from_str(&content)?;
+        // The web server versions its progress files and upgrades this format on
+        // load; don't read (and later overwrite) one of its files
+        if let Some(version) = value.get("schema_version") {
+            anyhow::bail!(
+                "{} was written by the Rust Tour server (progress schema {}) and can't be read by the exercise framework",
+                path.display(),
+                version
+            );
+        }
+        let progress: UserProgress = serde_json::from_value(value)?;
         Ok(progress)
     }
 
@@ -21,14 +21,14 @@ fn main() {
             let needs_build = !dist_dir.exists() 
                 || !dist_dir.join("index.html").exists()
                 || env::var("FORCE_FRONTEND_BUILD").is_ok()
-                || source_files_newer_than_dist(&web_dir, &dist_dir);
+                || source_files_newer_than_dist(web_dir, &dist_dir);
             
             if needs_build {
                 println!("cargo:warning=Building frontend assets for embedding...");
                 
                 // Change to web directory and run npm run build
                 let output = Command::new("npm")
-                    .args(&["run", "build"])
+                    .args(["run", "build"])
                     .current_dir(web_dir)
                     .output()
                     .expect("Failed to execute npm run build");
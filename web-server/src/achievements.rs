@@ -0,0 +1,176 @@
+// Achievements
+//
+// Achievements are defined in a JSON data file (`achievements.json`, built in
+// unless `--achievements-file` points elsewhere): an id, title, description,
+// icon, points and the rule that earns it. The progress task evaluates every
+// rule after each change, records newly earned achievements in the learner's
+// progress with the time they were earned and broadcasts an
+// `achievement_unlocked` message for each. Earned achievements are never taken
+// away, even if the definitions change later.
+//
+// Rules only look at the exercise history and the chapter statistics, so
+// achievements earned before the server knew about them are picked up the next
+// time progress is loaded.
+
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::ProgressData;
+
+const DEFAULT_ACHIEVEMENTS: &str = include_str!("../achievements.json");
+
+// An achievement the learner has earned, as stored in `ProgressData`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct Achievement {
+    pub id: String,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub points: u32,
+    #[serde(default)]
+    pub earned_at: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AchievementRule {
+    // At least `count` exercises completed
+    ExercisesCompleted { count: u32 },
+    // Every exercise of one chapter completed
+    ChapterCompleted { chapter: u32 },
+    // At least `count` chapters fully completed
+    ChaptersCompleted { count: u32 },
+    // At least `count` exercises completed without opening a hint
+    CompletedWithoutHints { count: u32 },
+    // A streak of at least `days` days, now or in the past
+    Streak { days: u32 },
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AchievementDefinition {
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub icon: String,
+    #[serde(default)]
+    pub points: u32,
+    pub rule: AchievementRule,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AchievementCatalog {
+    achievements: Vec<AchievementDefinition>,
+}
+
+impl AchievementCatalog {
+    // The definitions in `path`, or the built-in ones
+    pub fn load(path: Option<&Path>) -> anyhow::Result<Self> {
+        let catalog: Self = match path {
+            Some(path) => {
+                let content = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+                serde_json::from_str(&content)
+                    .map_err(|e| anyhow::anyhow!("Invalid achievements file {}: {}", path.display(), e))?
+            }
+            None => serde_json::from_str(DEFAULT_ACHIEVEMENTS)?,
+        };
+
+        let mut seen = std::collections::HashSet::new();
+        for definition in &catalog.achievements {
+            if !seen.insert(definition.id.as_str()) {
+                anyhow::bail!("Achievement {} is defined more than once", definition.id);
+            }
+        }
+        Ok(catalog)
+    }
+
+    pub fn definitions(&self) -> &[AchievementDefinition] {
+        &self.achievements
+    }
+
+    // Record every achievement whose rule is now met and return the new ones
+    pub fn evaluate(&self, progress: &mut ProgressData) -> Vec<Achievement> {
+        let stats = AchievementStats::new(progress);
+        let earned_at = Utc::now().to_rfc3339();
+
+        let unlocked: Vec<Achievement> = self
+            .achievements
+            .iter()
+            .filter(|definition| !progress.achievements.iter().any(|earned| earned.id == definition.id))
+            .filter(|definition| stats.satisfies(&definition.rule))
+            .map(|definition| Achievement {
+                id: definition.id.clone(),
+                title: definition.title.clone(),
+                description: definition.description.clone(),
+                icon: definition.icon.clone(),
+                points: definition.points,
+                earned_at: earned_at.clone(),
+            })
+            .collect();
+
+        progress.achievements.extend(unlocked.iter().cloned());
+        unlocked
+    }
+}
+
+// What the rules are checked against, worked out once per evaluation
+struct AchievementStats {
+    exercises_completed: u32,
+    completed_without_hints: u32,
+    completed_chapters: Vec<u32>,
+    longest_streak: u32,
+}
+
+impl AchievementStats {
+    fn new(progress: &ProgressData) -> Self {
+        let completed = progress
+            .exercise_history
+            .iter()
+            .filter(|entry| entry.completed_at.is_some());
+
+        let mut exercises_completed = 0;
+        let mut completed_without_hints = 0;
+        for entry in completed {
+            exercises_completed += 1;
+            if entry.hints_used.as_ref().is_none_or(|hints| hints.is_empty()) {
+                completed_without_hints += 1;
+            }
+        }
+
+        // `chapter_stats` has already marked the chapters that are done
+        let completed_chapters = progress
+            .chapters
+            .as_object()
+            .into_iter()
+            .flat_map(|chapters| chapters.values())
+            .filter(|chapter| chapter.get("completed_at").is_some_and(|completed_at| !completed_at.is_null()))
+            .filter_map(|chapter| chapter.get("chapter_number").and_then(|number| number.as_u64()))
+            .map(|number| number as u32)
+            .collect();
+
+        Self {
+            exercises_completed,
+            completed_without_hints,
+            completed_chapters,
+            longest_streak: progress.longest_streak.max(progress.current_streak),
+        }
+    }
+
+    fn satisfies(&self, rule: &AchievementRule) -> bool {
+        match rule {
+            AchievementRule::ExercisesCompleted { count } => self.exercises_completed >= *count,
+            AchievementRule::ChapterCompleted { chapter } => self.completed_chapters.contains(chapter),
+            AchievementRule::ChaptersCompleted { count } => self.completed_chapters.len() as u32 >= *count,
+            AchievementRule::CompletedWithoutHints { count } => self.completed_without_hints >= *count,
+            AchievementRule::Streak { days } => self.longest_streak >= *days,
+        }
+    }
+}
@@ -0,0 +1,368 @@
+// Active time tracking
+//
+// The server measures how long a learner works instead of trusting the minutes
+// the browser reports. Exercise views, edits, runs, hints and completions are
+// interactions; the time between two interactions counts as active when the
+// gap is at most the idle threshold (`--idle-threshold-secs`), and is a break
+// otherwise. WebSocket heartbeats fill in the time after an interaction while
+// the learner is still within the threshold, so reading an exercise counts but
+// a tab left open overnight doesn't.
+//
+// Active time goes to the exercise last viewed and to the current session. A
+// session is a stretch of activity without a break; the next interaction after
+// a break starts a new one. Credit is collected in memory and written to the
+// profile's progress about once a minute, when a session ends and before an
+// exercise is marked complete. `time_taken_minutes`, `total_time_minutes`, the
+// chapter statistics and `session_stats.time_spent` are derived from it.
+
+use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::{
+    event_log::{self, LearnerEventKind},
+    profiles::ProfileRegistry,
+    ExerciseHistoryEntry, ProgressData,
+};
+
+pub const DEFAULT_IDLE_THRESHOLD_SECS: u64 = 300;
+// Write collected credit once this much has built up
+const FLUSH_AFTER_SECS: u64 = 60;
+
+// A stretch of activity without a break longer than the idle threshold
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ActivitySession {
+    pub id: String,
+    pub started_at: String,
+    pub last_active_at: String,
+    #[serde(default)]
+    pub active_seconds: u64,
+    // Exercises worked on during the session
+    #[serde(default)]
+    pub exercises: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Activity {
+    // The learner opened an exercise; later time goes to it
+    View(String),
+    // An edit, run, test, check, hint or completion
+    Interaction,
+    // The page is still open
+    Heartbeat,
+}
+
+impl Activity {
+    fn is_interaction(&self) -> bool {
+        !matches!(self, Activity::Heartbeat)
+    }
+}
+
+// Active time not yet written to the progress
+#[derive(Debug)]
+struct Credit {
+    session: ActivitySession,
+    exercises: HashMap<String, u64>,
+}
+
+impl Credit {
+    fn new(now: DateTime<Utc>) -> Self {
+        Self {
+            session: ActivitySession {
+                id: format!("session_{}", Uuid::new_v4().simple()),
+                started_at: now.to_rfc3339(),
+                last_active_at: now.to_rfc3339(),
+                active_seconds: 0,
+                exercises: Vec::new(),
+            },
+            exercises: HashMap::new(),
+        }
+    }
+
+    // The same session with nothing credited yet
+    fn continuation(&self) -> Self {
+        Self {
+            session: ActivitySession {
+                active_seconds: 0,
+                exercises: Vec::new(),
+                ..self.session.clone()
+            },
+            exercises: HashMap::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.session.active_seconds == 0 && self.session.exercises.is_empty()
+    }
+}
+
+#[derive(Debug)]
+struct ProfileActivity {
+    exercise: Option<String>,
+    last_interaction: DateTime<Utc>,
+    // Time up to here has been credited or written off as a break
+    credited_until: DateTime<Utc>,
+    pending: Credit,
+}
+
+#[derive(Clone)]
+pub struct ActivityTracker {
+    idle_threshold: Duration,
+    profiles: Arc<Mutex<HashMap<String, ProfileActivity>>>,
+}
+
+impl ActivityTracker {
+    pub fn new(idle_threshold: Duration) -> Self {
+        Self {
+            idle_threshold,
+            profiles: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn record(&self, registry: &ProfileRegistry, profile: &str, activity: Activity) {
+        if let Some(credit) = self.track(profile, activity, Utc::now()) {
+            apply_credit(registry, profile, credit).await;
+        }
+    }
+
+    // Write everything collected so far, e.g. before the exercise is completed.
+    // Returns the current session id.
+    pub async fn flush(&self, registry: &ProfileRegistry, profile: &str) -> Option<String> {
+        let (session_id, credit) = {
+            let mut profiles = self.profiles.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let state = profiles.get_mut(profile)?;
+            let continuation = state.pending.continuation();
+            let credit = std::mem::replace(&mut state.pending, continuation);
+            (credit.session.id.clone(), credit)
+        };
+        if !credit.is_empty() {
+            apply_credit(registry, profile, credit).await;
+        }
+        Some(session_id)
+    }
+
+    // The profile's current session, if it has one
+    pub fn session_id(&self, profile: &str) -> Option<String> {
+        let profiles = self.profiles.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        profiles.get(profile).map(|state| state.pending.session.id.clone())
+    }
+
+    // Update the profile's state; returns credit that is due to be written
+    fn track(&self, profile: &str, activity: Activity, now: DateTime<Utc>) -> Option<Credit> {
+        let mut profiles = self.profiles.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let idle_threshold = chrono::Duration::from_std(self.idle_threshold).unwrap_or(chrono::Duration::MAX);
+
+        let Some(state) = profiles.get_mut(profile) else {
+            // Heartbeats alone don't start a session
+            if activity.is_interaction() {
+                let mut pending = Credit::new(now);
+                let exercise = match activity {
+                    Activity::View(exercise_id) => {
+                        pending.session.exercises.push(exercise_id.clone());
+                        Some(exercise_id)
+                    }
+                    _ => None,
+                };
+                profiles.insert(
+                    profile.to_string(),
+                    ProfileActivity {
+                        exercise,
+                        last_interaction: now,
+                        credited_until: now,
+                        pending,
+                    },
+                );
+            }
+            return None;
+        };
+
+        let mut due = None;
+        if now - state.last_interaction > idle_threshold {
+            if !activity.is_interaction() {
+                return None;
+            }
+            // Back from a break: close the session and start a new one
+            let pending = std::mem::replace(&mut state.pending, Credit::new(now));
+            due = Some(pending).filter(|credit| !credit.is_empty());
+        } else {
+            let seconds = (now - state.credited_until).num_seconds().max(0) as u64;
+            if seconds > 0 {
+                let pending = &mut state.pending;
+                pending.session.active_seconds += seconds;
+                pending.session.last_active_at = now.to_rfc3339();
+                if let Some(exercise_id) = &state.exercise {
+                    *pending.exercises.entry(exercise_id.clone()).or_default() += seconds;
+                    if !pending.session.exercises.contains(exercise_id) {
+                        pending.session.exercises.push(exercise_id.clone());
+                    }
+                }
+            }
+        }
+        state.credited_until = now;
+
+        if activity.is_interaction() {
+            state.last_interaction = now;
+        }
+        if let Activity::View(exercise_id) = activity {
+            if !state.pending.session.exercises.contains(&exercise_id) {
+                state.pending.session.exercises.push(exercise_id.clone());
+            }
+            state.exercise = Some(exercise_id);
+        }
+
+        if due.is_none() && state.pending.session.active_seconds >= FLUSH_AFTER_SECS {
+            let continuation = state.pending.continuation();
+            due = Some(std::mem::replace(&mut state.pending, continuation));
+        }
+        due
+    }
+}
+
+async fn apply_credit(registry: &ProfileRegistry, profile: &str, credit: Credit) {
+    let session = credit.session.clone();
+    let exercises: BTreeMap<String, u64> = credit.exercises.into_iter().collect();
+    let result = match registry.progress(profile).await {
+        Ok(progress) => {
+            let (session, exercises) = (session.clone(), exercises.clone());
+            progress.update(move |progress| add_active_time(progress, session, exercises)).await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => {
+            let session_id = Some(session.id.clone());
+            event_log::append_event(registry, profile, session_id, LearnerEventKind::ActiveTime { session, exercises }).await;
+        }
+        Err(e) => warn!("Failed to record active time for profile {}: {}", profile, e),
+    }
+}
+
+// Add time measured in `credited` to its session and to the exercises it was spent on
+pub fn add_active_time(
+    progress: &mut ProgressData,
+    credited: ActivitySession,
+    exercises: impl IntoIterator<Item = (String, u64)>,
+) -> bool {
+    for (exercise_id, seconds) in exercises {
+        if let Some(entry) = progress.exercise_history.iter_mut().find(|entry| entry.exercise_id == exercise_id) {
+            *entry.active_seconds.get_or_insert(0) += seconds;
+        }
+    }
+
+    let sessions = &mut progress.activity_sessions;
+    match sessions.iter_mut().find(|session| session.id == credited.id) {
+        Some(session) => {
+            session.active_seconds += credited.active_seconds;
+            session.last_active_at = credited.last_active_at;
+            for exercise_id in credited.exercises {
+                if !session.exercises.contains(&exercise_id) {
+                    session.exercises.push(exercise_id);
+                }
+            }
+        }
+        None => sessions.push(credited),
+    }
+    true
+}
+
+fn seconds_to_minutes(seconds: u64) -> u32 {
+    ((seconds + 30) / 60) as u32
+}
+
+// Minutes spent on an exercise: measured active time, or what older clients reported
+pub fn entry_minutes(entry: &ExerciseHistoryEntry) -> u32 {
+    match entry.active_seconds {
+        Some(seconds) if seconds > 0 => seconds_to_minutes(seconds),
+        _ => entry.time_taken_minutes.unwrap_or(0),
+    }
+}
+
+// Recompute the time totals from the history and sessions; returns whether they changed
+pub fn update_time_totals(progress: &mut ProgressData) -> bool {
+    let total_time_minutes = progress.exercise_history.iter().map(entry_minutes).sum();
+    let session_time = progress
+        .activity_sessions
+        .last()
+        .map(|session| seconds_to_minutes(session.active_seconds))
+        .unwrap_or(progress.session_stats.time_spent);
+
+    let changed = total_time_minutes != progress.total_time_minutes || session_time != progress.session_stats.time_spent;
+    progress.total_time_minutes = total_time_minutes;
+    progress.session_stats.time_spent = session_time;
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IDLE_THRESHOLD_SECS: i64 = 300;
+
+    fn tracker_with_view() -> (ActivityTracker, DateTime<Utc>, String) {
+        let tracker = ActivityTracker::new(Duration::from_secs(IDLE_THRESHOLD_SECS as u64));
+        let start = DateTime::parse_from_rfc3339("2026-10-18T09:00:00Z").unwrap().with_timezone(&Utc);
+        assert!(tracker.track("learner", Activity::View("ex01".to_string()), start).is_none());
+        let session_id = tracker.session_id("learner").expect("the view starts a session");
+        (tracker, start, session_id)
+    }
+
+    fn after(start: DateTime<Utc>, seconds: i64) -> DateTime<Utc> {
+        start + chrono::Duration::seconds(seconds)
+    }
+
+    #[test]
+    fn a_gap_below_the_threshold_is_active_time() {
+        let (tracker, start, session_id) = tracker_with_view();
+        let credit = tracker
+            .track("learner", Activity::Interaction, after(start, IDLE_THRESHOLD_SECS - 1))
+            .expect("a minute of credit is written");
+        assert_eq!(credit.session.id, session_id);
+        assert_eq!(credit.session.active_seconds, 299);
+        assert_eq!(credit.exercises.get("ex01"), Some(&299));
+        assert_eq!(tracker.session_id("learner"), Some(session_id));
+    }
+
+    #[test]
+    fn a_gap_of_exactly_the_threshold_is_active_time() {
+        let (tracker, start, session_id) = tracker_with_view();
+        let credit = tracker
+            .track("learner", Activity::Interaction, after(start, IDLE_THRESHOLD_SECS))
+            .expect("a minute of credit is written");
+        assert_eq!(credit.session.id, session_id);
+        assert_eq!(credit.session.active_seconds, 300);
+        assert_eq!(tracker.session_id("learner"), Some(session_id));
+    }
+
+    #[test]
+    fn a_gap_above_the_threshold_starts_a_new_session() {
+        let (tracker, start, session_id) = tracker_with_view();
+        assert!(tracker.track("learner", Activity::Interaction, after(start, 30)).is_none());
+
+        // Heartbeats after the break neither credit time nor start a session
+        assert!(tracker.track("learner", Activity::Heartbeat, after(start, 30 + IDLE_THRESHOLD_SECS + 1)).is_none());
+        assert_eq!(tracker.session_id("learner"), Some(session_id.clone()));
+
+        let credit = tracker
+            .track("learner", Activity::Interaction, after(start, 30 + IDLE_THRESHOLD_SECS + 2))
+            .expect("the closed session is written");
+        assert_eq!(credit.session.id, session_id);
+        assert_eq!(credit.session.active_seconds, 30);
+        assert_eq!(credit.exercises.get("ex01"), Some(&30));
+
+        let new_session = tracker.session_id("learner").unwrap();
+        assert_ne!(new_session, session_id);
+        // Later time goes to the new session and still to the exercise last viewed
+        let credit = tracker
+            .track("learner", Activity::Interaction, after(start, 30 + IDLE_THRESHOLD_SECS + 2 + 60))
+            .expect("a minute of credit is written");
+        assert_eq!(credit.session.id, new_session);
+        assert_eq!(credit.exercises.get("ex01"), Some(&60));
+    }
+}
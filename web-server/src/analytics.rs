@@ -0,0 +1,330 @@
+// Learning analytics
+//
+// `GET /api/analytics` derives the metrics the exercise framework's
+// `LearningAnalytics` describes from the profile's progress and the `concepts`
+// of the exercises on disk. Nothing is stored; every request computes them
+// afresh, with days and hours taken in the streak time zone:
+//
+//   - learning velocity: exercises completed per week over the last four weeks
+//     (or since the first activity, if that is more recent, but at least a week)
+//   - predicted completion time: days until every exercise is done at that pace
+//   - peak learning hours: the hours of the day with the most views, test runs
+//     and completions
+//   - concept strengths and weaknesses: concepts whose exercises were finished
+//     without much struggle, and concepts with many failed test runs and hints
+//     per exercise
+//   - error patterns: how often each rustc and clippy code came up, see
+//     `error_codes`
+//   - improvement trends: attempts, hints and minutes per exercise for the last
+//     few completions compared with the ones before (negative is better)
+//   - a daily activity calendar for a heatmap, with zero days included
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration, NaiveDate, Timelike};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use tracing::error;
+
+use crate::{
+    activity::entry_minutes, error_codes, profiles::ActiveProfile, scan_exercises, streaks::StreakConfig, AppState,
+    ExerciseHistoryEntry, ProgressData,
+};
+
+const DEFAULT_CALENDAR_DAYS: u32 = 365;
+const MAX_CALENDAR_DAYS: u32 = 3 * 366;
+const VELOCITY_WINDOW_DAYS: i64 = 28;
+const PEAK_HOURS: usize = 3;
+const LISTED_CONCEPTS: usize = 5;
+// Failed test runs plus hints per exercise
+const STRENGTH_MAX_STRUGGLE: f64 = 0.5;
+const WEAKNESS_MIN_STRUGGLE: f64 = 1.5;
+// Completions compared against the earlier ones for the trends
+const RECENT_COMPLETIONS: usize = 5;
+
+#[derive(Debug, Serialize)]
+pub struct LearningAnalytics {
+    // Exercises per week
+    pub learning_velocity: f64,
+    // Minutes
+    pub average_session_time: f64,
+    // Hours of the day (0-23), busiest first
+    pub peak_learning_hours: Vec<u32>,
+    // Activity per hour of the day, index 0 is midnight
+    pub activity_by_hour: Vec<u32>,
+    pub concept_strengths: Vec<String>,
+    pub concept_weaknesses: Vec<String>,
+    pub concepts: Vec<ConceptStats>,
+    pub error_patterns: BTreeMap<String, u32>,
+    pub improvement_trends: BTreeMap<String, f64>,
+    // Days until every exercise is completed; `None` without recent completions
+    pub predicted_completion_time: Option<u32>,
+    pub daily_activity: Vec<DailyActivity>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ConceptStats {
+    pub concept: String,
+    // Exercises with the concept the learner has worked on
+    pub exercises: u32,
+    pub completed: u32,
+    pub failed_test_runs: u32,
+    pub hints_used: u32,
+    // (failed test runs + hints) per exercise
+    pub struggle: f64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyActivity {
+    pub date: NaiveDate,
+    pub active_minutes: u32,
+    pub exercises_completed: u32,
+    pub test_runs: u32,
+    // Heatmap intensity from 0 (nothing) to 4 (the busiest days)
+    pub level: u8,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsParams {
+    // Days of the calendar, ending today
+    days: Option<u32>,
+}
+
+pub fn compute_analytics(
+    progress: &ProgressData,
+    concepts: &HashMap<String, Vec<String>>,
+    config: &StreakConfig,
+    calendar_days: u32,
+) -> LearningAnalytics {
+    let today = config.today();
+    let learning_velocity = learning_velocity(progress, config, today);
+    let remaining = progress.total_exercises.saturating_sub(progress.exercises_completed);
+    let predicted_completion_time = match remaining {
+        0 => Some(0),
+        _ if learning_velocity > 0.0 => Some((remaining as f64 / learning_velocity * 7.0).ceil() as u32),
+        _ => None,
+    };
+
+    let activity_by_hour = activity_by_hour(progress, config);
+    let mut peak_learning_hours: Vec<u32> = (0..24).filter(|hour| activity_by_hour[*hour as usize] > 0).collect();
+    peak_learning_hours.sort_by_key(|hour| std::cmp::Reverse(activity_by_hour[*hour as usize]));
+    peak_learning_hours.truncate(PEAK_HOURS);
+
+    let sessions: Vec<u64> = progress
+        .activity_sessions
+        .iter()
+        .map(|session| session.active_seconds)
+        .filter(|seconds| *seconds > 0)
+        .collect();
+    let average_session_time = match sessions.len() {
+        0 => 0.0,
+        count => sessions.iter().sum::<u64>() as f64 / 60.0 / count as f64,
+    };
+
+    let concept_stats = concept_stats(&progress.exercise_history, concepts);
+    let mut strengths: Vec<&ConceptStats> = concept_stats
+        .iter()
+        .filter(|stats| stats.completed > 0 && stats.completed == stats.exercises && stats.struggle <= STRENGTH_MAX_STRUGGLE)
+        .collect();
+    strengths.sort_by(|a, b| a.struggle.total_cmp(&b.struggle).then(b.completed.cmp(&a.completed)));
+    let mut weaknesses: Vec<&ConceptStats> = concept_stats
+        .iter()
+        .filter(|stats| stats.struggle >= WEAKNESS_MIN_STRUGGLE)
+        .collect();
+    weaknesses.sort_by(|a, b| b.struggle.total_cmp(&a.struggle));
+
+    LearningAnalytics {
+        learning_velocity,
+        average_session_time,
+        peak_learning_hours,
+        activity_by_hour,
+        concept_strengths: strengths.iter().take(LISTED_CONCEPTS).map(|stats| stats.concept.clone()).collect(),
+        concept_weaknesses: weaknesses.iter().take(LISTED_CONCEPTS).map(|stats| stats.concept.clone()).collect(),
+        error_patterns: error_codes::code_totals(&progress.exercise_history),
+        improvement_trends: improvement_trends(&progress.exercise_history),
+        predicted_completion_time,
+        daily_activity: daily_activity(progress, config, today, calendar_days),
+        concepts: concept_stats,
+    }
+}
+
+fn learning_velocity(progress: &ProgressData, config: &StreakConfig, today: NaiveDate) -> f64 {
+    let first_active = progress
+        .exercise_history
+        .iter()
+        .flat_map(|entry| [&entry.viewed_at, &entry.completed_at])
+        .flatten()
+        .filter_map(|timestamp| config.local_date(timestamp))
+        .min();
+    let Some(first_active) = first_active else {
+        return 0.0;
+    };
+
+    let window_days = (today - first_active).num_days().clamp(7, VELOCITY_WINDOW_DAYS);
+    let window_start = today - Duration::days(window_days - 1);
+    let completed = progress
+        .exercise_history
+        .iter()
+        .filter_map(|entry| entry.completed_at.as_deref())
+        .filter_map(|completed_at| config.local_date(completed_at))
+        .filter(|date| *date >= window_start)
+        .count();
+    completed as f64 / (window_days as f64 / 7.0)
+}
+
+fn activity_by_hour(progress: &ProgressData, config: &StreakConfig) -> Vec<u32> {
+    let mut hours = vec![0; 24];
+    for entry in &progress.exercise_history {
+        let timestamps = [entry.viewed_at.as_deref(), entry.completed_at.as_deref()]
+            .into_iter()
+            .flatten()
+            .chain(entry.test_runs.iter().map(|run| run.ran_at.as_str()));
+        for timestamp in timestamps {
+            if let Ok(time) = DateTime::parse_from_rfc3339(timestamp) {
+                hours[time.with_timezone(&config.timezone).hour() as usize] += 1;
+            }
+        }
+    }
+    hours
+}
+
+fn concept_stats(history: &[ExerciseHistoryEntry], concepts: &HashMap<String, Vec<String>>) -> Vec<ConceptStats> {
+    let mut by_concept: BTreeMap<&str, ConceptStats> = BTreeMap::new();
+    for entry in history {
+        let Some(entry_concepts) = concepts.get(&entry.exercise_id) else {
+            continue;
+        };
+        // Completions from before test runs were recorded only have the count
+        let failed_runs = entry.failed_test_runs.max(entry.test_failures.unwrap_or(0));
+        let hints = entry.hints_used.as_ref().map_or(0, |hints| hints.len() as u32);
+
+        for concept in entry_concepts {
+            let stats = by_concept.entry(concept).or_insert_with(|| ConceptStats {
+                concept: concept.clone(),
+                ..ConceptStats::default()
+            });
+            stats.exercises += 1;
+            stats.completed += entry.completed_at.is_some() as u32;
+            stats.failed_test_runs += failed_runs;
+            stats.hints_used += hints;
+        }
+    }
+
+    by_concept
+        .into_values()
+        .map(|mut stats| {
+            stats.struggle = (stats.failed_test_runs + stats.hints_used) as f64 / stats.exercises as f64;
+            stats
+        })
+        .collect()
+}
+
+type EntryMetric = fn(&ExerciseHistoryEntry) -> f64;
+
+fn improvement_trends(history: &[ExerciseHistoryEntry]) -> BTreeMap<String, f64> {
+    let mut completed: Vec<&ExerciseHistoryEntry> = history.iter().filter(|entry| entry.completed_at.is_some()).collect();
+    let mut trends = BTreeMap::new();
+    if completed.len() <= RECENT_COMPLETIONS {
+        return trends;
+    }
+    completed.sort_by_key(|entry| entry.completed_at.as_deref().and_then(|at| DateTime::parse_from_rfc3339(at).ok()));
+    let (earlier, recent) = completed.split_at(completed.len() - RECENT_COMPLETIONS);
+
+    let metrics: [(&str, EntryMetric); 3] = [
+        ("attempts_per_exercise", |entry| entry.attempts.unwrap_or(entry.test_run_count) as f64),
+        ("hints_per_exercise", |entry| entry.hints_used.as_ref().map_or(0, |hints| hints.len()) as f64),
+        ("minutes_per_exercise", |entry| entry_minutes(entry) as f64),
+    ];
+    for (name, metric) in metrics {
+        let average = |entries: &[&ExerciseHistoryEntry]| entries.iter().map(|entry| metric(entry)).sum::<f64>() / entries.len() as f64;
+        trends.insert(name.to_string(), average(recent) - average(earlier));
+    }
+    trends
+}
+
+fn daily_activity(progress: &ProgressData, config: &StreakConfig, today: NaiveDate, days: u32) -> Vec<DailyActivity> {
+    let first = today - Duration::days(days.max(1) as i64 - 1);
+    let mut calendar: Vec<DailyActivity> = first
+        .iter_days()
+        .take_while(|date| *date <= today)
+        .map(|date| DailyActivity {
+            date,
+            active_minutes: 0,
+            exercises_completed: 0,
+            test_runs: 0,
+            level: 0,
+        })
+        .collect();
+    let mut day = |timestamp: &str| {
+        config
+            .local_date(timestamp)
+            .filter(|date| *date >= first && *date <= today)
+            .map(|date| (date - first).num_days() as usize)
+    };
+
+    let mut active_seconds = vec![0u64; calendar.len()];
+    for session in &progress.activity_sessions {
+        if let Some(index) = day(&session.started_at) {
+            active_seconds[index] += session.active_seconds;
+        }
+    }
+    for entry in &progress.exercise_history {
+        if let Some(index) = entry.completed_at.as_deref().and_then(&mut day) {
+            calendar[index].exercises_completed += 1;
+        }
+        for run in &entry.test_runs {
+            if let Some(index) = day(&run.ran_at) {
+                calendar[index].test_runs += 1;
+            }
+        }
+    }
+    for (activity, seconds) in calendar.iter_mut().zip(active_seconds) {
+        activity.active_minutes = ((seconds + 30) / 60) as u32;
+    }
+
+    // Levels are quarters of the busiest day, so the heatmap scales with the learner
+    let score = |activity: &DailyActivity| activity.active_minutes + 10 * activity.exercises_completed + activity.test_runs;
+    let busiest = calendar.iter().map(score).max().unwrap_or(0);
+    for activity in &mut calendar {
+        let score = score(activity);
+        activity.level = match score {
+            0 => 0,
+            _ => (score * 4).div_ceil(busiest).clamp(1, 4) as u8,
+        };
+    }
+    calendar
+}
+
+// API handler
+pub async fn get_analytics(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Query(params): Query<AnalyticsParams>,
+) -> Result<Json<LearningAnalytics>, StatusCode> {
+    let progress = match state.profiles.progress(&profile).await {
+        Ok(progress) => progress.snapshot().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        error!("Error loading progress for analytics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let exercises = scan_exercises(&state.exercises_path).await.map_err(|e| {
+        error!("Error loading exercises for analytics: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // History entries are keyed by metadata id; older ones by path
+    let mut concepts = HashMap::new();
+    for exercise in exercises {
+        concepts.insert(exercise.path, exercise.metadata.concepts.clone());
+        concepts.insert(exercise.metadata.id, exercise.metadata.concepts);
+    }
+
+    let days = params.days.unwrap_or(DEFAULT_CALENDAR_DAYS).clamp(1, MAX_CALENDAR_DAYS);
+    let config = state.profiles.options().streaks;
+    Ok(Json(compute_analytics(&progress, &concepts, &config, days)))
+}
@@ -0,0 +1,852 @@
+// Progress export and import
+//
+// An export is a zip archive that describes itself:
+//
+//   manifest.json                      format, schema version, profile, contents
+//   progress.json                      the profile's `ProgressData`
+//   sources/<chapter>/<exercise>/...   editable files of exercises the learner worked on
+//   snapshots/user_progress.json.bak.N rotating progress backups (JSON backend)
+//
+// Importing validates the archive, can preview what would change, and merges
+// it into the current profile: exercises missing locally are added, existing
+// ones keep the earliest view and completion and the union of hints used.
+// Source files overwrite the local copies; snapshots are carried along for
+// manual recovery and are not restored.
+
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+use tracing::{error, info};
+use walkdir::WalkDir;
+use zip::{write::FileOptions, CompressionMethod, ZipArchive, ZipWriter};
+
+use crate::{
+    collab, is_editable_file, load_exercise_title,
+    profiles::{self, ActiveProfile, ProfileRegistry, DEFAULT_PROFILE},
+    progress_schema,
+    progress_store::{backup_path, BACKUP_COUNT},
+    protocol::ServerMessage,
+    scan_exercises,
+    test_runs::RECENT_TEST_RUNS,
+    validate_exercise_file_path, AppState, ExerciseHistoryEntry, ProgressData,
+};
+
+pub const ARCHIVE_FORMAT: &str = "rust-tour-progress";
+pub const ARCHIVE_SCHEMA_VERSION: u32 = 1;
+
+// Archives with source files are larger than axum's default body limit
+pub const ARCHIVE_UPLOAD_LIMIT: usize = 50 * 1024 * 1024;
+
+// Guards against zip bombs; real archives are far below these
+const MAX_ARCHIVE_ENTRIES: usize = 10_000;
+const MAX_ENTRY_SIZE: u64 = 10 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchiveManifest {
+    pub format: String,
+    pub schema_version: u32,
+    pub exported_at: String,
+    pub server_version: String,
+    pub profile: String,
+    // "chapter/exercise/file" paths under `sources/`
+    #[serde(default)]
+    pub sources: Vec<String>,
+    #[serde(default)]
+    pub snapshots: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ExportOptions {
+    pub sources: bool,
+    pub snapshots: bool,
+}
+
+pub struct ProgressArchive {
+    pub manifest: ArchiveManifest,
+    pub progress: ProgressData,
+    pub sources: Vec<SourceFile>,
+}
+
+pub struct SourceFile {
+    // "chapter/exercise"
+    pub exercise: String,
+    // Relative to the exercise, e.g. "src/main.rs"
+    pub file: String,
+    pub content: String,
+}
+
+// Exercise paths the learner has worked on: everything in their history, plus
+// every exercise the profile has its own copy of
+async fn touched_exercises(profiles: &ProfileRegistry, profile: &str, progress: &ProgressData) -> anyhow::Result<Vec<String>> {
+    let catalog = scan_exercises(profiles.exercises_path()).await?;
+    let mut paths: Vec<String> = progress
+        .exercise_history
+        .iter()
+        .filter_map(|entry| {
+            catalog
+                .iter()
+                .find(|exercise| exercise.metadata.id == entry.exercise_id || exercise.path == entry.exercise_id)
+                .map(|exercise| exercise.path.clone())
+        })
+        .collect();
+    paths.extend(profiles.edited_exercises(profile).await?);
+    paths.sort();
+    paths.dedup();
+    Ok(paths)
+}
+
+fn editable_files(exercise_dir: &Path) -> Vec<String> {
+    let mut files: Vec<String> = WalkDir::new(exercise_dir)
+        .into_iter()
+        .filter_entry(|entry| entry.file_name() != "target")
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| {
+            let relative = entry.path().strip_prefix(exercise_dir).ok()?;
+            let relative = relative.to_string_lossy().replace('\\', "/");
+            is_editable_file(&relative).then_some(relative)
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+pub async fn export_archive(profiles: &ProfileRegistry, profile: &str, options: ExportOptions) -> anyhow::Result<Vec<u8>> {
+    let progress = profiles.progress(profile).await?.snapshot().await?;
+
+    let mut sources = Vec::new();
+    if options.sources {
+        for exercise in touched_exercises(profiles, profile, &progress).await? {
+            let dir = profiles.exercise_dir(profile, &exercise);
+            for file in editable_files(&dir) {
+                let content = fs::read_to_string(dir.join(&file)).await?;
+                sources.push((format!("{}/{}", exercise, file), content));
+            }
+        }
+    }
+
+    let mut snapshots = Vec::new();
+    if options.snapshots {
+        let progress_path = profiles.progress_path(profile);
+        for index in 1..=BACKUP_COUNT {
+            let path = backup_path(&progress_path, index);
+            if let Ok(content) = fs::read(&path).await {
+                let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+                snapshots.push((name, content));
+            }
+        }
+    }
+
+    let manifest = ArchiveManifest {
+        format: ARCHIVE_FORMAT.to_string(),
+        schema_version: ARCHIVE_SCHEMA_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        profile: profile.to_string(),
+        sources: sources.iter().map(|(path, _)| path.clone()).collect(),
+        snapshots: snapshots.iter().map(|(name, _)| name.clone()).collect(),
+    };
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file("manifest.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&manifest)?)?;
+    zip.start_file("progress.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(&progress)?)?;
+    for (path, content) in &sources {
+        zip.start_file(format!("sources/{}", path), options)?;
+        zip.write_all(content.as_bytes())?;
+    }
+    for (name, content) in &snapshots {
+        zip.start_file(format!("snapshots/{}", name), options)?;
+        zip.write_all(content)?;
+    }
+
+    info!(
+        "Exported profile {} ({} exercises, {} source files, {} snapshots)",
+        profile,
+        progress.exercise_history.len(),
+        sources.len(),
+        snapshots.len()
+    );
+    Ok(zip.finish()?.into_inner())
+}
+
+fn read_entry(archive: &mut ZipArchive<Cursor<&[u8]>>, name: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    if entry.size() > MAX_ENTRY_SIZE {
+        anyhow::bail!("{} is too large ({} bytes)", name, entry.size());
+    }
+    let mut content = Vec::new();
+    entry.by_ref().take(MAX_ENTRY_SIZE + 1).read_to_end(&mut content)?;
+    // The size in the header can't be trusted
+    if content.len() as u64 > MAX_ENTRY_SIZE {
+        anyhow::bail!("{} is too large", name);
+    }
+    Ok(Some(content))
+}
+
+// Parse and validate an archive without touching any state
+pub fn read_archive(bytes: &[u8]) -> anyhow::Result<ProgressArchive> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes)).map_err(|e| anyhow::anyhow!("Not a zip archive: {}", e))?;
+    if archive.len() > MAX_ARCHIVE_ENTRIES {
+        anyhow::bail!("Archive has too many entries ({})", archive.len());
+    }
+
+    let manifest = read_entry(&mut archive, "manifest.json")?
+        .ok_or_else(|| anyhow::anyhow!("Archive has no manifest.json"))?;
+    let manifest: ArchiveManifest =
+        serde_json::from_slice(&manifest).map_err(|e| anyhow::anyhow!("Invalid manifest.json: {}", e))?;
+    if manifest.format != ARCHIVE_FORMAT {
+        anyhow::bail!("Not a Rust Tour progress archive (format {:?})", manifest.format);
+    }
+    if manifest.schema_version > ARCHIVE_SCHEMA_VERSION {
+        anyhow::bail!(
+            "Archive schema version {} was written by a newer Rust Tour ({}); this server reads up to version {}",
+            manifest.schema_version,
+            manifest.server_version,
+            ARCHIVE_SCHEMA_VERSION
+        );
+    }
+
+    let progress = read_entry(&mut archive, "progress.json")?
+        .ok_or_else(|| anyhow::anyhow!("Archive has no progress.json"))?;
+    let progress: serde_json::Value =
+        serde_json::from_slice(&progress).map_err(|e| anyhow::anyhow!("Invalid progress.json: {}", e))?;
+    let (progress, _) = progress_schema::upgrade(progress, "progress.json in the archive")?;
+    let progress: ProgressData =
+        serde_json::from_value(progress).map_err(|e| anyhow::anyhow!("Invalid progress.json: {}", e))?;
+
+    let mut sources = Vec::new();
+    for path in &manifest.sources {
+        let mut parts = path.splitn(3, '/');
+        let (Some(chapter), Some(exercise), Some(file)) = (parts.next(), parts.next(), parts.next()) else {
+            anyhow::bail!("Invalid source path in manifest: {}", path);
+        };
+        if chapter.is_empty() || exercise.is_empty() || chapter.contains("..") || exercise.contains("..") {
+            anyhow::bail!("Invalid source path in manifest: {}", path);
+        }
+        if validate_exercise_file_path(file).is_err() {
+            anyhow::bail!("Source file is not an editable exercise file: {}", path);
+        }
+
+        let content = read_entry(&mut archive, &format!("sources/{}", path))?
+            .ok_or_else(|| anyhow::anyhow!("Manifest lists sources/{} but the archive doesn't contain it", path))?;
+        let content = String::from_utf8(content).map_err(|_| anyhow::anyhow!("sources/{} is not UTF-8", path))?;
+        sources.push(SourceFile {
+            exercise: format!("{}/{}", chapter, exercise),
+            file: file.to_string(),
+            content,
+        });
+    }
+
+    Ok(ProgressArchive {
+        manifest,
+        progress,
+        sources,
+    })
+}
+
+// Earliest of two optional RFC 3339 timestamps
+fn earliest(a: &Option<String>, b: &Option<String>) -> Option<String> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if b < a { b.clone() } else { a.clone() }),
+        (a, b) => a.clone().or_else(|| b.clone()),
+    }
+}
+
+fn merge_entry(local: &mut ExerciseHistoryEntry, imported: &ExerciseHistoryEntry) {
+    local.viewed_at = earliest(&local.viewed_at, &imported.viewed_at);
+
+    if imported.completed_at.is_some() && earliest(&local.completed_at, &imported.completed_at) == imported.completed_at {
+        local.completed_at = imported.completed_at.clone();
+        local.time_taken_minutes = imported.time_taken_minutes;
+        local.session_id = imported.session_id.clone();
+        local.attempts = imported.attempts;
+        local.test_failures = imported.test_failures;
+        local.completed_by = imported.completed_by;
+    }
+
+    // The same time can't be told apart from separate sessions, so keep the larger measurement
+    local.active_seconds = local.active_seconds.max(imported.active_seconds);
+
+    // Runs on both machines, in the order they happened
+    for run in &imported.test_runs {
+        if !local.test_runs.iter().any(|local_run| local_run.ran_at == run.ran_at) {
+            local.test_runs.push(run.clone());
+        }
+    }
+    local.test_runs.sort_by(|a, b| a.ran_at.cmp(&b.ran_at));
+    // Runs that are no longer kept can't be matched up, so keep the larger count
+    let failed_runs = local.test_runs.iter().filter(|run| !run.succeeded()).count() as u32;
+    local.test_run_count = local
+        .test_run_count
+        .max(imported.test_run_count)
+        .max(local.test_runs.len() as u32);
+    local.failed_test_runs = local.failed_test_runs.max(imported.failed_test_runs).max(failed_runs);
+    let overflow = local.test_runs.len().saturating_sub(RECENT_TEST_RUNS);
+    local.test_runs.drain(..overflow);
+    for (code, stats) in &imported.error_codes {
+        match local.error_codes.get_mut(code) {
+            Some(local_stats) => local_stats.merge(stats),
+            None => {
+                local.error_codes.insert(code.clone(), stats.clone());
+            }
+        }
+    }
+
+    if let Some(imported_hints) = &imported.hints_used {
+        let hints = local.hints_used.get_or_insert_with(Vec::new);
+        for level in imported_hints {
+            if !hints.contains(level) {
+                hints.push(*level);
+            }
+        }
+    }
+
+    local.status = if local.completed_at.is_some() {
+        "completed".to_string()
+    } else if local.hints_used.as_ref().is_some_and(|hints| !hints.is_empty()) {
+        "in_progress".to_string()
+    } else {
+        local.status.clone()
+    };
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct MergeSummary {
+    pub exercises_added: Vec<String>,
+    pub exercises_updated: Vec<String>,
+}
+
+// Merge imported progress into `local` and recompute the derived counters
+pub fn merge_progress(local: &mut ProgressData, imported: &ProgressData) -> MergeSummary {
+    let mut summary = MergeSummary::default();
+
+    for entry in &imported.exercise_history {
+        match local
+            .exercise_history
+            .iter_mut()
+            .find(|local_entry| local_entry.exercise_id == entry.exercise_id)
+        {
+            Some(local_entry) => {
+                let before = local_entry.clone();
+                merge_entry(local_entry, entry);
+                if *local_entry != before {
+                    summary.exercises_updated.push(entry.exercise_id.clone());
+                }
+            }
+            None => {
+                local.exercise_history.push(entry.clone());
+                summary.exercises_added.push(entry.exercise_id.clone());
+            }
+        }
+    }
+
+    let completed = local
+        .exercise_history
+        .iter()
+        .filter(|entry| entry.completed_at.is_some())
+        .count() as u32;
+    local.exercises_completed = completed;
+    if local.total_exercises > 0 {
+        local.overall_progress = completed as f64 / local.total_exercises as f64;
+    }
+    local.total_time_minutes = local.total_time_minutes.max(imported.total_time_minutes);
+    local.session_stats.exercises_viewed = local.session_stats.exercises_viewed.max(imported.session_stats.exercises_viewed);
+    local.session_stats.exercises_completed = local.session_stats.exercises_completed.max(imported.session_stats.exercises_completed);
+    local.session_stats.hints_used = local.session_stats.hints_used.max(imported.session_stats.hints_used);
+    local.session_stats.time_spent = local.session_stats.time_spent.max(imported.session_stats.time_spent);
+    for session in &imported.activity_sessions {
+        if !local.activity_sessions.iter().any(|local_session| local_session.id == session.id) {
+            local.activity_sessions.push(session.clone());
+        }
+    }
+    local.activity_sessions.sort_by(|a, b| a.started_at.cmp(&b.started_at));
+    for achievement in &imported.achievements {
+        match local.achievements.iter_mut().find(|earned| earned.id == achievement.id) {
+            Some(earned) if !achievement.earned_at.is_empty() && achievement.earned_at < earned.earned_at => {
+                earned.earned_at = achievement.earned_at.clone();
+            }
+            Some(_) => {}
+            None => local.achievements.push(achievement.clone()),
+        }
+    }
+    // Both audit trails, in order
+    for reset in &imported.resets {
+        if !local.resets.contains(reset) {
+            local.resets.push(reset.clone());
+        }
+    }
+    local.resets.sort_by(|a, b| a.reset_at.cmp(&b.reset_at));
+
+    summary
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileImportStatus {
+    New,
+    Changed,
+    Unchanged,
+    // The exercise doesn't exist on this installation
+    UnknownExercise,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FilePreview {
+    pub exercise: String,
+    pub file: String,
+    pub status: FileImportStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportPreview {
+    pub manifest: ArchiveManifest,
+    pub profile: String,
+    #[serde(flatten)]
+    pub merge: MergeSummary,
+    pub exercises_completed_before: u32,
+    pub exercises_completed_after: u32,
+    pub files: Vec<FilePreview>,
+}
+
+async fn preview_files(profiles: &ProfileRegistry, profile: &str, sources: &[SourceFile]) -> anyhow::Result<Vec<FilePreview>> {
+    let known: HashSet<String> = scan_exercises(profiles.exercises_path())
+        .await?
+        .into_iter()
+        .map(|exercise| exercise.path)
+        .collect();
+
+    let mut previews = Vec::new();
+    for source in sources {
+        let status = if !known.contains(&source.exercise) {
+            FileImportStatus::UnknownExercise
+        } else {
+            let local = profiles.exercise_dir(profile, &source.exercise).join(&source.file);
+            match fs::read_to_string(&local).await {
+                Ok(content) if content == source.content => FileImportStatus::Unchanged,
+                Ok(_) => FileImportStatus::Changed,
+                Err(_) => FileImportStatus::New,
+            }
+        };
+        previews.push(FilePreview {
+            exercise: source.exercise.clone(),
+            file: source.file.clone(),
+            status,
+        });
+    }
+    Ok(previews)
+}
+
+pub async fn preview_import(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    archive: &ProgressArchive,
+    include_sources: bool,
+) -> anyhow::Result<ImportPreview> {
+    let current = profiles.progress(profile).await?.snapshot().await?;
+    let mut merged = current.clone();
+    let merge = merge_progress(&mut merged, &archive.progress);
+    let files = if include_sources {
+        preview_files(profiles, profile, &archive.sources).await?
+    } else {
+        Vec::new()
+    };
+
+    Ok(ImportPreview {
+        manifest: archive.manifest.clone(),
+        profile: profile.to_string(),
+        merge,
+        exercises_completed_before: current.exercises_completed,
+        exercises_completed_after: merged.exercises_completed,
+        files,
+    })
+}
+
+pub struct ImportOutcome {
+    pub preview: ImportPreview,
+    pub progress: ProgressData,
+    // (exercise, file) pairs written
+    pub written: Vec<(String, String)>,
+}
+
+pub async fn apply_import(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    archive: ProgressArchive,
+    include_sources: bool,
+) -> anyhow::Result<ImportOutcome> {
+    let preview = preview_import(profiles, profile, &archive, include_sources).await?;
+
+    let imported = archive.progress;
+    let progress = profiles
+        .progress(profile)
+        .await?
+        .update(move |progress| {
+            let summary = merge_progress(progress, &imported);
+            !summary.exercises_added.is_empty() || !summary.exercises_updated.is_empty()
+        })
+        .await?;
+
+    let mut written = Vec::new();
+    if include_sources {
+        let statuses: HashMap<(&str, &str), &FileImportStatus> = preview
+            .files
+            .iter()
+            .map(|file| ((file.exercise.as_str(), file.file.as_str()), &file.status))
+            .collect();
+        for source in &archive.sources {
+            match statuses.get(&(source.exercise.as_str(), source.file.as_str())) {
+                Some(FileImportStatus::New) | Some(FileImportStatus::Changed) => {}
+                _ => continue,
+            }
+            let dir = profiles.writable_exercise_dir(profile, &source.exercise).await?;
+            let path: PathBuf = dir.join(&source.file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            fs::write(&path, &source.content).await?;
+            written.push((source.exercise.clone(), source.file.clone()));
+        }
+    }
+
+    info!(
+        "Imported archive into profile {}: {} exercises added, {} updated, {} files written",
+        profile,
+        preview.merge.exercises_added.len(),
+        preview.merge.exercises_updated.len(),
+        written.len()
+    );
+    Ok(ImportOutcome {
+        preview,
+        progress,
+        written,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    #[serde(default = "default_true")]
+    sources: bool,
+    #[serde(default)]
+    snapshots: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportParams {
+    #[serde(default = "default_true")]
+    sources: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    #[serde(flatten)]
+    preview: ImportPreview,
+    files_written: usize,
+    progress: ProgressData,
+}
+
+// API handlers
+pub async fn export_progress(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Query(params): Query<ExportParams>,
+) -> Result<Response, StatusCode> {
+    let options = ExportOptions {
+        sources: params.sources,
+        snapshots: params.snapshots,
+    };
+    match export_archive(&state.profiles, &profile, options).await {
+        Ok(archive) => {
+            let filename = format!(
+                "rust-tour-{}-{}.zip",
+                profile,
+                Utc::now().format("%Y%m%d-%H%M%S")
+            );
+            Ok((
+                [
+                    (header::CONTENT_TYPE, "application/zip".to_string()),
+                    (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{}\"", filename)),
+                ],
+                archive,
+            )
+                .into_response())
+        }
+        Err(e) => {
+            error!("Error exporting progress for profile {}: {}", profile, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn preview_progress_import(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Query(params): Query<ImportParams>,
+    body: Bytes,
+) -> Result<Json<ImportPreview>, (StatusCode, String)> {
+    let archive = read_archive(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+    preview_import(&state.profiles, &profile, &archive, params.sources)
+        .await
+        .map(Json)
+        .map_err(|e| {
+            error!("Error previewing import for profile {}: {}", profile, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Failed to preview import".to_string())
+        })
+}
+
+pub async fn import_progress(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Query(params): Query<ImportParams>,
+    body: Bytes,
+) -> Result<Json<ImportResponse>, (StatusCode, String)> {
+    let archive = read_archive(&body).map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    // A shared editing session would write its text back over the imported file
+    if params.sources {
+        for source in &archive.sources {
+            let scoped_path = profiles::scoped_exercise_path(&profile, &source.exercise);
+            if collab::has_open_documents(&state, &scoped_path).await {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("{} is open in a collaborative editing session", source.exercise),
+                ));
+            }
+        }
+    }
+
+    let outcome = apply_import(&state.profiles, &profile, archive, params.sources)
+        .await
+        .map_err(|e| {
+            error!("Error importing progress for profile {}: {}", profile, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to import: {}", e))
+        })?;
+
+    let _ = state.broadcast_tx.send(ServerMessage::ProgressUpdated {
+        profile: profile.clone(),
+        exercise_id: None,
+        progress: Box::new(outcome.progress.clone()),
+    });
+    if profile == DEFAULT_PROFILE {
+        for (exercise, file) in &outcome.written {
+            let exercise_name = load_exercise_title(&state.profiles.exercise_dir(&profile, exercise))
+                .await
+                .unwrap_or_else(|_| exercise.clone());
+            let _ = state.broadcast_tx.send(ServerMessage::FileUpdated {
+                exercise: exercise_name,
+                file: file.clone(),
+            });
+        }
+    }
+
+    Ok(Json(ImportResponse {
+        files_written: outcome.written.len(),
+        preview: outcome.preview,
+        progress: outcome.progress,
+    }))
+}
+
+// `rust-tour export` / `rust-tour import`, run instead of the server
+pub async fn export_command(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    output: Option<PathBuf>,
+    options: ExportOptions,
+) -> anyhow::Result<()> {
+    let archive = export_archive(profiles, profile, options).await?;
+    let output = output.unwrap_or_else(|| {
+        PathBuf::from(format!("rust-tour-{}-{}.zip", profile, Utc::now().format("%Y%m%d-%H%M%S")))
+    });
+    fs::write(&output, &archive).await?;
+    println!("📦 Exported profile '{}' to {} ({} bytes)", profile, output.display(), archive.len());
+    Ok(())
+}
+
+pub async fn import_command(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    input: &Path,
+    include_sources: bool,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let bytes = fs::read(input).await?;
+    let archive = read_archive(&bytes)?;
+    println!(
+        "📦 Archive from profile '{}', exported {} by Rust Tour {}",
+        archive.manifest.profile, archive.manifest.exported_at, archive.manifest.server_version
+    );
+
+    let preview = if dry_run {
+        preview_import(profiles, profile, &archive, include_sources).await?
+    } else {
+        apply_import(profiles, profile, archive, include_sources).await?.preview
+    };
+
+    let changed_files = preview
+        .files
+        .iter()
+        .filter(|file| matches!(file.status, FileImportStatus::New | FileImportStatus::Changed))
+        .count();
+    let unknown_files = preview
+        .files
+        .iter()
+        .filter(|file| matches!(file.status, FileImportStatus::UnknownExercise))
+        .count();
+    println!("   Exercises added:     {}", preview.merge.exercises_added.len());
+    println!("   Exercises updated:   {}", preview.merge.exercises_updated.len());
+    println!(
+        "   Completed exercises: {} -> {}",
+        preview.exercises_completed_before, preview.exercises_completed_after
+    );
+    println!("   Source files:        {} to write, {} for unknown exercises", changed_files, unknown_files);
+    if dry_run {
+        println!("ℹ️  Dry run: nothing was changed");
+    } else {
+        println!("✅ Imported into profile '{}'", profile);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn progress(history: serde_json::Value) -> ProgressData {
+        serde_json::from_value(serde_json::json!({
+            "schema_version": progress_schema::PROGRESS_SCHEMA_VERSION,
+            "user_id": "learner",
+            "total_exercises": 10,
+            "exercise_history": history,
+        }))
+        .unwrap()
+    }
+
+    // An archive listing `sources` in its manifest, stored uncompressed
+    fn archive_with(sources: &[(&str, &[u8])]) -> Vec<u8> {
+        let manifest = ArchiveManifest {
+            format: ARCHIVE_FORMAT.to_string(),
+            schema_version: ARCHIVE_SCHEMA_VERSION,
+            exported_at: "2026-10-18T09:00:00Z".to_string(),
+            server_version: "test".to_string(),
+            profile: DEFAULT_PROFILE.to_string(),
+            sources: sources.iter().map(|(path, _)| path.to_string()).collect(),
+            snapshots: Vec::new(),
+        };
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        let options = FileOptions::default().compression_method(CompressionMethod::Stored);
+        zip.start_file("manifest.json", options).unwrap();
+        zip.write_all(&serde_json::to_vec(&manifest).unwrap()).unwrap();
+        zip.start_file("progress.json", options).unwrap();
+        zip.write_all(&serde_json::to_vec(&progress(serde_json::json!([]))).unwrap())
+            .unwrap();
+        for (path, content) in sources {
+            zip.start_file(format!("sources/{}", path), options).unwrap();
+            zip.write_all(content).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn reads_sources_of_editable_files() {
+        let archive = read_archive(&archive_with(&[("ch01_getting_started/ex01_hello_world/src/main.rs", b"fn main() {}")])).unwrap();
+        assert_eq!(archive.sources.len(), 1);
+        assert_eq!(archive.sources[0].exercise, "ch01_getting_started/ex01_hello_world");
+        assert_eq!(archive.sources[0].file, "src/main.rs");
+        assert_eq!(archive.sources[0].content, "fn main() {}");
+    }
+
+    #[test]
+    fn rejects_sources_outside_the_exercise() {
+        for path in [
+            "../ex01_hello_world/src/main.rs",
+            "ch01_getting_started/../src/main.rs",
+            "ch01_getting_started/ex01_hello_world/../../../etc/passwd",
+            "ch01_getting_started/ex01_hello_world/tests/unit_tests.rs",
+        ] {
+            let result = read_archive(&archive_with(&[(path, b"")]));
+            assert!(result.is_err(), "{} was accepted", path);
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_entries() {
+        let content = vec![b' '; MAX_ENTRY_SIZE as usize + 1];
+        let Err(e) = read_archive(&archive_with(&[("ch01_getting_started/ex01_hello_world/src/main.rs", &content)])) else {
+            panic!("an oversized entry was accepted");
+        };
+        assert!(e.to_string().contains("too large"), "{}", e);
+    }
+
+    #[test]
+    fn merge_keeps_the_first_completion_and_unions_hints() {
+        let mut local = progress(serde_json::json!([
+            {
+                "exercise_id": "ch01-ex01-hello",
+                "status": "completed",
+                "viewed_at": "2026-10-02T09:00:00Z",
+                "completed_at": "2026-10-03T10:00:00Z",
+                "time_taken_minutes": 30,
+                "hints_used": [1],
+            },
+            {"exercise_id": "ch01-ex02-cargo", "status": "viewed", "viewed_at": "2026-10-02T09:00:00Z"},
+        ]));
+        let imported = progress(serde_json::json!([
+            {
+                "exercise_id": "ch01-ex01-hello",
+                "status": "completed",
+                "viewed_at": "2026-10-01T09:00:00Z",
+                "completed_at": "2026-10-01T10:00:00Z",
+                "time_taken_minutes": 12,
+                "hints_used": [2, 1],
+            },
+            {
+                "exercise_id": "ch01-ex02-cargo",
+                "status": "completed",
+                "viewed_at": "2026-10-04T09:00:00Z",
+                "completed_at": "2026-10-05T10:00:00Z",
+            },
+            {"exercise_id": "ch01-ex03-compile", "status": "viewed", "viewed_at": "2026-10-05T09:00:00Z"},
+        ]));
+
+        let summary = merge_progress(&mut local, &imported);
+        assert_eq!(summary.exercises_added, vec!["ch01-ex03-compile"]);
+        assert_eq!(summary.exercises_updated, vec!["ch01-ex01-hello", "ch01-ex02-cargo"]);
+
+        let hello = &local.exercise_history[0];
+        assert_eq!(hello.viewed_at.as_deref(), Some("2026-10-01T09:00:00Z"));
+        assert_eq!(hello.completed_at.as_deref(), Some("2026-10-01T10:00:00Z"));
+        assert_eq!(hello.time_taken_minutes, Some(12));
+        assert_eq!(hello.hints_used, Some(vec![1, 2]));
+
+        // Completed only on the imported side
+        let cargo = &local.exercise_history[1];
+        assert_eq!(cargo.viewed_at.as_deref(), Some("2026-10-02T09:00:00Z"));
+        assert_eq!(cargo.completed_at.as_deref(), Some("2026-10-05T10:00:00Z"));
+        assert_eq!(cargo.status, "completed");
+
+        assert_eq!(local.exercises_completed, 2);
+        assert_eq!(local.overall_progress, 0.2);
+    }
+}
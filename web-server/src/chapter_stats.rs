@@ -0,0 +1,254 @@
+// Per-chapter statistics
+//
+// The chapter catalog in `ProgressData.chapters` comes from the exercises on
+// disk (`discover_chapters`); the statistics in it are derived from the
+// exercise history, like streaks, and recomputed whenever progress is loaded or
+// changed. An exercise belongs to the chapter in its id (`ch04-...` or
+// `ch04_.../...`). Once the catalog lists the chapter's exercise numbers,
+// history for exercises no longer on disk is left out.
+
+use chrono::{DateTime, FixedOffset};
+use std::collections::HashMap;
+
+use crate::{activity::entry_minutes, ChapterInfo, ExerciseHistoryEntry, ProgressData};
+
+// Chapter number of an exercise id, e.g. 4 for `ch04-ex01-ownership-basics`
+// or `ch04_understanding_ownership/ex01_ownership_basics`
+pub fn exercise_chapter(exercise_id: &str) -> Option<u32> {
+    let digits: String = exercise_id
+        .strip_prefix("ch")?
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.parse().ok()
+}
+
+// Exercise number of an exercise id or directory, e.g. 1 for
+// `ch04-ex01-ownership-basics`, `ch04_understanding_ownership/ex01_ownership_basics`
+// or `ex01_ownership_basics`
+pub fn exercise_number(exercise_id: &str) -> Option<u32> {
+    exercise_id.split(['/', '-']).find_map(|segment| {
+        let digits: String = segment
+            .strip_prefix("ex")?
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        digits.parse().ok()
+    })
+}
+
+fn parse_time(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_rfc3339(timestamp).ok()
+}
+
+// Keep whichever timestamp is earlier (`later = false`) or later
+fn pick_time(current: &mut Option<String>, candidate: &str, later: bool) {
+    let Some(candidate_time) = parse_time(candidate) else {
+        return;
+    };
+    let replace = match current.as_deref().and_then(parse_time) {
+        Some(current_time) if later => candidate_time > current_time,
+        Some(current_time) => candidate_time < current_time,
+        None => true,
+    };
+    if replace {
+        *current = Some(candidate.to_string());
+    }
+}
+
+// Fill in the statistics of every chapter from the history
+pub fn apply_chapter_stats(chapters: &mut HashMap<u32, ChapterInfo>, history: &[ExerciseHistoryEntry]) {
+    for chapter in chapters.values_mut() {
+        chapter.exercises_completed = 0;
+        chapter.time_spent_minutes = 0;
+        chapter.first_started = None;
+        chapter.completed_at = None;
+    }
+
+    let mut last_completed: HashMap<u32, Option<String>> = HashMap::new();
+    for entry in history {
+        let Some(chapter) = exercise_chapter(&entry.exercise_id).and_then(|number| chapters.get_mut(&number)) else {
+            continue;
+        };
+        if !chapter.exercises.is_empty()
+            && !exercise_number(&entry.exercise_id).is_some_and(|number| chapter.exercises.contains(&number))
+        {
+            continue;
+        }
+
+        for started in [&entry.viewed_at, &entry.completed_at].into_iter().flatten() {
+            pick_time(&mut chapter.first_started, started, false);
+        }
+        // Time on unfinished exercises counts too
+        chapter.time_spent_minutes += entry_minutes(entry);
+        if let Some(completed_at) = &entry.completed_at {
+            chapter.exercises_completed += 1;
+            pick_time(last_completed.entry(chapter.chapter_number).or_default(), completed_at, true);
+        }
+    }
+
+    for chapter in chapters.values_mut() {
+        // A fraction, like `overall_progress`
+        chapter.completion_percentage = if chapter.total_exercises > 0 {
+            (chapter.exercises_completed as f64 / chapter.total_exercises as f64).min(1.0)
+        } else {
+            0.0
+        };
+        // Completed when the last remaining exercise was
+        if chapter.total_exercises > 0 && chapter.exercises_completed >= chapter.total_exercises {
+            chapter.completed_at = last_completed.remove(&chapter.chapter_number).flatten();
+        }
+    }
+}
+
+// Recompute the chapter statistics and `chapters_completed` in place; returns
+// whether anything changed
+pub fn update_chapter_stats(progress: &mut ProgressData) -> bool {
+    let Ok(stored) = serde_json::from_value::<HashMap<String, ChapterInfo>>(progress.chapters.clone()) else {
+        return false;
+    };
+    let mut chapters: HashMap<u32, ChapterInfo> = stored
+        .into_values()
+        .map(|chapter| (chapter.chapter_number, chapter))
+        .collect();
+    apply_chapter_stats(&mut chapters, &progress.exercise_history);
+
+    let chapters_completed = chapters
+        .values()
+        .filter(|chapter| chapter.completed_at.is_some())
+        .count() as u32;
+    let Ok(chapters) = serde_json::to_value(&chapters) else {
+        return false;
+    };
+
+    let changed = chapters != progress.chapters || chapters_completed != progress.chapters_completed;
+    progress.chapters = chapters;
+    progress.chapters_completed = chapters_completed;
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chapter(chapter_number: u32, exercises: &[u32]) -> ChapterInfo {
+        ChapterInfo {
+            chapter_number,
+            title: format!("Chapter {}", chapter_number),
+            exercises_completed: 0,
+            total_exercises: exercises.len() as u32,
+            completion_percentage: 0.0,
+            time_spent_minutes: 0,
+            first_started: None,
+            completed_at: None,
+            exercises: exercises.to_vec(),
+        }
+    }
+
+    fn entry(exercise_id: &str, completed_at: Option<&str>, minutes: u32) -> ExerciseHistoryEntry {
+        serde_json::from_value(serde_json::json!({
+            "exercise_id": exercise_id,
+            "status": if completed_at.is_some() { "completed" } else { "viewed" },
+            "viewed_at": "2026-10-01T09:00:00Z",
+            "completed_at": completed_at,
+            "time_taken_minutes": minutes,
+        }))
+        .unwrap()
+    }
+
+    fn catalog(chapters: Vec<ChapterInfo>) -> HashMap<u32, ChapterInfo> {
+        chapters.into_iter().map(|chapter| (chapter.chapter_number, chapter)).collect()
+    }
+
+    #[test]
+    fn parses_chapter_and_exercise_numbers_of_both_id_forms() {
+        assert_eq!(exercise_chapter("ch04-ex01-ownership-basics"), Some(4));
+        assert_eq!(exercise_number("ch04-ex01-ownership-basics"), Some(1));
+        assert_eq!(exercise_chapter("ch04_understanding_ownership/ex12_slices"), Some(4));
+        assert_eq!(exercise_number("ch04_understanding_ownership/ex12_slices"), Some(12));
+        assert_eq!(exercise_number("ex03_compilation"), Some(3));
+        assert_eq!(exercise_chapter("intro"), None);
+        assert_eq!(exercise_number("ch04"), None);
+    }
+
+    #[test]
+    fn aggregates_the_history_per_chapter() {
+        let mut chapters = catalog(vec![chapter(1, &[1, 2]), chapter(2, &[1, 2, 3])]);
+        let history = vec![
+            entry("ch01-ex01-hello", Some("2026-10-01T10:00:00Z"), 5),
+            entry("ch01_getting_started/ex02_cargo", Some("2026-10-03T10:00:00Z"), 7),
+            entry("ch02-ex01-guess", Some("2026-10-02T10:00:00Z"), 10),
+            entry("ch02-ex02-input", None, 4),
+        ];
+        apply_chapter_stats(&mut chapters, &history);
+
+        let first = &chapters[&1];
+        assert_eq!(first.exercises_completed, 2);
+        assert_eq!(first.completion_percentage, 1.0);
+        assert_eq!(first.time_spent_minutes, 12);
+        assert_eq!(first.first_started.as_deref(), Some("2026-10-01T09:00:00Z"));
+        assert_eq!(first.completed_at.as_deref(), Some("2026-10-03T10:00:00Z"));
+
+        let second = &chapters[&2];
+        assert_eq!(second.exercises_completed, 1);
+        assert!((second.completion_percentage - 1.0 / 3.0).abs() < 1e-9);
+        // Unfinished exercises count towards the time, not the completion
+        assert_eq!(second.time_spent_minutes, 14);
+        assert_eq!(second.completed_at, None);
+    }
+
+    #[test]
+    fn history_for_exercises_no_longer_on_disk_is_left_out() {
+        let mut chapters = catalog(vec![chapter(1, &[1, 2])]);
+        let history = vec![
+            entry("ch01-ex01-hello", Some("2026-10-01T10:00:00Z"), 5),
+            entry("ch01-ex07-removed", Some("2026-10-02T10:00:00Z"), 30),
+            entry("ch09-ex01-removed-chapter", Some("2026-10-02T10:00:00Z"), 30),
+        ];
+        apply_chapter_stats(&mut chapters, &history);
+
+        let first = &chapters[&1];
+        assert_eq!(first.exercises_completed, 1);
+        assert_eq!(first.completion_percentage, 0.5);
+        assert_eq!(first.time_spent_minutes, 5);
+        assert_eq!(first.completed_at, None);
+        assert!(!chapters.contains_key(&9));
+    }
+
+    #[test]
+    fn derives_chapters_completed() {
+        let mut progress: ProgressData = serde_json::from_value(serde_json::json!({
+            "schema_version": crate::progress_schema::PROGRESS_SCHEMA_VERSION,
+            "user_id": "learner",
+            "created_at": "2026-10-01T08:00:00Z",
+            "overall_progress": 0.0,
+            "chapters_completed": 0,
+            "exercises_completed": 0,
+            "total_exercises": 3,
+            "current_streak": 0,
+            "longest_streak": 0,
+            "total_time_minutes": 0,
+            "chapters": serde_json::to_value(catalog(vec![chapter(1, &[1]), chapter(2, &[1, 2])])).unwrap(),
+            "exercise_history": [],
+            "achievements": [],
+            "session_stats": {"exercises_viewed": 0, "exercises_completed": 0, "hints_used": 0, "time_spent": 0},
+        }))
+        .unwrap();
+        progress.exercise_history = vec![
+            entry("ch01-ex01-hello", Some("2026-10-01T10:00:00Z"), 5),
+            entry("ch02-ex01-guess", Some("2026-10-02T10:00:00Z"), 5),
+            entry("ch02-ex05-removed", Some("2026-10-02T11:00:00Z"), 5),
+        ];
+
+        assert!(update_chapter_stats(&mut progress));
+        assert_eq!(progress.chapters_completed, 1);
+        assert!(!update_chapter_stats(&mut progress));
+
+        progress
+            .exercise_history
+            .push(entry("ch02-ex02-input", Some("2026-10-04T10:00:00Z"), 5));
+        assert!(update_chapter_stats(&mut progress));
+        assert_eq!(progress.chapters_completed, 2);
+        assert_eq!(progress.chapters["2"]["completed_at"], "2026-10-04T10:00:00Z");
+    }
+}
@@ -0,0 +1,781 @@
+// Collaborative editing of exercise files
+//
+// Server-authoritative operational transformation in the style of ot.js. Each
+// open file is a document with a revision counter. A client sends operations
+// against the last revision it saw; the server transforms them over everything
+// applied since, applies the result, acknowledges the sender and forwards the
+// transformed operation to the other participants, so every editor converges
+// on the same text. Positions are UTF-16 code units, matching the browser
+// editor. Merged documents are written back to disk shortly after each change
+// and when the last participant leaves.
+
+use anyhow::{anyhow, bail};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, VecDeque},
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{fs, sync::Mutex};
+use tracing::{error, info, warn};
+
+use crate::{
+    find_exercise, load_exercise_title, profiles,
+    protocol::{CollabEvent, ServerMessage},
+    send_message, validate_exercise_file_path, AppState, ConnectionId,
+};
+
+const SAVE_DEBOUNCE: Duration = Duration::from_millis(500);
+// Operations kept for transforming late edits; older clients must rejoin
+const MAX_HISTORY: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OpComponent {
+    Retain(usize),
+    Insert(String),
+    Delete(usize),
+}
+
+// A sequence of retain/insert/delete steps that covers the whole document
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(transparent)]
+pub struct TextOperation {
+    ops: Vec<OpComponent>,
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+impl TextOperation {
+    fn retain(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if let Some(OpComponent::Retain(last)) = self.ops.last_mut() {
+            *last += n;
+        } else {
+            self.ops.push(OpComponent::Retain(n));
+        }
+    }
+
+    // Inserts always go before a delete at the same position, so equal
+    // operations have a single representation
+    fn insert(&mut self, text: &str) {
+        if text.is_empty() {
+            return;
+        }
+        let len = self.ops.len();
+        match self.ops.as_mut_slice() {
+            [.., OpComponent::Insert(last)] => last.push_str(text),
+            [.., OpComponent::Insert(before), OpComponent::Delete(_)] => before.push_str(text),
+            [.., OpComponent::Delete(_)] => self.ops.insert(len - 1, OpComponent::Insert(text.to_string())),
+            _ => self.ops.push(OpComponent::Insert(text.to_string())),
+        }
+    }
+
+    fn delete(&mut self, n: usize) {
+        if n == 0 {
+            return;
+        }
+        if let Some(OpComponent::Delete(last)) = self.ops.last_mut() {
+            *last += n;
+        } else {
+            self.ops.push(OpComponent::Delete(n));
+        }
+    }
+
+    fn push(&mut self, component: &OpComponent) {
+        match component {
+            OpComponent::Retain(n) => self.retain(*n),
+            OpComponent::Insert(text) => self.insert(text),
+            OpComponent::Delete(n) => self.delete(*n),
+        }
+    }
+
+    // Rebuild from client input, merging adjacent steps and dropping empty ones.
+    // Once the lengths add up without overflowing, so do any merged steps.
+    fn normalized(&self) -> anyhow::Result<Self> {
+        self.base_len()?;
+        let mut operation = Self::default();
+        for component in &self.ops {
+            operation.push(component);
+        }
+        Ok(operation)
+    }
+
+    fn base_len(&self) -> anyhow::Result<usize> {
+        self.ops
+            .iter()
+            .map(|component| match component {
+                OpComponent::Retain(n) | OpComponent::Delete(n) => *n,
+                OpComponent::Insert(_) => 0,
+            })
+            .try_fold(0usize, |total, n| total.checked_add(n))
+            .ok_or_else(|| anyhow!("operation is longer than any document"))
+    }
+
+    fn is_noop(&self) -> bool {
+        self.ops.iter().all(|component| matches!(component, OpComponent::Retain(_)))
+    }
+
+    fn apply(&self, text: &[u16]) -> anyhow::Result<Vec<u16>> {
+        let base_len = self.base_len()?;
+        if base_len != text.len() {
+            bail!(
+                "operation expects a document of length {} but it has length {}",
+                base_len,
+                text.len()
+            );
+        }
+
+        let mut result = Vec::with_capacity(text.len());
+        let mut index = 0;
+        for component in &self.ops {
+            match component {
+                OpComponent::Retain(n) => {
+                    result.extend_from_slice(&text[index..index + n]);
+                    index += n;
+                }
+                OpComponent::Insert(inserted) => result.extend(inserted.encode_utf16()),
+                OpComponent::Delete(n) => index += n,
+            }
+        }
+        Ok(result)
+    }
+
+    // Move a cursor position across this operation
+    fn transform_index(&self, index: usize) -> usize {
+        let mut remaining = index as isize;
+        let mut new_index = index;
+        for component in &self.ops {
+            if remaining < 0 {
+                break;
+            }
+            match component {
+                OpComponent::Retain(n) => remaining -= *n as isize,
+                OpComponent::Insert(text) => new_index += utf16_len(text),
+                OpComponent::Delete(n) => {
+                    new_index -= (*n).min(remaining.max(0) as usize);
+                    remaining -= *n as isize;
+                }
+            }
+        }
+        new_index
+    }
+}
+
+// Rest of a partially consumed retain or delete
+fn shorten(component: OpComponent, by: usize) -> Option<OpComponent> {
+    match component {
+        OpComponent::Retain(n) if n > by => Some(OpComponent::Retain(n - by)),
+        OpComponent::Delete(n) if n > by => Some(OpComponent::Delete(n - by)),
+        _ => None,
+    }
+}
+
+// Given concurrent operations a and b on the same document, produce (a', b')
+// such that applying a then b' gives the same text as applying b then a'
+fn transform(a: &TextOperation, b: &TextOperation) -> anyhow::Result<(TextOperation, TextOperation)> {
+    if a.base_len()? != b.base_len()? {
+        bail!("concurrent operations must start from the same document");
+    }
+
+    let mut a_prime = TextOperation::default();
+    let mut b_prime = TextOperation::default();
+    let mut ops1 = a.ops.iter().cloned();
+    let mut ops2 = b.ops.iter().cloned();
+    let mut op1 = ops1.next();
+    let mut op2 = ops2.next();
+
+    loop {
+        match (op1.clone(), op2.clone()) {
+            (None, None) => break,
+            (Some(OpComponent::Insert(text)), _) => {
+                a_prime.insert(&text);
+                b_prime.retain(utf16_len(&text));
+                op1 = ops1.next();
+            }
+            (_, Some(OpComponent::Insert(text))) => {
+                a_prime.retain(utf16_len(&text));
+                b_prime.insert(&text);
+                op2 = ops2.next();
+            }
+            (None, _) | (_, None) => bail!("concurrent operations have different lengths"),
+            (Some(first), Some(second)) => {
+                let (n1, n2) = match (&first, &second) {
+                    (OpComponent::Retain(n1) | OpComponent::Delete(n1), OpComponent::Retain(n2) | OpComponent::Delete(n2)) => {
+                        (*n1, *n2)
+                    }
+                    _ => unreachable!("inserts are handled above"),
+                };
+                let n = n1.min(n2);
+                match (&first, &second) {
+                    (OpComponent::Retain(_), OpComponent::Retain(_)) => {
+                        a_prime.retain(n);
+                        b_prime.retain(n);
+                    }
+                    // Both deleted the same text
+                    (OpComponent::Delete(_), OpComponent::Delete(_)) => {}
+                    (OpComponent::Delete(_), OpComponent::Retain(_)) => a_prime.delete(n),
+                    (OpComponent::Retain(_), OpComponent::Delete(_)) => b_prime.delete(n),
+                    _ => unreachable!("inserts are handled above"),
+                }
+                op1 = shorten(first, n).or_else(|| ops1.next());
+                op2 = shorten(second, n).or_else(|| ops2.next());
+            }
+        }
+    }
+
+    Ok((a_prime, b_prime))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+pub struct SelectionRange {
+    anchor: usize,
+    head: usize,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct Participant {
+    connection_id: String,
+    client: Option<String>,
+    ranges: Vec<SelectionRange>,
+}
+
+pub struct CollabDocument {
+    id: String,
+    exercise_dir: PathBuf,
+    file: String,
+    text: Vec<u16>,
+    revision: u64,
+    // Operations that produced the last `history.len()` revisions
+    history: VecDeque<TextOperation>,
+    saved_revision: u64,
+    save_scheduled: bool,
+    participants: HashMap<ConnectionId, Participant>,
+    // Messages for participants, queued under the lock and sent after it is
+    // released, in the order they were queued
+    outbox: VecDeque<(ConnectionId, CollabEvent)>,
+    delivering: bool,
+}
+
+pub type SharedDocument = Arc<Mutex<CollabDocument>>;
+
+impl CollabDocument {
+    fn content(&self) -> String {
+        String::from_utf16_lossy(&self.text)
+    }
+
+    // Operations applied after `revision`, or an error if they are no longer kept
+    fn operations_since(&self, revision: u64) -> anyhow::Result<impl Iterator<Item = &TextOperation>> {
+        let first_kept = self.revision - self.history.len() as u64;
+        if revision > self.revision {
+            bail!("Unknown revision {}", revision);
+        }
+        if revision < first_kept {
+            bail!("Revision {} is too old, rejoin the document", revision);
+        }
+        Ok(self.history.iter().skip((revision - first_kept) as usize))
+    }
+
+    fn receive_operation(&mut self, revision: u64, operation: &TextOperation) -> anyhow::Result<TextOperation> {
+        let mut operation = operation.normalized()?;
+        for concurrent in self.operations_since(revision)? {
+            operation = transform(&operation, concurrent)?.0;
+        }
+
+        self.text = operation.apply(&self.text)?;
+        self.revision += 1;
+        self.history.push_back(operation.clone());
+        if self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+        Ok(operation)
+    }
+
+    fn transform_ranges(&self, revision: u64, ranges: Vec<SelectionRange>) -> anyhow::Result<Vec<SelectionRange>> {
+        let operations: Vec<&TextOperation> = self.operations_since(revision)?.collect();
+        let max = self.text.len();
+        Ok(ranges
+            .into_iter()
+            .map(|range| {
+                let (anchor, head) = operations.iter().fold((range.anchor, range.head), |(anchor, head), op| {
+                    (op.transform_index(anchor), op.transform_index(head))
+                });
+                SelectionRange {
+                    anchor: anchor.min(max),
+                    head: head.min(max),
+                }
+            })
+            .collect())
+    }
+
+    fn send(&mut self, connection_id: ConnectionId, event: CollabEvent) {
+        self.outbox.push_back((connection_id, event));
+    }
+
+    fn broadcast(&mut self, except: Option<ConnectionId>, event: CollabEvent) {
+        let recipients: Vec<ConnectionId> = self
+            .participants
+            .keys()
+            .copied()
+            .filter(|connection_id| Some(*connection_id) != except)
+            .collect();
+        for connection_id in recipients {
+            self.send(connection_id, event.clone());
+        }
+    }
+}
+
+// Send the queued messages of a document without holding its lock. One caller
+// delivers at a time, so participants see operations in revision order; the
+// others leave their messages to it.
+async fn deliver(state: &AppState, shared: &SharedDocument) {
+    {
+        let mut document = shared.lock().await;
+        if document.delivering {
+            return;
+        }
+        document.delivering = true;
+    }
+    loop {
+        let batch: Vec<(ConnectionId, CollabEvent)> = {
+            let mut document = shared.lock().await;
+            if document.outbox.is_empty() {
+                document.delivering = false;
+                return;
+            }
+            document.outbox.drain(..).collect()
+        };
+        for (connection_id, event) in batch {
+            send_event(state, connection_id, event).await;
+        }
+    }
+}
+
+async fn send_event(state: &AppState, connection_id: ConnectionId, event: CollabEvent) {
+    send_message(state, connection_id, &ServerMessage::Collab(event)).await;
+}
+
+async fn send_error(state: &AppState, connection_id: ConnectionId, document_id: Option<String>, message: String) {
+    send_event(state, connection_id, CollabEvent::Error { document_id, message }).await;
+}
+
+async fn lookup_document(state: &AppState, document_id: &str) -> Option<SharedDocument> {
+    state.collab_documents.read().await.get(document_id).cloned()
+}
+
+// Documents are keyed by "chapter_dir/exercise_dir/file", prefixed with "~profile/"
+// outside the default profile
+fn document_id(exercise_path: &str, file: &str) -> String {
+    format!("{}/{}", exercise_path, file)
+}
+
+pub async fn is_open(state: &AppState, exercise_path: &str, file: &str) -> bool {
+    state
+        .collab_documents
+        .read()
+        .await
+        .contains_key(&document_id(exercise_path, file))
+}
+
+// Whether any file of the exercise is open for collaborative editing
+pub async fn has_open_documents(state: &AppState, exercise_path: &str) -> bool {
+    let prefix = format!("{}/", exercise_path);
+    state
+        .collab_documents
+        .read()
+        .await
+        .keys()
+        .any(|document_id| document_id.starts_with(&prefix))
+}
+
+pub async fn join(state: &AppState, connection_id: ConnectionId, exercise_id: String, file: String) -> anyhow::Result<()> {
+    // Same checks as saving files over HTTP
+    if validate_exercise_file_path(&file).is_err() {
+        send_error(state, connection_id, None, format!("File cannot be edited: {}", file)).await;
+        return Ok(());
+    }
+    let Some(exercise) = find_exercise(state, &exercise_id).await else {
+        send_error(state, connection_id, None, format!("Unknown exercise: {}", exercise_id)).await;
+        return Ok(());
+    };
+
+    // Each profile edits its own copy of the exercise
+    let profile = profiles::connection_profile(state, connection_id).await;
+    let exercise_dir = match state.profiles.writable_exercise_dir(&profile, &exercise.path).await {
+        Ok(dir) => dir,
+        Err(e) => {
+            send_error(state, connection_id, None, format!("Cannot open {}: {}", file, e)).await;
+            return Ok(());
+        }
+    };
+    let id = document_id(&profiles::scoped_exercise_path(&profile, &exercise.path), &file);
+
+    let document = {
+        let mut documents = state.collab_documents.write().await;
+        match documents.get(&id) {
+            Some(document) => document.clone(),
+            None => {
+                let content = match fs::read_to_string(exercise_dir.join(&file)).await {
+                    Ok(content) => content,
+                    Err(e) => {
+                        drop(documents);
+                        send_error(state, connection_id, None, format!("Cannot open {}: {}", file, e)).await;
+                        return Ok(());
+                    }
+                };
+                info!("Opened collaborative document {}", id);
+                let document = Arc::new(Mutex::new(CollabDocument {
+                    id: id.clone(),
+                    exercise_dir,
+                    file,
+                    text: content.encode_utf16().collect(),
+                    revision: 0,
+                    history: VecDeque::new(),
+                    saved_revision: 0,
+                    save_scheduled: false,
+                    participants: HashMap::new(),
+                    outbox: VecDeque::new(),
+                    delivering: false,
+                }));
+                documents.insert(id.clone(), document.clone());
+                document
+            }
+        }
+    };
+
+    let client = state
+        .connections
+        .read()
+        .await
+        .get(&connection_id)
+        .and_then(|connection| connection.presence.client.clone());
+    let participant = Participant {
+        connection_id: connection_id.to_string(),
+        client,
+        ranges: Vec::new(),
+    };
+
+    {
+        let mut document = document.lock().await;
+        document.participants.insert(connection_id, participant.clone());
+
+        let joined = CollabEvent::Joined {
+            document_id: id.clone(),
+            revision: document.revision,
+            content: document.content(),
+            participants: document.participants.values().cloned().collect(),
+        };
+        document.send(connection_id, joined);
+        document.broadcast(Some(connection_id), CollabEvent::ParticipantJoined { document_id: id, participant });
+    }
+    deliver(state, &document).await;
+
+    Ok(())
+}
+
+pub async fn leave(state: &AppState, connection_id: ConnectionId, document_id: &str) -> anyhow::Result<()> {
+    let mut documents = state.collab_documents.write().await;
+    let Some(shared) = documents.get(document_id).cloned() else {
+        return Ok(());
+    };
+
+    let mut document = shared.lock().await;
+    if document.participants.remove(&connection_id).is_none() {
+        return Ok(());
+    }
+
+    document.broadcast(
+        None,
+        CollabEvent::ParticipantLeft {
+            document_id: document_id.to_string(),
+            connection_id: connection_id.to_string(),
+        },
+    );
+
+    let closed = document.participants.is_empty();
+    if closed {
+        documents.remove(document_id);
+    }
+    drop(documents);
+    if closed {
+        save_document(state, &mut document).await;
+        info!("Closed collaborative document {}", document_id);
+    }
+    drop(document);
+    deliver(state, &shared).await;
+
+    Ok(())
+}
+
+// Remove a disconnected client from every document it had joined
+pub async fn leave_all(state: &AppState, connection_id: ConnectionId) -> anyhow::Result<()> {
+    let documents: Vec<SharedDocument> = state.collab_documents.read().await.values().cloned().collect();
+    let mut joined = Vec::new();
+    for document in documents {
+        let document = document.lock().await;
+        if document.participants.contains_key(&connection_id) {
+            joined.push(document.id.clone());
+        }
+    }
+
+    for document_id in joined {
+        leave(state, connection_id, &document_id).await?;
+    }
+    Ok(())
+}
+
+pub async fn receive_operation(
+    state: &AppState,
+    connection_id: ConnectionId,
+    document_id: String,
+    revision: u64,
+    operation: TextOperation,
+) -> anyhow::Result<()> {
+    let Some(shared) = lookup_document(state, &document_id).await else {
+        send_error(state, connection_id, Some(document_id), "Document is not open".to_string()).await;
+        return Ok(());
+    };
+
+    let mut document = shared.lock().await;
+    if !document.participants.contains_key(&connection_id) {
+        drop(document);
+        send_error(state, connection_id, Some(document_id), "Join the document before editing it".to_string()).await;
+        return Ok(());
+    }
+
+    let operation = match document.receive_operation(revision, &operation) {
+        Ok(operation) => operation,
+        Err(e) => {
+            drop(document);
+            warn!("Rejected operation on {} from {}: {}", document_id, connection_id, e);
+            send_error(state, connection_id, Some(document_id), e.to_string()).await;
+            return Ok(());
+        }
+    };
+
+    // Keep remote cursors in place as the text around them changes
+    for participant in document.participants.values_mut() {
+        for range in &mut participant.ranges {
+            range.anchor = operation.transform_index(range.anchor);
+            range.head = operation.transform_index(range.head);
+        }
+    }
+
+    let revision = document.revision;
+    document.send(connection_id, CollabEvent::Ack { document_id: document_id.clone(), revision });
+    document.broadcast(
+        Some(connection_id),
+        CollabEvent::Operation {
+            document_id,
+            revision,
+            connection_id: connection_id.to_string(),
+            operation: operation.clone(),
+        },
+    );
+
+    if !operation.is_noop() {
+        schedule_save(state, &shared, &mut document);
+    }
+    drop(document);
+    deliver(state, &shared).await;
+
+    Ok(())
+}
+
+pub async fn update_selection(
+    state: &AppState,
+    connection_id: ConnectionId,
+    document_id: String,
+    revision: u64,
+    ranges: Vec<SelectionRange>,
+) -> anyhow::Result<()> {
+    let Some(shared) = lookup_document(state, &document_id).await else {
+        send_error(state, connection_id, Some(document_id), "Document is not open".to_string()).await;
+        return Ok(());
+    };
+
+    let mut document = shared.lock().await;
+    let ranges = match document.transform_ranges(revision, ranges) {
+        Ok(ranges) => ranges,
+        Err(e) => {
+            drop(document);
+            send_error(state, connection_id, Some(document_id), e.to_string()).await;
+            return Ok(());
+        }
+    };
+
+    let Some(participant) = document.participants.get_mut(&connection_id) else {
+        drop(document);
+        send_error(state, connection_id, Some(document_id), "Join the document first".to_string()).await;
+        return Ok(());
+    };
+    participant.ranges = ranges.clone();
+
+    document.broadcast(
+        Some(connection_id),
+        CollabEvent::Selection {
+            document_id,
+            connection_id: connection_id.to_string(),
+            ranges,
+        },
+    );
+    drop(document);
+    deliver(state, &shared).await;
+
+    Ok(())
+}
+
+fn schedule_save(state: &AppState, shared: &SharedDocument, document: &mut CollabDocument) {
+    if document.save_scheduled {
+        return;
+    }
+    document.save_scheduled = true;
+
+    let state = state.clone();
+    let shared = shared.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(SAVE_DEBOUNCE).await;
+        {
+            let mut document = shared.lock().await;
+            document.save_scheduled = false;
+            save_document(&state, &mut document).await;
+        }
+        deliver(&state, &shared).await;
+    });
+}
+
+async fn save_document(state: &AppState, document: &mut CollabDocument) {
+    if document.saved_revision == document.revision {
+        return;
+    }
+
+    let path = document.exercise_dir.join(&document.file);
+    if let Err(e) = write_document(&path, &document.content()).await {
+        error!("Error saving collaborative document {}: {}", document.id, e);
+        return;
+    }
+    document.saved_revision = document.revision;
+
+    let exercise = load_exercise_title(&document.exercise_dir)
+        .await
+        .unwrap_or_else(|_| document.id.clone());
+    let _ = state.broadcast_tx.send(ServerMessage::FileUpdated {
+        exercise,
+        file: document.file.clone(),
+    });
+    let saved = CollabEvent::Saved {
+        document_id: document.id.clone(),
+        revision: document.revision,
+    };
+    document.broadcast(None, saved);
+}
+
+async fn write_document(path: &std::path::Path, content: &str) -> anyhow::Result<()> {
+    let parent = path.parent().ok_or_else(|| anyhow!("{} has no parent directory", path.display()))?;
+    fs::create_dir_all(parent).await?;
+    fs::write(path, content).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn operation(components: &[OpComponent]) -> TextOperation {
+        let mut operation = TextOperation::default();
+        for component in components {
+            operation.push(component);
+        }
+        operation
+    }
+
+    fn apply(operation: &TextOperation, text: &str) -> String {
+        let text: Vec<u16> = text.encode_utf16().collect();
+        String::from_utf16(&operation.apply(&text).expect("operation applies")).expect("valid UTF-16")
+    }
+
+    // Both orders of applying a and b give the same text
+    fn converge(text: &str, a: &TextOperation, b: &TextOperation) -> String {
+        let (a_prime, b_prime) = transform(a, b).expect("operations transform");
+        let a_then_b = apply(&b_prime, &apply(a, text));
+        let b_then_a = apply(&a_prime, &apply(b, text));
+        assert_eq!(a_then_b, b_then_a);
+        a_then_b
+    }
+
+    #[test]
+    fn concurrent_inserts_converge() {
+        use OpComponent::*;
+        let a = operation(&[Retain(1), Insert("X".into()), Retain(2)]);
+        let b = operation(&[Retain(2), Insert("Y".into()), Retain(1)]);
+        assert_eq!(converge("abc", &a, &b), "aXbYc");
+
+        // At the same position the first operation's text goes first
+        let a = operation(&[Retain(1), Insert("X".into()), Retain(2)]);
+        let b = operation(&[Retain(1), Insert("Y".into()), Retain(2)]);
+        assert_eq!(converge("abc", &a, &b), "aXYbc");
+    }
+
+    #[test]
+    fn concurrent_insert_and_delete_converge() {
+        use OpComponent::*;
+        let insert = operation(&[Retain(2), Insert("X".into()), Retain(2)]);
+        let delete = operation(&[Retain(1), Delete(2), Retain(1)]);
+        assert_eq!(converge("abcd", &insert, &delete), "aXd");
+        assert_eq!(converge("abcd", &delete, &insert), "aXd");
+
+        // Deleting the same text twice removes it once
+        let delete_more = operation(&[Delete(3), Retain(1)]);
+        assert_eq!(converge("abcd", &delete, &delete_more), "d");
+    }
+
+    #[test]
+    fn positions_count_utf16_code_units() {
+        use OpComponent::*;
+        // The emoji is a surrogate pair, two code units
+        let text = "a😀b";
+        assert_eq!(utf16_len(text), 4);
+
+        let after_emoji = operation(&[Retain(3), Insert("é".into()), Retain(1)]);
+        assert_eq!(apply(&after_emoji, text), "a😀éb");
+        let delete_emoji = operation(&[Retain(1), Delete(2), Retain(1)]);
+        assert_eq!(apply(&delete_emoji, text), "ab");
+
+        let insert_emoji = operation(&[Retain(1), Insert("🦀".into()), Retain(3)]);
+        assert_eq!(converge(text, &insert_emoji, &delete_emoji), "a🦀b");
+        assert_eq!(converge(text, &after_emoji, &delete_emoji), "aéb");
+
+        // A cursor after the emoji moves by the length of the inserted pair
+        assert_eq!(insert_emoji.transform_index(3), 5);
+        assert_eq!(delete_emoji.transform_index(3), 1);
+    }
+
+    #[test]
+    fn oversized_operations_are_rejected() {
+        use OpComponent::*;
+        let overflowing = TextOperation {
+            ops: vec![Retain(usize::MAX), Retain(1)],
+        };
+        assert!(overflowing.normalized().is_err());
+        assert!(overflowing.base_len().is_err());
+
+        let overflowing = TextOperation {
+            ops: vec![Delete(usize::MAX), Insert("x".into()), Delete(1)],
+        };
+        assert!(overflowing.normalized().is_err());
+    }
+
+    #[test]
+    fn operations_must_cover_the_document() {
+        use OpComponent::*;
+        let short = operation(&[Retain(2)]);
+        assert!(short.apply(&"a😀b".encode_utf16().collect::<Vec<u16>>()).is_err());
+        assert!(transform(&short, &operation(&[Retain(3)])).is_err());
+    }
+}
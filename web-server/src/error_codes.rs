@@ -0,0 +1,390 @@
+// Compiler error codes
+//
+// The output of every run, check and test started from the browser (REST or
+// WebSocket) is scanned for the diagnostics the learner ran into: rustc error
+// codes (`error[E0382]`) and clippy lints (`clippy::needless_return`, taken
+// from the lint's documentation link clippy prints with each warning). Each
+// diagnostic counts once, so two moves out of the same value are two E0382.
+// Rustc lints without a code, like `unused_variables`, aren't counted.
+//
+// The codes of one run are recorded with a timestamp in the event log, and
+// added up per code in the exercise's history entry: how often it occurred,
+// when first and last, and per day for the last `2 * MAX_TREND_DAYS` days, so
+// the entry doesn't grow with every failing build. `GET /api/analytics/errors`
+// lists the learner's most frequent codes with how often they came up in the
+// last `days` days compared with the `days` before, and the exercises they
+// occurred in.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, HashMap},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    event_log::LearnerEventKind, find_exercise, history_entry_mut, log_learner_event, profiles::ActiveProfile,
+    protocol::CodeAction, scan_exercises, test_runs::strip_ansi, AppState, CargoResult, ExerciseHistoryEntry, ProgressData,
+};
+
+const DEFAULT_TREND_DAYS: u32 = 7;
+const MAX_TREND_DAYS: u32 = 366;
+const DEFAULT_LISTED_ERRORS: usize = 10;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorCodeRecord {
+    pub at: String,
+    pub action: CodeAction,
+    // Occurrences per code, e.g. "E0382" or "clippy::needless_return"
+    pub codes: BTreeMap<String, u32>,
+}
+
+// Occurrences of one code in one exercise
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ErrorCodeStats {
+    pub count: u32,
+    pub first_seen: String,
+    pub last_seen: String,
+    // Occurrences per UTC day ("2026-10-18"), for the trend windows
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub daily: BTreeMap<String, u32>,
+}
+
+impl ErrorCodeStats {
+    pub fn new(at: &str) -> Self {
+        Self {
+            count: 0,
+            first_seen: at.to_string(),
+            last_seen: at.to_string(),
+            daily: BTreeMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, at: &str, count: u32) {
+        self.count += count;
+        if at < self.first_seen.as_str() {
+            self.first_seen = at.to_string();
+        }
+        if at > self.last_seen.as_str() {
+            self.last_seen = at.to_string();
+        }
+        if let Some(day) = utc_day(at) {
+            *self.daily.entry(day.to_string()).or_default() += count;
+        }
+        self.prune_days();
+    }
+
+    // Combine with the stats of another installation; overlapping
+    // occurrences can't be told apart, so the larger count is kept
+    pub fn merge(&mut self, other: &ErrorCodeStats) {
+        for (day, count) in &other.daily {
+            let local = self.daily.entry(day.clone()).or_default();
+            *local = (*local).max(*count);
+        }
+        self.prune_days();
+        self.count = self.count.max(other.count).max(self.daily.values().sum());
+        if other.first_seen < self.first_seen {
+            self.first_seen = other.first_seen.clone();
+        }
+        if other.last_seen > self.last_seen {
+            self.last_seen = other.last_seen.clone();
+        }
+    }
+
+    // Keep the days the longest trend windows can reach back to
+    fn prune_days(&mut self) {
+        let latest = self.daily.keys().next_back().and_then(|day| day.parse::<NaiveDate>().ok());
+        if let Some(latest) = latest {
+            let oldest = (latest - Duration::days(2 * MAX_TREND_DAYS as i64)).to_string();
+            self.daily.retain(|day, _| *day > oldest);
+        }
+    }
+
+    // Occurrences on days from `from` to `until`, both included
+    fn count_between(&self, from: NaiveDate, until: NaiveDate) -> u32 {
+        self.daily
+            .range(from.to_string()..=until.to_string())
+            .map(|(_, count)| count)
+            .sum()
+    }
+}
+
+fn utc_day(at: &str) -> Option<NaiveDate> {
+    DateTime::parse_from_rfc3339(at)
+        .ok()
+        .map(|at| at.with_timezone(&Utc).date_naive())
+}
+
+// Error codes in cargo output, with how often each occurred
+pub fn parse_error_codes(output: &str) -> BTreeMap<String, u32> {
+    let mut codes = BTreeMap::new();
+    let mut in_diagnostic = false;
+    let mut current: Option<String> = None;
+
+    for line in strip_ansi(output).lines() {
+        // A diagnostic starts unindented; its notes and help follow it
+        if let Some(rest) = line.strip_prefix("error").or_else(|| line.strip_prefix("warning")) {
+            if let Some(code) = current.take() {
+                *codes.entry(code).or_default() += 1;
+            }
+            in_diagnostic = rest.starts_with('[') || rest.starts_with(':');
+            current = rest
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once(']'))
+                .map(|(code, _)| code)
+                .filter(|code| is_rustc_code(code))
+                .map(str::to_string);
+        } else if in_diagnostic && current.is_none() {
+            current = line
+                .split_once("rust-clippy/")
+                .and_then(|(_, link)| link.split_once("index.html#"))
+                .map(|(_, lint)| lint.trim())
+                .filter(|lint| !lint.is_empty() && lint.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'))
+                .map(|lint| format!("clippy::{}", lint));
+        }
+    }
+    if let Some(code) = current {
+        *codes.entry(code).or_default() += 1;
+    }
+    codes
+}
+
+// "E" and four digits
+fn is_rustc_code(code: &str) -> bool {
+    code.len() == 5 && code.starts_with('E') && code[1..].chars().all(|c| c.is_ascii_digit())
+}
+
+// Where the code is explained
+fn explanation_url(code: &str) -> String {
+    match code.strip_prefix("clippy::") {
+        Some(lint) => format!("https://rust-lang.github.io/rust-clippy/master/index.html#{}", lint),
+        None => format!("https://doc.rust-lang.org/error_codes/{}.html", code),
+    }
+}
+
+// Record the error codes of a run, check or test of the exercise, found by
+// metadata id or path
+pub async fn record_error_codes(
+    state: &AppState,
+    profile: &str,
+    exercise: &str,
+    action: CodeAction,
+    result: &CargoResult,
+) {
+    let codes = parse_error_codes(&result.output);
+    if codes.is_empty() {
+        return;
+    }
+    // History entries are keyed by the metadata id, like test runs
+    let exercise_id = match find_exercise(state, exercise).await {
+        Some(found) => found.metadata.id,
+        None => exercise.to_string(),
+    };
+    info!("Error codes for {}: {:?}", exercise_id, codes);
+
+    let record = ErrorCodeRecord {
+        at: Utc::now().to_rfc3339(),
+        action,
+        codes,
+    };
+    let event = LearnerEventKind::ErrorCodes {
+        exercise_id: exercise_id.clone(),
+        record: record.clone(),
+    };
+    let result = match state.profiles.progress(profile).await {
+        Ok(progress) => {
+            progress
+                .update(move |progress| add_error_codes(progress, exercise_id, record))
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        warn!("Failed to record error codes for profile {}: {}", profile, e);
+        return;
+    }
+    log_learner_event(state, profile, event).await;
+}
+
+pub fn add_error_codes(progress: &mut ProgressData, exercise_id: String, record: ErrorCodeRecord) -> bool {
+    let entry = history_entry_mut(progress, &exercise_id, "in_progress");
+    for (code, count) in record.codes {
+        entry
+            .error_codes
+            .entry(code)
+            .or_insert_with(|| ErrorCodeStats::new(&record.at))
+            .add(&record.at, count);
+    }
+    true
+}
+
+// Occurrences per code over the whole history
+pub fn code_totals(history: &[ExerciseHistoryEntry]) -> BTreeMap<String, u32> {
+    let mut totals = BTreeMap::new();
+    for (code, stats) in history.iter().flat_map(|entry| &entry.error_codes) {
+        *totals.entry(code.clone()).or_default() += stats.count;
+    }
+    totals
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorReport {
+    // Occurrences of all codes
+    pub total: u32,
+    // Length of the windows `recent` and `previous` are counted over
+    pub trend_days: u32,
+    pub errors: Vec<ErrorSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorSummary {
+    pub code: String,
+    // "rustc" or "clippy"
+    pub source: &'static str,
+    pub explanation_url: String,
+    pub count: u32,
+    pub first_seen: String,
+    pub last_seen: String,
+    // Occurrences in the last `trend_days` days and in the `trend_days` before
+    pub recent: u32,
+    pub previous: u32,
+    pub trend: ErrorTrend,
+    // Most occurrences first
+    pub exercises: Vec<ErrorExercise>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorTrend {
+    // Every occurrence is recent
+    New,
+    Rising,
+    Steady,
+    Falling,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorExercise {
+    pub exercise_id: String,
+    // "chapter/exercise", when the exercise is still on disk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    pub count: u32,
+    pub last_seen: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorReportParams {
+    days: Option<u32>,
+    limit: Option<usize>,
+}
+
+// Path and title of an exercise, by metadata id or path
+type ExerciseNames = HashMap<String, (String, String)>;
+
+pub fn error_report(progress: &ProgressData, exercises: &ExerciseNames, trend_days: u32, limit: usize) -> ErrorReport {
+    // Windows of whole UTC days, the last one ending today
+    let today = Utc::now().date_naive();
+    let recent_from = today - Duration::days(trend_days as i64 - 1);
+    let previous_from = recent_from - Duration::days(trend_days as i64);
+
+    let mut summaries: BTreeMap<&str, ErrorSummary> = BTreeMap::new();
+    for entry in &progress.exercise_history {
+        for (code, stats) in &entry.error_codes {
+            let summary = summaries.entry(code).or_insert_with(|| ErrorSummary {
+                code: code.clone(),
+                source: if code.starts_with("clippy::") { "clippy" } else { "rustc" },
+                explanation_url: explanation_url(code),
+                count: 0,
+                first_seen: stats.first_seen.clone(),
+                last_seen: stats.last_seen.clone(),
+                recent: 0,
+                previous: 0,
+                trend: ErrorTrend::Steady,
+                exercises: Vec::new(),
+            });
+            summary.count += stats.count;
+            if stats.first_seen < summary.first_seen {
+                summary.first_seen = stats.first_seen.clone();
+            }
+            if stats.last_seen > summary.last_seen {
+                summary.last_seen = stats.last_seen.clone();
+            }
+            summary.recent += stats.count_between(recent_from, today);
+            summary.previous += stats.count_between(previous_from, recent_from - Duration::days(1));
+
+            let names = exercises.get(&entry.exercise_id);
+            summary.exercises.push(ErrorExercise {
+                exercise_id: entry.exercise_id.clone(),
+                path: names.map(|(path, _)| path.clone()),
+                title: names.map(|(_, title)| title.clone()),
+                url: names.map(|(path, _)| format!("/api/exercises/{}", path)),
+                count: stats.count,
+                last_seen: stats.last_seen.clone(),
+            });
+        }
+    }
+
+    let mut errors: Vec<ErrorSummary> = summaries.into_values().collect();
+    for summary in &mut errors {
+        summary.trend = match summary.recent.cmp(&summary.previous) {
+            _ if summary.recent == summary.count => ErrorTrend::New,
+            Ordering::Greater => ErrorTrend::Rising,
+            Ordering::Less => ErrorTrend::Falling,
+            Ordering::Equal => ErrorTrend::Steady,
+        };
+        summary.exercises.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_seen.cmp(&a.last_seen)));
+    }
+    errors.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_seen.cmp(&a.last_seen)));
+    let total = errors.iter().map(|summary| summary.count).sum();
+    errors.truncate(limit);
+
+    ErrorReport {
+        total,
+        trend_days,
+        errors,
+    }
+}
+
+// API handler
+pub async fn get_error_report(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Query(params): Query<ErrorReportParams>,
+) -> Result<Json<ErrorReport>, StatusCode> {
+    let progress = match state.profiles.progress(&profile).await {
+        Ok(progress) => progress.snapshot().await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        error!("Error loading progress for the error report: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+    let exercises = scan_exercises(&state.exercises_path).await.map_err(|e| {
+        error!("Error loading exercises for the error report: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    // History entries are keyed by metadata id; older ones by path
+    let mut names = HashMap::new();
+    for exercise in exercises {
+        let name = (exercise.path.clone(), exercise.metadata.title.clone());
+        names.insert(exercise.path, name.clone());
+        names.insert(exercise.metadata.id, name);
+    }
+
+    let days = params.days.unwrap_or(DEFAULT_TREND_DAYS).clamp(1, MAX_TREND_DAYS);
+    let limit = params.limit.unwrap_or(DEFAULT_LISTED_ERRORS).max(1);
+    Ok(Json(error_report(&progress, &names, days, limit)))
+}
@@ -0,0 +1,305 @@
+// Learner event log
+//
+// The progress keeps one entry per exercise and updates it in place, so the
+// order things happened in is lost: which hint came before which failed run,
+// how often a file was saved. Every interaction is therefore also appended to
+// `learner_events.jsonl` next to the profile's progress file, one JSON object
+// per line: views, file saves, runs and checks, test results, error codes,
+// hint unlocks, completions, terminal starts, active time, resets and
+// preference changes, each with a timestamp and the activity session it
+// happened in. Lines are never rewritten.
+//
+// Once the file would grow past `--event-log-max-mb` it is renamed to
+// `learner_events.jsonl.<n>` (1 is the oldest) and a new one is started.
+// Rotated files are kept, so `replay` can rebuild `ProgressData` from the
+// first event: views, hints, test results, error codes, completions, active
+// time, resets and preferences are applied to fresh progress in order, then
+// the statistics and achievements are derived as usual. Imported progress
+// isn't in the log.
+//
+// Available as `rust-tour replay`.
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    fs::{self, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+use tracing::{info, warn};
+
+use crate::{
+    activity::{self, ActivitySession},
+    error_codes::{self, ErrorCodeRecord},
+    preferences::UserPreferences,
+    prepare_progress,
+    profiles::ProfileRegistry,
+    progress_store,
+    protocol::CodeAction,
+    record_completion, record_hint, record_view,
+    reset::{self, ResetRecord},
+    test_runs::{self, TestRun},
+    CompletionSource, ProgressData,
+};
+
+pub const DEFAULT_EVENT_LOG_MAX_MB: u64 = 8;
+const EVENT_LOG_FILE: &str = "learner_events.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LearnerEvent {
+    pub at: String,
+    // Activity session the event happened in, see `activity`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub session_id: Option<String>,
+    #[serde(flatten)]
+    pub kind: LearnerEventKind,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum LearnerEventKind {
+    ExerciseViewed {
+        exercise_id: String,
+    },
+    // `exercise` is the "chapter/exercise" path
+    FileSaved {
+        exercise: String,
+        file: String,
+        bytes: usize,
+    },
+    // `cargo run` or `cargo clippy`; test runs are `test_result`
+    CodeRun {
+        exercise_id: String,
+        action: CodeAction,
+        success: bool,
+    },
+    TestResult {
+        exercise_id: String,
+        run: TestRun,
+    },
+    // Rustc and clippy codes in the output of a run, check or test
+    ErrorCodes {
+        exercise_id: String,
+        record: ErrorCodeRecord,
+    },
+    HintUnlocked {
+        exercise_id: String,
+        level: u32,
+    },
+    ExerciseCompleted {
+        exercise_id: String,
+        completed_by: CompletionSource,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        time_taken_minutes: Option<u32>,
+    },
+    TerminalStarted {
+        terminal_session_id: String,
+    },
+    // Active time written to the progress, per exercise
+    ActiveTime {
+        session: ActivitySession,
+        exercises: BTreeMap<String, u64>,
+    },
+    ProgressReset {
+        reset: ResetRecord,
+    },
+    // The preferences after a change
+    PreferencesChanged {
+        preferences: UserPreferences,
+    },
+}
+
+// `exercise_completed` for a completion just recorded in `progress`
+pub fn completion_event(progress: &ProgressData, exercise_id: &str) -> Option<LearnerEventKind> {
+    let entry = progress
+        .exercise_history
+        .iter()
+        .find(|entry| entry.exercise_id == exercise_id && entry.completed_at.is_some())?;
+    Some(LearnerEventKind::ExerciseCompleted {
+        exercise_id: exercise_id.to_string(),
+        completed_by: entry.completed_by.unwrap_or(CompletionSource::Client),
+        time_taken_minutes: entry.time_taken_minutes,
+    })
+}
+
+// Appends events; one lock for all profiles keeps rotation and appends apart
+#[derive(Clone)]
+pub struct EventLog {
+    max_bytes: u64,
+    lock: Arc<Mutex<()>>,
+}
+
+impl EventLog {
+    pub fn new(max_mb: u64) -> Self {
+        Self {
+            max_bytes: max_mb.max(1) * 1024 * 1024,
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    // `progress_path` is the profile's JSON progress path; the log sits next to it
+    pub async fn append(&self, progress_path: &Path, event: &LearnerEvent) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(event)?;
+        line.push(b'\n');
+
+        let path = log_path(progress_path);
+        let _guard = self.lock.lock().await;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let size = fs::metadata(&path).await.map(|metadata| metadata.len()).unwrap_or(0);
+        if size > 0 && size + line.len() as u64 > self.max_bytes {
+            rotate(&path).await?;
+        }
+
+        // One write per line; with O_APPEND a crash can only leave the last line torn
+        let mut file = OpenOptions::new().create(true).append(true).open(&path).await?;
+        file.write_all(&line).await?;
+        Ok(())
+    }
+}
+
+pub fn log_path(progress_path: &Path) -> PathBuf {
+    progress_path.with_file_name(EVENT_LOG_FILE)
+}
+
+fn segment_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+// Rotated files, oldest first
+async fn rotated_segments(path: &Path) -> Vec<PathBuf> {
+    let mut segments = Vec::new();
+    loop {
+        let segment = segment_path(path, segments.len() + 1);
+        if !fs::try_exists(&segment).await.unwrap_or(false) {
+            return segments;
+        }
+        segments.push(segment);
+    }
+}
+
+async fn rotate(path: &Path) -> anyhow::Result<()> {
+    let segment = segment_path(path, rotated_segments(path).await.len() + 1);
+    fs::rename(path, &segment).await?;
+    info!("Rotated event log to {}", segment.display());
+    Ok(())
+}
+
+// Every event of a profile, oldest first
+pub async fn read_events(progress_path: &Path) -> anyhow::Result<Vec<LearnerEvent>> {
+    let path = log_path(progress_path);
+    let mut files = rotated_segments(&path).await;
+    files.push(path);
+
+    let mut events = Vec::new();
+    for file in files {
+        let content = match fs::read_to_string(&file).await {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e.into()),
+        };
+        for (index, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+            match serde_json::from_str(line) {
+                Ok(event) => events.push(event),
+                Err(e) => warn!("Skipping line {} of {}: {}", index + 1, file.display(), e),
+            }
+        }
+    }
+    Ok(events)
+}
+
+// Rebuild a profile's progress from its event log alone
+pub async fn replay(profiles: &ProfileRegistry, profile: &str) -> anyhow::Result<(ProgressData, usize)> {
+    let events = read_events(&profiles.progress_path(profile)).await?;
+    let (mut progress, _) = prepare_progress(None, profiles.exercises_path(), profile).await?;
+    if let Some(first) = events.first() {
+        progress.created_at = first.at.clone();
+    }
+    for event in &events {
+        apply_event(&mut progress, event);
+    }
+
+    let options = profiles.options();
+    progress_store::refresh_derived(&mut progress, options);
+    options.achievements.evaluate(&mut progress);
+    Ok((progress, events.len()))
+}
+
+fn apply_event(progress: &mut ProgressData, event: &LearnerEvent) {
+    match &event.kind {
+        LearnerEventKind::ExerciseViewed { exercise_id } => {
+            record_view(progress, exercise_id, &event.at);
+        }
+        LearnerEventKind::HintUnlocked { exercise_id, level } => {
+            record_hint(progress, exercise_id, *level, &event.at);
+        }
+        LearnerEventKind::TestResult { exercise_id, run } => {
+            test_runs::add_test_run(progress, exercise_id.clone(), run.clone());
+        }
+        LearnerEventKind::ErrorCodes { exercise_id, record } => {
+            error_codes::add_error_codes(progress, exercise_id.clone(), record.clone());
+        }
+        LearnerEventKind::ExerciseCompleted {
+            exercise_id,
+            completed_by,
+            time_taken_minutes,
+        } => {
+            if record_completion(progress, exercise_id, *time_taken_minutes, event.session_id.clone(), *completed_by) {
+                // Keep what was recorded at the time
+                if let Some(entry) = progress.exercise_history.iter_mut().find(|entry| entry.exercise_id == *exercise_id) {
+                    entry.completed_at = Some(event.at.clone());
+                    entry.time_taken_minutes = time_taken_minutes.or(entry.time_taken_minutes);
+                }
+            }
+        }
+        LearnerEventKind::ActiveTime { session, exercises } => {
+            activity::add_active_time(progress, session.clone(), exercises.clone());
+        }
+        LearnerEventKind::ProgressReset { reset } => {
+            reset::clear_progress(progress, &reset.exercises, reset.clone());
+        }
+        LearnerEventKind::PreferencesChanged { preferences } => {
+            progress.preferences = preferences.clone();
+        }
+        LearnerEventKind::FileSaved { .. } | LearnerEventKind::CodeRun { .. } | LearnerEventKind::TerminalStarted { .. } => {}
+    }
+}
+
+// `rust-tour replay`, run instead of the server
+pub async fn replay_command(profiles: &ProfileRegistry, profile: &str, output: Option<PathBuf>) -> anyhow::Result<()> {
+    let (progress, events) = replay(profiles, profile).await?;
+    println!("🔁 Replayed {} events of profile '{}'", events, profile);
+    println!(
+        "   Exercises completed: {}/{}, time: {} minutes, achievements: {}",
+        progress.exercises_completed,
+        progress.total_exercises,
+        progress.total_time_minutes,
+        progress.achievements.len()
+    );
+
+    if let Some(output) = output {
+        fs::write(&output, serde_json::to_vec_pretty(&progress)?).await?;
+        println!("✅ Rebuilt progress written to {}", output.display());
+    }
+    Ok(())
+}
+
+// Stamp an event and append it to the profile's log; failures are only logged
+pub async fn append_event(profiles: &ProfileRegistry, profile: &str, session_id: Option<String>, kind: LearnerEventKind) {
+    let event = LearnerEvent {
+        at: Utc::now().to_rfc3339(),
+        session_id,
+        kind,
+    };
+    if let Err(e) = profiles.options().event_log.append(&profiles.progress_path(profile), &event).await {
+        warn!("Failed to append to the event log of profile {}: {}", profile, e);
+    }
+}
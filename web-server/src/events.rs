@@ -0,0 +1,325 @@
+// Server-Sent Events
+//
+// `/api/events` carries the same messages as the WebSocket broadcast channel for
+// clients behind proxies that break WebSocket upgrades. Every broadcast gets a
+// sequential id, prefixed with an id of this server run (`<boot_id>-<seq>`), and
+// the most recent ones are kept in memory, so a client that reconnects with
+// `Last-Event-ID` receives what it missed. When the requested
+// id is no longer available (too old, or from before a server restart) the
+// client gets a `resync` event and should reload its state over REST.
+// Progress events are only sent to streams of the same learner profile.
+
+use axum::{
+    extract::{Query, State},
+    http::HeaderMap,
+    response::sse::{Event, KeepAlive, Sse},
+};
+use futures_util::stream::Stream;
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{profiles::ActiveProfile, protocol::ServerMessage, AppState};
+
+const EVENT_HISTORY_SIZE: usize = 1000;
+const EVENT_STREAM_QUEUE_SIZE: usize = 256;
+
+#[derive(Debug)]
+pub struct SequencedEvent {
+    id: u64,
+    message: ServerMessage,
+}
+
+// Numbered copy of recent broadcasts, shared by all SSE streams
+#[derive(Clone)]
+pub struct EventHistory {
+    inner: Arc<Mutex<HistoryInner>>,
+    tx: broadcast::Sender<Arc<SequencedEvent>>,
+}
+
+struct HistoryInner {
+    // Tells ids of this server run from those of earlier ones
+    boot_id: String,
+    next_id: u64,
+    events: VecDeque<Arc<SequencedEvent>>,
+}
+
+// Events a client missed, or a marker that they can no longer be replayed
+enum Replay {
+    Events(Vec<Arc<SequencedEvent>>),
+    Resync { latest_id: u64 },
+}
+
+impl EventHistory {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_STREAM_QUEUE_SIZE);
+        Self {
+            inner: Arc::new(Mutex::new(HistoryInner {
+                boot_id: Uuid::new_v4().simple().to_string(),
+                next_id: 1,
+                events: VecDeque::with_capacity(EVENT_HISTORY_SIZE),
+            })),
+            tx,
+        }
+    }
+
+    fn record(&self, message: ServerMessage) {
+        let Ok(mut inner) = self.inner.lock() else {
+            return;
+        };
+        let event = Arc::new(SequencedEvent {
+            id: inner.next_id,
+            message,
+        });
+        inner.next_id += 1;
+        if inner.events.len() == EVENT_HISTORY_SIZE {
+            inner.events.pop_front();
+        }
+        inner.events.push_back(event.clone());
+        // Sent while holding the lock so subscribers never see ids out of order
+        let _ = self.tx.send(event);
+    }
+
+    fn boot_id(&self) -> String {
+        let inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.boot_id.clone()
+    }
+
+    // Subscribe to new events together with everything after `last_event_id`,
+    // atomically; also returns the latest id, which the receiver continues from
+    fn subscribe_after(&self, last_event_id: Option<&str>) -> (Replay, u64, broadcast::Receiver<Arc<SequencedEvent>>) {
+        let inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let rx = self.tx.subscribe();
+        let replay = match last_event_id {
+            Some(last_event_id) => inner.events_after(last_event_id),
+            None => Replay::Events(Vec::new()),
+        };
+        (replay, inner.next_id - 1, rx)
+    }
+
+    fn events_after_seq(&self, last_id: u64) -> Replay {
+        let inner = self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        inner.events_after_seq(last_id)
+    }
+}
+
+impl HistoryInner {
+    // Events after a `Last-Event-ID` sent by a client
+    fn events_after(&self, last_event_id: &str) -> Replay {
+        let last_id = last_event_id
+            .trim()
+            .rsplit_once('-')
+            .filter(|(boot_id, _)| *boot_id == self.boot_id)
+            .and_then(|(_, seq)| seq.parse::<u64>().ok());
+        match last_id {
+            Some(last_id) => self.events_after_seq(last_id),
+            // An id from a previous server run, or not one of ours
+            None => Replay::Resync {
+                latest_id: self.next_id - 1,
+            },
+        }
+    }
+
+    fn events_after_seq(&self, last_id: u64) -> Replay {
+        let latest_id = self.next_id - 1;
+        let oldest_id = self.events.front().map_or(self.next_id, |event| event.id);
+
+        // Ids that were never handed out, or events that have already been dropped
+        if last_id > latest_id || last_id + 1 < oldest_id {
+            return Replay::Resync { latest_id };
+        }
+
+        Replay::Events(
+            self.events
+                .iter()
+                .filter(|event| event.id > last_id)
+                .cloned()
+                .collect(),
+        )
+    }
+}
+
+// Copy every broadcast into the event history
+pub fn spawn_event_sequencer(state: &AppState) {
+    let mut broadcast_rx = state.broadcast_tx.subscribe();
+    let history = state.event_history.clone();
+
+    tokio::spawn(async move {
+        loop {
+            match broadcast_rx.recv().await {
+                Ok(message) => history.record(message),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Event history lagged behind, skipped {} broadcast messages", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventStreamParams {
+    // Alternative to the Last-Event-ID header for the first connection
+    last_event_id: Option<String>,
+}
+
+impl SequencedEvent {
+    fn visible_to(&self, profile: &str) -> bool {
+        self.message.profile().is_none_or(|owner| owner == profile)
+    }
+}
+
+fn to_sse_event(boot_id: &str, event: &SequencedEvent) -> Event {
+    Event::default()
+        .id(format!("{}-{}", boot_id, event.id))
+        .data(event.message.to_json())
+}
+
+fn resync_event(boot_id: &str, latest_id: u64) -> Event {
+    let message = ServerMessage::Resync {
+        reason: "Missed events are no longer available; reload state from the REST API".to_string(),
+    };
+    Event::default()
+        .id(format!("{}-{}", boot_id, latest_id))
+        .data(message.to_json())
+}
+
+// API handler
+pub async fn event_stream(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    headers: HeaderMap,
+    Query(params): Query<EventStreamParams>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .or(params.last_event_id);
+
+    let history = state.event_history.clone();
+    let boot_id = history.boot_id();
+    let (replay, last_sent, rx) = history.subscribe_after(last_event_id.as_deref());
+
+    let mut pending = VecDeque::new();
+    match replay {
+        Replay::Events(events) => {
+            pending.extend(
+                events
+                    .iter()
+                    .filter(|event| event.visible_to(&profile))
+                    .map(|event| to_sse_event(&boot_id, event)),
+            );
+        }
+        Replay::Resync { latest_id } => pending.push_back(resync_event(&boot_id, latest_id)),
+    }
+
+    info!("Event stream client connected (Last-Event-ID: {:?})", last_event_id);
+
+    let stream = futures_util::stream::unfold(
+        (history, boot_id, rx, pending, last_sent, profile),
+        |(history, boot_id, mut rx, mut pending, mut last_sent, profile)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    return Some((Ok(event), (history, boot_id, rx, pending, last_sent, profile)));
+                }
+
+                match rx.recv().await {
+                    Ok(event) => {
+                        // Skip anything already delivered as part of a replay
+                        if event.id > last_sent {
+                            last_sent = event.id;
+                            if event.visible_to(&profile) {
+                                pending.push_back(to_sse_event(&boot_id, &event));
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(_)) => {
+                        // Fill the gap from the history instead of dropping events
+                        match history.events_after_seq(last_sent) {
+                            Replay::Events(events) => {
+                                if let Some(last) = events.last() {
+                                    last_sent = last.id;
+                                }
+                                pending.extend(
+                                    events
+                                        .iter()
+                                        .filter(|event| event.visible_to(&profile))
+                                        .map(|event| to_sse_event(&boot_id, event)),
+                                );
+                            }
+                            Replay::Resync { latest_id } => {
+                                last_sent = latest_id;
+                                pending.push_back(resync_event(&boot_id, latest_id));
+                            }
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        },
+    );
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history_with(event_count: u64) -> HistoryInner {
+        let mut inner = HistoryInner {
+            boot_id: "boot".to_string(),
+            next_id: 1,
+            events: VecDeque::new(),
+        };
+        for id in 1..=event_count {
+            inner.events.push_back(Arc::new(SequencedEvent {
+                id,
+                message: ServerMessage::Resync {
+                    reason: format!("event {}", id),
+                },
+            }));
+            inner.next_id = id + 1;
+        }
+        inner
+    }
+
+    fn replayed_ids(replay: Replay) -> Vec<u64> {
+        match replay {
+            Replay::Events(events) => events.iter().map(|event| event.id).collect(),
+            Replay::Resync { latest_id } => panic!("expected a replay, got a resync at {}", latest_id),
+        }
+    }
+
+    #[test]
+    fn replays_the_events_after_the_last_id() {
+        let history = history_with(10);
+        assert_eq!(replayed_ids(history.events_after("boot-7")), vec![8, 9, 10]);
+        assert_eq!(replayed_ids(history.events_after("boot-10")), Vec::<u64>::new());
+    }
+
+    #[test]
+    fn ids_of_another_server_run_resync() {
+        let history = history_with(10);
+        // A client from before a restart that is behind the new run's ids
+        assert!(matches!(history.events_after("earlier-5"), Replay::Resync { latest_id: 10 }));
+        assert!(matches!(history.events_after("5"), Replay::Resync { latest_id: 10 }));
+        assert!(matches!(history.events_after("boot-11"), Replay::Resync { latest_id: 10 }));
+    }
+
+    #[test]
+    fn ids_older_than_the_history_resync() {
+        let mut history = history_with(10);
+        history.events.drain(..5);
+        assert!(matches!(history.events_after("boot-3"), Replay::Resync { latest_id: 10 }));
+        // The oldest kept event directly follows id 5
+        assert_eq!(replayed_ids(history.events_after("boot-5")), vec![6, 7, 8, 9, 10]);
+    }
+}
@@ -1,7 +1,7 @@
 use axum::{
     extract::{
-        ws::{Message, WebSocket, WebSocketUpgrade},
-        Path as AxumPath, Query, State,
+        ws::{close_code, CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Path as AxumPath, Query, State,
     },
     http::{header, HeaderValue, Method, StatusCode},
     response::{IntoResponse, Response},
@@ -14,24 +14,27 @@ use futures_util::{sink::SinkExt, stream::StreamExt};
 use notify::{RecommendedWatcher, RecursiveMode, Watcher};
 use portable_pty::{native_pty_system, CommandBuilder, PtySize};
 use regex::Regex;
-use reqwest;
 use scraper::{Html as ScraperHtml, Selector};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{BTreeMap, HashMap},
     env,
     io::{Read, Write},
     net::SocketAddr,
     path::PathBuf,
     process::Stdio,
-    sync::Arc,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 use tokio::{
     fs,
     process::Command,
-    sync::{broadcast, RwLock, Mutex},
+    sync::{broadcast, mpsc, RwLock, Mutex},
     time::timeout,
 };
 use tower::ServiceBuilder;
@@ -48,14 +51,53 @@ use walkdir::WalkDir;
 #[cfg(feature = "embed-assets")]
 use rust_embed::RustEmbed;
 
+mod achievements;
+mod activity;
+mod analytics;
+mod archive;
+mod chapter_stats;
+mod collab;
+mod error_codes;
+mod event_log;
+mod events;
+mod preferences;
+mod presence;
+mod profiles;
+mod progress_changes;
+mod progress_schema;
+mod progress_sqlite;
+mod progress_store;
+mod protocol;
+mod reconcile;
+mod recording;
+mod reset;
+mod sharing;
+mod streaks;
+mod test_runs;
+
+use event_log::{EventLog, LearnerEventKind};
+use events::EventHistory;
+use presence::ConnectionPresence;
+use preferences::UserPreferences;
+use profiles::{ActiveProfile, ProfileRegistry};
+use achievements::{Achievement, AchievementCatalog};
+use activity::{Activity, ActivitySession, ActivityTracker};
+use progress_store::{ProgressBackend, ProgressHandle, ProgressOptions};
+use streaks::StreakConfig;
+use test_runs::TestRun;
+use error_codes::ErrorCodeStats;
+use collab::SharedDocument;
+use protocol::{ClientMessage, CodeAction, CollabRequest, ErrorCode, ServerMessage, TerminalEvent, TerminalRequest};
+use recording::AsciicastRecorder;
+use reset::{ResetRecord, ResetScope};
+use sharing::{Scrollback, TerminalShare, ViewerSender};
+
 #[cfg(feature = "download-exercises")]
 use dialoguer::{Confirm, Input, Select};
 #[cfg(feature = "download-exercises")]
 use git2::Repository;
 #[cfg(feature = "download-exercises")]
 use tempfile::TempDir;
-#[cfg(feature = "download-exercises")]
-use open;
 
 #[cfg(feature = "embed-assets")]
 #[derive(RustEmbed)]
@@ -87,64 +129,253 @@ struct Cli {
     /// Custom path to exercises directory (for development)
     #[arg(long)]
     exercises_path: Option<PathBuf>,
+
+    /// Record integrated terminal sessions as asciicast files under the progress directory
+    #[arg(long, env = "RECORD_TERMINALS")]
+    record_terminals: bool,
+
+    /// Where progress is stored; an existing user_progress.json is migrated into SQLite on first start
+    #[arg(long, value_enum, default_value = "json", env = "PROGRESS_BACKEND")]
+    progress_backend: ProgressBackend,
+
+    /// Time zone that decides which day a completion counts for in streaks (IANA name, e.g. Europe/Berlin)
+    #[arg(long, default_value = "UTC", env = "STREAK_TIMEZONE")]
+    streak_timezone: chrono_tz::Tz,
+
+    /// Days in a row without a completed exercise that don't break a streak
+    #[arg(long, default_value = "0", env = "STREAK_GRACE_DAYS")]
+    streak_grace_days: u32,
+
+    /// JSON file with achievement definitions, replacing the built-in ones
+    #[arg(long, value_name = "PATH", env = "ACHIEVEMENTS_FILE")]
+    achievements_file: Option<PathBuf>,
+
+    /// Also accept completions posted by the browser (POST /api/progress/complete) instead of
+    /// only completing exercises whose tests pass
+    #[arg(long, env = "LEGACY_CLIENT_COMPLETION")]
+    legacy_client_completion: bool,
+
+    /// Seconds without interaction after which time stops counting as active
+    #[arg(long, value_name = "SECONDS", default_value_t = activity::DEFAULT_IDLE_THRESHOLD_SECS, env = "IDLE_THRESHOLD_SECS")]
+    idle_threshold_secs: u64,
+
+    /// Size at which the learner event log is rotated, in megabytes
+    #[arg(long, value_name = "MB", default_value_t = event_log::DEFAULT_EVENT_LOG_MAX_MB, env = "EVENT_LOG_MAX_MB")]
+    event_log_max_mb: u64,
+
+    #[command(subcommand)]
+    command: Option<CliCommand>,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum CliCommand {
+    /// Export a profile's progress (and edited sources) to a zip archive
+    ///
+    /// Takes the progress directory's lock, so it fails while a server uses the same directory
+    Export {
+        /// Archive to write [default: rust-tour-<profile>-<timestamp>.zip]
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Profile to export
+        #[arg(long, default_value = profiles::DEFAULT_PROFILE)]
+        profile: String,
+
+        /// Leave out the edited exercise source files
+        #[arg(long)]
+        no_sources: bool,
+
+        /// Include the rotating progress backups
+        #[arg(long)]
+        snapshots: bool,
+    },
+    /// Merge an exported archive into a profile's progress
+    ///
+    /// Takes the progress directory's lock, so it fails while a server uses the same directory
+    Import {
+        /// Archive created by `rust-tour export` or /api/progress/export
+        archive: PathBuf,
+
+        /// Profile to import into
+        #[arg(long, default_value = profiles::DEFAULT_PROFILE)]
+        profile: String,
+
+        /// Only import progress, not source files
+        #[arg(long)]
+        no_sources: bool,
+
+        /// Show what would change without changing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Clear progress for one exercise, a chapter or the whole profile
+    ///
+    /// Takes the progress directory's lock, so it fails while a server uses the same directory
+    #[command(group(clap::ArgGroup::new("scope").required(true).args(["exercise", "chapter", "all"])))]
+    Reset {
+        /// Exercise id or chapter/exercise path
+        #[arg(long)]
+        exercise: Option<String>,
+
+        /// Chapter number
+        #[arg(long)]
+        chapter: Option<u32>,
+
+        /// Every exercise, plus achievements and activity sessions
+        #[arg(long)]
+        all: bool,
+
+        /// Also put back the exercises' original source files
+        #[arg(long)]
+        restore_sources: bool,
+
+        /// Profile to reset
+        #[arg(long, default_value = profiles::DEFAULT_PROFILE)]
+        profile: String,
+    },
+    /// Run the tests of edited exercises and mark the passing ones completed
+    ///
+    /// Takes the progress directory's lock, so it fails while a server uses the same directory
+    Reconcile {
+        /// Profile to reconcile
+        #[arg(long, default_value = profiles::DEFAULT_PROFILE)]
+        profile: String,
+
+        /// Exercises tested at the same time [default: half the CPU cores]
+        #[arg(short, long)]
+        jobs: Option<usize>,
+    },
+    /// Rebuild a profile's progress from its learner event log
+    ///
+    /// Only reads the event log, so it can run while a server uses the same directory
+    Replay {
+        /// Profile to replay
+        #[arg(long, default_value = profiles::DEFAULT_PROFILE)]
+        profile: String,
+
+        /// Write the rebuilt progress to this JSON file
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
 }
 
 // Application state
 #[derive(Clone)]
 struct AppState {
-    connections: Arc<RwLock<HashSet<ConnectionId>>>,
+    connections: Arc<RwLock<HashMap<ConnectionId, ConnectionHandle>>>,
     terminal_sessions: Arc<RwLock<HashMap<String, TerminalSession>>>,
     pty_handles: Arc<RwLock<HashMap<String, PtyHandle>>>,
-    broadcast_tx: broadcast::Sender<BroadcastMessage>,
+    terminal_shares: Arc<RwLock<HashMap<String, TerminalShare>>>,
+    collab_documents: Arc<RwLock<HashMap<String, SharedDocument>>>,
+    broadcast_tx: broadcast::Sender<ServerMessage>,
+    event_history: EventHistory,
     debug_websocket: bool,
+    record_terminals: bool,
     exercises_path: PathBuf,
     progress_path: PathBuf,
+    profiles: ProfileRegistry,
+    activity: ActivityTracker,
+    // Accept completions posted by the browser
+    legacy_client_completion: bool,
 }
 
 type ConnectionId = Uuid;
 
+// Per-connection outbound queue, used for messages addressed to a single client
+#[derive(Debug, Clone)]
+struct ConnectionHandle {
+    sender: mpsc::Sender<Message>,
+    presence: ConnectionPresence,
+}
+
+// The server pings every connection and drops those that stay silent, so
+// half-open sockets don't keep their terminals alive
+const PING_INTERVAL: Duration = Duration::from_secs(15);
+const PONG_TIMEOUT: Duration = Duration::from_secs(45);
+
+// Bounded queues between the PTY, the session task and the socket, so a client
+// that can't keep up pauses the shell instead of growing memory without limit
+const CONNECTION_QUEUE_SIZE: usize = 64;
+const TERMINAL_READ_QUEUE_SIZE: usize = 32;
+const TERMINAL_INPUT_QUEUE_SIZE: usize = 256;
+const TERMINAL_READ_BUFFER_BYTES: usize = 8 * 1024;
+const MAX_TERMINAL_FRAME_BYTES: usize = 64 * 1024;
+
+// Binary terminal frames: [kind][session id length][session id][raw PTY bytes]
+const BINARY_FRAME_TERMINAL_OUTPUT: u8 = 0x01;
+
 #[derive(Debug, Clone)]
 struct TerminalSession {
     session_id: String,
     connection_id: ConnectionId,
+    // Deliver output as binary frames instead of JSON text messages
+    binary_output: bool,
 }
 
 // Separate struct for actual PTY handles (not Clone/Send)
 struct PtyHandle {
-    writer: Arc<Mutex<Box<dyn std::io::Write + Send>>>,
+    // Input is queued in order and written by a dedicated thread
+    input_tx: mpsc::Sender<Vec<u8>>,
     child: Arc<Mutex<Box<dyn portable_pty::Child + Send + Sync>>>,
     master: Arc<Mutex<Box<dyn portable_pty::MasterPty + Send>>>,
-}
-
-#[derive(Debug, Clone, Serialize)]
-struct BroadcastMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    #[serde(flatten)]
-    data: serde_json::Value,
-}
+    recorder: Option<Arc<std::sync::Mutex<AsciicastRecorder>>>,
+    // Recent output and a lossy feed of new output for read-only viewers
+    scrollback: Arc<std::sync::Mutex<Scrollback>>,
+    viewer_tx: ViewerSender,
+}
+
+// Incremental UTF-8 decoder for PTY output. Multi-byte characters split across
+// reads are held back until the rest of the sequence arrives.
+#[derive(Debug, Default)]
+struct Utf8StreamDecoder {
+    pending: Vec<u8>,
+}
+
+impl Utf8StreamDecoder {
+    fn decode(&mut self, bytes: &[u8]) -> String {
+        self.pending.extend_from_slice(bytes);
+        let mut output = String::with_capacity(self.pending.len());
+        let mut rest: &[u8] = &self.pending;
+
+        loop {
+            match std::str::from_utf8(rest) {
+                Ok(valid) => {
+                    output.push_str(valid);
+                    rest = &[];
+                    break;
+                }
+                Err(e) => {
+                    let (valid, after) = rest.split_at(e.valid_up_to());
+                    // Safe: from_utf8 just validated this prefix
+                    output.push_str(std::str::from_utf8(valid).unwrap_or_default());
+                    match e.error_len() {
+                        Some(len) => {
+                            output.push(char::REPLACEMENT_CHARACTER);
+                            rest = &after[len..];
+                        }
+                        None => {
+                            // Incomplete sequence at the end, wait for more bytes
+                            rest = after;
+                            break;
+                        }
+                    }
+                }
+            }
+        }
 
-// WebSocket message types
-#[derive(Debug, Deserialize)]
-struct WebSocketMessage {
-    #[serde(rename = "type")]
-    msg_type: String,
-    #[serde(flatten)]
-    data: serde_json::Value,
-}
+        self.pending = rest.to_vec();
+        output
+    }
 
-#[derive(Debug, Deserialize)]
-struct TerminalMessage {
-    action: String,
-    #[serde(rename = "sessionId")]
-    session_id: Option<String>,
-    input: Option<String>,
-    cols: Option<u16>,
-    rows: Option<u16>,
+    fn finish(&mut self) -> String {
+        let remainder = String::from_utf8_lossy(&self.pending).to_string();
+        self.pending.clear();
+        remainder
+    }
 }
 
 // API response types
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize, JsonSchema)]
 struct CargoResult {
     success: bool,
     code: Option<i32>,
@@ -201,8 +432,10 @@ struct ExerciseDetails {
     files: Vec<FileContent>,  // New field for all files
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
 struct ProgressData {
+    // See `progress_schema`; stored progress is upgraded before it's deserialized
+    schema_version: u32,
     user_id: String,
     #[serde(default = "default_created_at")]
     created_at: String,
@@ -225,12 +458,20 @@ struct ProgressData {
     #[serde(default)]
     exercise_history: Vec<ExerciseHistoryEntry>,
     #[serde(default)]
-    achievements: Vec<serde_json::Value>,
+    achievements: Vec<Achievement>,
+    #[serde(default)]
+    activity_sessions: Vec<ActivitySession>,
     #[serde(default)]
     session_stats: SessionStats,
+    // Audit trail of progress resets, see `reset`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    resets: Vec<ResetRecord>,
+    // See `preferences`
+    #[serde(default)]
+    preferences: UserPreferences,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, JsonSchema)]
 struct ExerciseHistoryEntry {
     exercise_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -244,9 +485,47 @@ struct ExerciseHistoryEntry {
     session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     hints_used: Option<Vec<u32>>,
+    // Active time measured by the server, see `activity`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    active_seconds: Option<u64>,
+    // The most recent `cargo test` runs, see `test_runs`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    test_runs: Vec<TestRun>,
+    // Every run counted, including the ones no longer in `test_runs`
+    #[serde(default, skip_serializing_if = "is_zero")]
+    test_run_count: u32,
+    #[serde(default, skip_serializing_if = "is_zero")]
+    failed_test_runs: u32,
+    // Occurrences of each rustc and clippy error code, see `error_codes`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    error_codes: BTreeMap<String, ErrorCodeStats>,
+    // Test runs it took to complete the exercise, and how many of them failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    test_failures: Option<u32>,
+    // Missing for completions from before this was recorded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    completed_by: Option<CompletionSource>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+// How an exercise came to be marked completed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+enum CompletionSource {
+    // The browser reported it (`--legacy-client-completion`)
+    Client,
+    // A test run started from the browser passed every test
+    Tests,
+    // Its tests passed when progress was reconciled with the exercises on disk
+    Reconcile,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
 struct SessionStats {
     #[serde(default)]
     exercises_viewed: u32,
@@ -271,17 +550,6 @@ fn default_chapters() -> serde_json::Value {
     serde_json::Value::Object(serde_json::Map::new())
 }
 
-impl Default for SessionStats {
-    fn default() -> Self {
-        Self {
-            exercises_viewed: 0,
-            exercises_completed: 0,
-            hints_used: 0,
-            time_spent: 0,
-        }
-    }
-}
-
 // Helper trait for string case conversion
 trait ToTitleCase {
     fn to_title_case(&self) -> String;
@@ -302,14 +570,25 @@ impl ToTitleCase for str {
     }
 }
 
-#[derive(Debug, Serialize)]
+// Statistics are filled in from the history by `chapter_stats`
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ChapterInfo {
     chapter_number: u32,
     title: String,
+    #[serde(default)]
     exercises_completed: u32,
     total_exercises: u32,
+    #[serde(default)]
     completion_percentage: f64,
+    #[serde(default)]
     time_spent_minutes: u32,
+    #[serde(default)]
+    first_started: Option<String>,
+    #[serde(default)]
+    completed_at: Option<String>,
+    // Numbers of the exercises on disk, so history for removed ones is left out
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    exercises: Vec<u32>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -349,6 +628,9 @@ struct HintRequest {
 #[derive(Debug, Deserialize)]
 struct ViewRequest {
     exercise_id: String,
+    // WebSocket connection of the tab that opened the exercise, for presence
+    #[serde(default)]
+    connection_id: Option<ConnectionId>,
 }
 
 #[derive(Debug, Serialize)]
@@ -394,16 +676,6 @@ impl<T> ApiResponse<T> {
             extra: Some(extra),
         }
     }
-
-    fn error(message: String) -> ApiResponse<()> {
-        ApiResponse {
-            success: Some(false),
-            message: None,
-            error: Some(message),
-            data: None,
-            extra: None,
-        }
-    }
 }
 
 #[tokio::main]
@@ -442,25 +714,63 @@ async fn main() -> anyhow::Result<()> {
         .unwrap_or_else(|| std::path::Path::new("."))
         .join("progress")
         .join("user_progress.json");
+    let progress_storage_path = match cli.progress_backend {
+        ProgressBackend::Json => progress_path.clone(),
+        ProgressBackend::Sqlite => progress_store::sqlite_path(&progress_path),
+    };
         
-    println!("📊 Progress file: {}", progress_path.display());
+    println!("📊 Progress file: {}", progress_storage_path.display());
+
+    // Load the default profile's progress and start the task that owns it
+    let achievements = AchievementCatalog::load(cli.achievements_file.as_deref())?;
+    info!("🏆 Loaded {} achievement definitions", achievements.definitions().len());
 
     // Create broadcast channel for WebSocket messages
     let (broadcast_tx, _) = broadcast::channel(100);
 
+    let progress_options = ProgressOptions {
+        backend: cli.progress_backend,
+        streaks: StreakConfig {
+            timezone: cli.streak_timezone,
+            grace_days: cli.streak_grace_days,
+        },
+        achievements: Arc::new(achievements),
+        broadcast_tx: broadcast_tx.clone(),
+        event_log: EventLog::new(cli.event_log_max_mb),
+    };
+    let profiles = match ProfileRegistry::open(progress_options, progress_path.clone(), exercises_path.clone()).await {
+        Ok(profiles) => profiles,
+        Err(e) => {
+            error!("Failed to initialize progress system: {}", e);
+            return Err(e);
+        }
+    };
+    info!("📊 Progress system initialized");
+
+    if let Some(command) = cli.command {
+        return run_cli_command(command, &profiles).await;
+    }
+
     // Initialize application state
     let state = AppState {
-        connections: Arc::new(RwLock::new(HashSet::new())),
+        connections: Arc::new(RwLock::new(HashMap::new())),
         terminal_sessions: Arc::new(RwLock::new(HashMap::new())),
         pty_handles: Arc::new(RwLock::new(HashMap::new())),
+        terminal_shares: Arc::new(RwLock::new(HashMap::new())),
+        collab_documents: Arc::new(RwLock::new(HashMap::new())),
         broadcast_tx: broadcast_tx.clone(),
+        event_history: EventHistory::new(),
         debug_websocket,
+        record_terminals: cli.record_terminals,
         exercises_path: exercises_path.clone(),
         progress_path: progress_path.clone(),
+        profiles,
+        activity: ActivityTracker::new(Duration::from_secs(cli.idle_threshold_secs)),
+        legacy_client_completion: cli.legacy_client_completion,
     };
 
-    // Initialize progress system
-    initialize_progress_system(&state).await?;
+    // Number broadcasts for the Server-Sent Events stream
+    events::spawn_event_sequencer(&state);
 
     // Set up file watching
     setup_file_watcher(state.clone()).await?;
@@ -476,10 +786,14 @@ async fn main() -> anyhow::Result<()> {
     println!();
     println!("  🌐 Web interface:    http://localhost:{}", port);
     println!("  📡 WebSocket:        ws://localhost:{}/ws", port);
+    println!("  📨 Event stream:     http://localhost:{}/api/events", port);
     println!("  🩺 Health check:     http://localhost:{}/health", port);
     println!();
     println!("  📚 Exercises path:   {}", exercises_path.display());
-    println!("  💾 Progress path:    {}", progress_path.display());
+    println!("  💾 Progress path:    {}", progress_storage_path.display());
+    if state.record_terminals {
+        println!("  🎬 Recordings path:  {}", recording::recordings_dir(&state).display());
+    }
     println!();
     println!("  Press Ctrl+C to stop the server");
     println!("━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━━");
@@ -504,6 +818,48 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+// Subcommands that work on the progress directory instead of starting the server
+async fn run_cli_command(command: CliCommand, profiles: &ProfileRegistry) -> anyhow::Result<()> {
+    match command {
+        CliCommand::Export { output, profile, no_sources, snapshots } => {
+            ensure_profile_exists(profiles, &profile)?;
+            let options = archive::ExportOptions {
+                sources: !no_sources,
+                snapshots,
+            };
+            archive::export_command(profiles, &profile, output, options).await
+        }
+        CliCommand::Import { archive, profile, no_sources, dry_run } => {
+            ensure_profile_exists(profiles, &profile)?;
+            archive::import_command(profiles, &profile, &archive, !no_sources, dry_run).await
+        }
+        CliCommand::Reset { exercise, chapter, all: _, restore_sources, profile } => {
+            ensure_profile_exists(profiles, &profile)?;
+            let scope = match (exercise, chapter) {
+                (Some(exercise_id), _) => ResetScope::Exercise { exercise_id },
+                (None, Some(chapter)) => ResetScope::Chapter { chapter },
+                (None, None) => ResetScope::All,
+            };
+            reset::reset_command(profiles, &profile, scope, restore_sources).await
+        }
+        CliCommand::Reconcile { profile, jobs } => {
+            ensure_profile_exists(profiles, &profile)?;
+            reconcile::reconcile_command(profiles, &profile, jobs.unwrap_or_else(reconcile::default_jobs)).await
+        }
+        CliCommand::Replay { profile, output } => {
+            ensure_profile_exists(profiles, &profile)?;
+            event_log::replay_command(profiles, &profile, output).await
+        }
+    }
+}
+
+fn ensure_profile_exists(profiles: &ProfileRegistry, profile: &str) -> anyhow::Result<()> {
+    if !profiles::is_valid_profile_name(profile) || !profiles.exists(profile) {
+        anyhow::bail!("Unknown profile: {}", profile);
+    }
+    Ok(())
+}
+
 // Health check handler
 async fn health_check() -> Json<serde_json::Value> {
     Json(serde_json::json!({
@@ -513,6 +869,11 @@ async fn health_check() -> Json<serde_json::Value> {
     }))
 }
 
+// JSON Schema of the WebSocket protocol, for client authors and validation
+async fn get_protocol_schema() -> Json<serde_json::Value> {
+    Json(protocol::protocol_schema())
+}
+
 fn create_router(state: AppState) -> Router {
     Router::new()
         // Health check route
@@ -535,8 +896,30 @@ fn create_router(state: AppState) -> Router {
         .route("/api/progress/complete", post(complete_exercise))
         .route("/api/progress/hint", post(track_hint_usage))
         .route("/api/progress/view", post(track_exercise_view))
+        .route("/api/progress/reset", post(reset::reset_progress))
+        .route("/api/progress/reconcile", post(reconcile::reconcile_progress))
+        .route("/api/progress/export", get(archive::export_progress))
+        .route(
+            "/api/progress/import",
+            post(archive::import_progress).layer(DefaultBodyLimit::max(archive::ARCHIVE_UPLOAD_LIMIT)),
+        )
+        .route(
+            "/api/progress/import/preview",
+            post(archive::preview_progress_import).layer(DefaultBodyLimit::max(archive::ARCHIVE_UPLOAD_LIMIT)),
+        )
+        .route("/api/analytics", get(analytics::get_analytics))
+        .route("/api/analytics/errors", get(error_codes::get_error_report))
+        .route("/api/preferences", get(preferences::get_preferences).put(preferences::update_preferences))
+        .route("/api/profiles", get(profiles::list_profiles).post(profiles::create_profile))
+        .route("/api/profiles/switch", post(profiles::switch_profile))
         .route("/api/book/:chapter", get(get_book_chapter))
         .route("/api/book/fetch", get(get_book_by_url))
+        .route("/api/protocol/schema", get(get_protocol_schema))
+        .route("/api/events", get(events::event_stream))
+        .route("/api/presence", get(presence::list_presence))
+        .route("/api/recordings", get(recording::list_recordings))
+        .route("/api/recordings/:id", get(recording::download_recording))
+        .route("/api/recordings/:id/replay", get(recording::replay_recording))
         
         // Static file routes
         .fallback(serve_static_files)
@@ -556,56 +939,126 @@ fn create_router(state: AppState) -> Router {
         .with_state(state)
 }
 
+#[derive(Debug, Deserialize)]
+struct WebSocketParams {
+    // Viewer token for read-only access to a shared terminal session
+    view: Option<String>,
+}
+
 // WebSocket handlers
 async fn websocket_handler(
     ws: WebSocketUpgrade,
+    Query(params): Query<WebSocketParams>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
 ) -> Response {
-    ws.on_upgrade(|socket| websocket_connection(socket, state))
+    if let Some(token) = params.view {
+        return match sharing::lookup_share(&state, &token).await {
+            Some(share) => ws.on_upgrade(|socket| sharing::viewer_connection(socket, state, share)),
+            None => StatusCode::FORBIDDEN.into_response(),
+        };
+    }
+    
+    ws.on_upgrade(|socket| websocket_connection(socket, state, profile))
 }
 
-async fn websocket_connection(socket: WebSocket, state: AppState) {
+async fn websocket_connection(socket: WebSocket, state: AppState, profile: String) {
     let connection_id = Uuid::new_v4();
+    let (outbound_tx, mut outbound_rx) = mpsc::channel::<Message>(CONNECTION_QUEUE_SIZE);
+    let ping_tx = outbound_tx.clone();
     
     // Add connection to state
     {
         let mut connections = state.connections.write().await;
-        connections.insert(connection_id);
+        connections.insert(
+            connection_id,
+            ConnectionHandle {
+                sender: outbound_tx,
+                presence: ConnectionPresence::new(profile.clone()),
+            },
+        );
     }
     
     info!("Client connected to WebSocket: {}", connection_id);
     
+    // Greet the client with the protocol versions this server understands
+    let hello = ServerMessage::Hello {
+        protocol_version: protocol::PROTOCOL_VERSION,
+        min_protocol_version: protocol::MIN_PROTOCOL_VERSION,
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        connection_id: connection_id.to_string(),
+    };
+    send_message(&state, connection_id, &hello).await;
+    
     let mut broadcast_rx = state.broadcast_tx.subscribe();
     let (mut sender, mut receiver) = socket.split();
     
-    // Spawn task to handle broadcast messages
-    let _broadcast_state = state.clone();
+    // Spawn task to forward broadcast messages and messages addressed to this connection
     let broadcast_task = tokio::spawn(async move {
-        while let Ok(msg) = broadcast_rx.recv().await {
-            if let Ok(json) = serde_json::to_string(&msg) {
-                if sender.send(Message::Text(json)).await.is_err() {
-                    break;
-                }
+        loop {
+            let message = tokio::select! {
+                direct = outbound_rx.recv() => match direct {
+                    Some(message) => message,
+                    None => break,
+                },
+                broadcast = broadcast_rx.recv() => match broadcast {
+                    // Progress belongs to one profile; don't show it to the others
+                    Ok(msg) if msg.profile().is_some_and(|owner| owner != profile) => continue,
+                    Ok(msg) => Message::Text(msg.to_json()),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket {} lagged behind, skipped {} broadcast messages", connection_id, skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            };
+            
+            if sender.send(message).await.is_err() {
+                break;
             }
         }
     });
     
-    // Handle incoming messages
-    while let Some(msg) = receiver.next().await {
-        match msg {
-            Ok(Message::Text(text)) => {
-                if let Err(e) = handle_websocket_message(text, &state, connection_id).await {
-                    error!("Error handling WebSocket message: {}", e);
+    // Handle incoming messages, pinging the client while it is quiet
+    let mut liveness_check = tokio::time::interval(PING_INTERVAL);
+    liveness_check.tick().await;
+    let mut last_seen = Instant::now();
+    
+    loop {
+        tokio::select! {
+            msg = receiver.next() => {
+                let Some(msg) = msg else {
+                    break;
+                };
+                last_seen = Instant::now();
+                
+                match msg {
+                    Ok(Message::Text(text)) => {
+                        presence::record_activity(&state, connection_id).await;
+                        if let Err(e) = handle_websocket_message(text, &state, connection_id).await {
+                            error!("Error handling WebSocket message: {}", e);
+                        }
+                    }
+                    Ok(Message::Close(_)) => {
+                        break;
+                    }
+                    Err(e) => {
+                        error!("WebSocket error: {}", e);
+                        break;
+                    }
+                    _ => {
+                        presence::record_seen(&state, connection_id).await;
+                    }
                 }
             }
-            Ok(Message::Close(_)) => {
-                break;
-            }
-            Err(e) => {
-                error!("WebSocket error: {}", e);
-                break;
+            _ = liveness_check.tick() => {
+                if last_seen.elapsed() > PONG_TIMEOUT {
+                    warn!("WebSocket {} missed pongs for {:?}, dropping connection", connection_id, PONG_TIMEOUT);
+                    break;
+                }
+                // Don't wait on a full queue: a stuck writer is exactly what this detects
+                let _ = ping_tx.try_send(Message::Ping(Vec::new()));
             }
-            _ => {}
         }
     }
     
@@ -616,7 +1069,13 @@ async fn websocket_connection(socket: WebSocket, state: AppState) {
     }
     
     // Clean up terminal sessions for this connection
-    cleanup_terminal_sessions(&state, connection_id).await;
+    if let Err(e) = cleanup_terminal_sessions(&state, connection_id).await {
+        error!("Error cleaning up terminal sessions for {}: {}", connection_id, e);
+    }
+    
+    if let Err(e) = collab::leave_all(&state, connection_id).await {
+        error!("Error leaving collaborative documents for {}: {}", connection_id, e);
+    }
     
     broadcast_task.abort();
     info!("Client disconnected from WebSocket: {}", connection_id);
@@ -627,118 +1086,327 @@ async fn handle_websocket_message(
     state: &AppState,
     connection_id: ConnectionId,
 ) -> anyhow::Result<()> {
-    let message: WebSocketMessage = serde_json::from_str(&text)?;
+    let message = match protocol::parse_client_message(&text) {
+        Ok(message) => message,
+        Err(error) => {
+            warn!("Rejected WebSocket message from {}: {}", connection_id, error.to_json());
+            send_message(state, connection_id, &error).await;
+            return Ok(());
+        }
+    };
     
     if state.debug_websocket {
-        debug!("Received WebSocket message: {}", message.msg_type);
+        debug!("Received WebSocket message: {}", message.type_name());
     }
     
-    match message.msg_type.as_str() {
-        "terminal" => {
-            let terminal_msg: TerminalMessage = serde_json::from_value(message.data)?;
-            handle_terminal_message(state, connection_id, terminal_msg).await?;
+    match message {
+        ClientMessage::Hello { protocol_version, client } => {
+            handle_hello_message(state, connection_id, protocol_version, client).await?;
         }
-        "heartbeat" => {
-            handle_heartbeat_message(state, connection_id, &message).await?;
+        ClientMessage::Terminal(request) => {
+            handle_terminal_message(state, connection_id, request).await?;
         }
-        "exercise_view" => {
-            // Handle exercise view tracking
-            if state.debug_websocket {
-                debug!("Exercise view from connection {}", connection_id);
+        ClientMessage::Collab(request) => {
+            if matches!(request, CollabRequest::Operation { .. }) {
+                record_connection_activity(state, connection_id, Activity::Interaction).await;
             }
+            handle_collab_message(state, connection_id, request).await?;
         }
-        "code_execution" => {
-            // Handle code execution events
-            if state.debug_websocket {
-                debug!("Code execution from connection {}", connection_id);
-            }
+        ClientMessage::Heartbeat { timestamp } => {
+            record_connection_activity(state, connection_id, Activity::Heartbeat).await;
+            handle_heartbeat_message(state, connection_id, timestamp).await?;
         }
-        "progress_update" => {
-            // Handle progress updates
-            if state.debug_websocket {
-                debug!("Progress update from connection {}", connection_id);
-            }
+        ClientMessage::ExerciseView { exercise_id } => {
+            record_connection_activity(state, connection_id, Activity::View(exercise_id.clone())).await;
+            handle_exercise_view_message(state, connection_id, exercise_id).await?;
         }
-        _ => {
-            warn!("Unknown WebSocket message type: {}", message.msg_type);
+        ClientMessage::CodeExecution { exercise_id, action } => {
+            record_connection_activity(state, connection_id, Activity::Interaction).await;
+            handle_code_execution_message(state, connection_id, exercise_id, action).await?;
+        }
+        ClientMessage::ProgressUpdate { exercise_id } => {
+            handle_progress_update_message(state, connection_id, exercise_id).await?;
         }
     }
     
     Ok(())
 }
 
+// Feed the active time tracker on behalf of a WebSocket connection
+async fn record_connection_activity(state: &AppState, connection_id: ConnectionId, activity: Activity) {
+    let profile = profiles::connection_profile(state, connection_id).await;
+    state.activity.record(&state.profiles, &profile, activity).await;
+}
+
+// Append to the profile's learner event log, in its current activity session
+async fn log_learner_event(state: &AppState, profile: &str, event: LearnerEventKind) {
+    let session_id = state.activity.session_id(profile);
+    event_log::append_event(&state.profiles, profile, session_id, event).await;
+}
+
+async fn handle_hello_message(
+    state: &AppState,
+    connection_id: ConnectionId,
+    protocol_version: u32,
+    client: Option<String>,
+) -> anyhow::Result<()> {
+    if !protocol::is_supported_version(protocol_version) {
+        warn!(
+            "Closing WebSocket {}: client speaks protocol {}, server supports {}..={}",
+            connection_id,
+            protocol_version,
+            protocol::MIN_PROTOCOL_VERSION,
+            protocol::PROTOCOL_VERSION
+        );
+        let error = ServerMessage::error(
+            ErrorCode::UnsupportedProtocolVersion,
+            format!(
+                "Protocol version {} is not supported; this server supports versions {} to {}",
+                protocol_version,
+                protocol::MIN_PROTOCOL_VERSION,
+                protocol::PROTOCOL_VERSION
+            ),
+            Some("hello"),
+        );
+        send_message(state, connection_id, &error).await;
+        send_to_connection(
+            state,
+            connection_id,
+            Message::Close(Some(CloseFrame {
+                code: close_code::PROTOCOL,
+                reason: "unsupported protocol version".into(),
+            })),
+        )
+        .await;
+        return Ok(());
+    }
+    
+    if state.debug_websocket {
+        debug!(
+            "Connection {} speaks protocol {} ({})",
+            connection_id,
+            protocol_version,
+            client.as_deref().unwrap_or("unknown client")
+        );
+    }
+    
+    presence::record_hello(state, connection_id, protocol_version, client).await;
+    send_message(state, connection_id, &ServerMessage::HelloAck { protocol_version }).await;
+    Ok(())
+}
+
 async fn handle_heartbeat_message(
     state: &AppState,
     connection_id: ConnectionId,
-    message: &WebSocketMessage,
+    timestamp: Option<u64>,
 ) -> anyhow::Result<()> {
     if state.debug_websocket {
         debug!("Received heartbeat from connection {}", connection_id);
     }
     
-    // Extract timestamp from heartbeat if available
-    let timestamp = message.data.get("timestamp")
-        .and_then(|v| v.as_u64())
-        .unwrap_or_else(|| std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .unwrap()
-            .as_millis() as u64
-        );
+    let server_time = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_millis() as u64;
     
-    // Send heartbeat response (pong) back to client via broadcast
-    let response = BroadcastMessage {
-        msg_type: "heartbeat_response".to_string(),
-        data: serde_json::json!({
-            "timestamp": timestamp,
-            "server_time": std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_millis() as u64
-        }),
+    // Answer only the client that sent the heartbeat
+    let response = ServerMessage::HeartbeatResponse {
+        timestamp: timestamp.unwrap_or(server_time),
+        server_time,
     };
+    send_message(state, connection_id, &response).await;
+    
+    Ok(())
+}
+
+async fn handle_exercise_view_message(
+    state: &AppState,
+    connection_id: ConnectionId,
+    exercise_id: String,
+) -> anyhow::Result<()> {
+    presence::record_exercise_view(state, connection_id, &exercise_id).await;
+    
+    let profile = profiles::connection_profile(state, connection_id).await;
+    let result = match state.profiles.progress(&profile).await {
+        Ok(progress) => update_exercise_view(&progress, exercise_id.clone()).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(progress) => {
+            log_learner_event(state, &profile, LearnerEventKind::ExerciseViewed { exercise_id: exercise_id.clone() }).await;
+            let _ = state.broadcast_tx.send(ServerMessage::ProgressUpdated {
+                profile,
+                exercise_id: Some(exercise_id),
+                progress: Box::new(progress),
+            });
+        }
+        Err(e) => {
+            error!("Error tracking exercise view over WebSocket: {}", e);
+            let error = ServerMessage::error(ErrorCode::Internal, "Failed to record exercise view", Some("exercise_view"));
+            send_message(state, connection_id, &error).await;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_code_execution_message(
+    state: &AppState,
+    connection_id: ConnectionId,
+    exercise_id: String,
+    action: CodeAction,
+) -> anyhow::Result<()> {
+    let profile = profiles::connection_profile(state, connection_id).await;
+    let Some(exercise_path) = resolve_exercise_path(state, &profile, &exercise_id).await else {
+        let error = ServerMessage::error(
+            ErrorCode::InvalidExercise,
+            format!("Unknown exercise: {}", exercise_id),
+            Some("code_execution"),
+        );
+        send_message(state, connection_id, &error).await;
+        return Ok(());
+    };
+    
+    // Cargo can take a while, so run it without blocking this connection's other messages
+    let state = state.clone();
+    tokio::spawn(async move {
+        let (command, args) = match action {
+            CodeAction::Test => ("test", vec!["--", "--nocapture"]),
+            CodeAction::Run => ("run", vec![]),
+            CodeAction::Check => ("clippy", vec!["--", "-W", "clippy::all"]),
+        };
+        
+        let started = Instant::now();
+        let response = match run_cargo_command(command, &exercise_path, args).await {
+            Ok(result) => {
+                if action == CodeAction::Test {
+                    test_runs::record_test_run(&state, &profile, &exercise_id, &result, started.elapsed()).await;
+                } else {
+                    let event = LearnerEventKind::CodeRun {
+                        exercise_id: exercise_id.clone(),
+                        action,
+                        success: result.success,
+                    };
+                    log_learner_event(&state, &profile, event).await;
+                }
+                error_codes::record_error_codes(&state, &profile, &exercise_id, action, &result).await;
+                ServerMessage::CodeExecutionResult {
+                    exercise_id,
+                    action,
+                    result,
+                }
+            }
+            Err(e) => {
+                error!("Error running cargo {} for {}: {}", command, exercise_id, e);
+                ServerMessage::error(
+                    ErrorCode::ExecutionFailed,
+                    format!("Failed to run cargo {}: {}", command, e),
+                    Some("code_execution"),
+                )
+            }
+        };
+        send_message(&state, connection_id, &response).await;
+    });
     
-    // Send response via broadcast system
-    let _ = state.broadcast_tx.send(response);
+    Ok(())
+}
+
+async fn handle_progress_update_message(
+    state: &AppState,
+    connection_id: ConnectionId,
+    exercise_id: Option<String>,
+) -> anyhow::Result<()> {
+    let profile = profiles::connection_profile(state, connection_id).await;
+    let result = match state.profiles.progress(&profile).await {
+        Ok(progress) => progress.snapshot().await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(progress) => {
+            let _ = state.broadcast_tx.send(ServerMessage::ProgressUpdated {
+                profile,
+                exercise_id,
+                progress: Box::new(progress),
+            });
+        }
+        Err(e) => {
+            error!("Error loading progress for WebSocket update: {}", e);
+            let error = ServerMessage::error(ErrorCode::Internal, "Failed to load progress", Some("progress_update"));
+            send_message(state, connection_id, &error).await;
+        }
+    }
+    Ok(())
+}
+
+async fn handle_collab_message(
+    state: &AppState,
+    connection_id: ConnectionId,
+    request: CollabRequest,
+) -> anyhow::Result<()> {
+    match request {
+        CollabRequest::Join { exercise_id, file } => {
+            collab::join(state, connection_id, exercise_id, file).await?;
+        }
+        CollabRequest::Leave { document_id } => {
+            collab::leave(state, connection_id, &document_id).await?;
+        }
+        CollabRequest::Operation { document_id, revision, operation } => {
+            collab::receive_operation(state, connection_id, document_id, revision, operation).await?;
+        }
+        CollabRequest::Selection { document_id, revision, ranges } => {
+            collab::update_selection(state, connection_id, document_id, revision, ranges).await?;
+        }
+    }
     
     Ok(())
 }
 
+// Look up an exercise by id ("ch01-ex01-hello-world") or path ("ch01_getting_started/ex01_hello_world")
+async fn find_exercise(state: &AppState, exercise_id: &str) -> Option<ExerciseWithPath> {
+    let exercises = scan_exercises(&state.exercises_path).await.ok()?;
+    exercises
+        .into_iter()
+        .find(|exercise| exercise.metadata.id == exercise_id || exercise.path == exercise_id)
+}
+
+// Directory of an exercise as the profile sees it (its own copy once it has edited it)
+async fn resolve_exercise_path(state: &AppState, profile: &str, exercise_id: &str) -> Option<PathBuf> {
+    find_exercise(state, exercise_id)
+        .await
+        .map(|exercise| state.profiles.exercise_dir(profile, &exercise.path))
+}
+
 async fn handle_terminal_message(
     state: &AppState,
     connection_id: ConnectionId,
-    msg: TerminalMessage,
+    request: TerminalRequest,
 ) -> anyhow::Result<()> {
     if state.debug_websocket {
-        debug!("Handling terminal message: {}", msg.action);
+        debug!("Handling terminal message: {:?}", request);
     }
     
-    match msg.action.as_str() {
-        "create" => {
-            let session_id = msg.session_id.unwrap_or_else(|| Uuid::new_v4().to_string());
-            create_terminal_session(state, connection_id, session_id, msg.cols, msg.rows).await?;
+    match request {
+        TerminalRequest::Create { session_id, cols, rows, binary } => {
+            let session_id = session_id.unwrap_or_else(|| Uuid::new_v4().to_string());
+            create_terminal_session(state, connection_id, session_id, cols, rows, binary).await?;
         }
-        "check" => {
-            if let Some(session_id) = msg.session_id {
-                check_terminal_session(state, connection_id, session_id).await?;
-            }
+        TerminalRequest::Check { session_id, binary } => {
+            check_terminal_session(state, connection_id, session_id, binary).await?;
         }
-        "input" => {
-            if let (Some(session_id), Some(input)) = (msg.session_id, msg.input) {
-                send_input_to_terminal(state, session_id, input).await?;
-            }
+        TerminalRequest::Input { session_id, input } => {
+            send_input_to_terminal(state, connection_id, session_id, input).await?;
         }
-        "resize" => {
-            if let (Some(session_id), Some(cols), Some(rows)) = (msg.session_id, msg.cols, msg.rows) {
-                resize_terminal(state, session_id, cols, rows).await?;
-            }
+        TerminalRequest::Resize { session_id, cols, rows } => {
+            resize_terminal(state, connection_id, session_id, cols, rows).await?;
         }
-        "destroy" => {
-            if let Some(session_id) = msg.session_id {
+        TerminalRequest::Destroy { session_id } => {
+            if require_terminal_owner(state, connection_id, &session_id).await? {
                 destroy_terminal_session(state, session_id).await?;
             }
         }
-        _ => {
-            warn!("Unknown terminal action: {}", msg.action);
+        TerminalRequest::Share { session_id, ttl_seconds } => {
+            sharing::create_share(state, connection_id, session_id, ttl_seconds).await?;
+        }
+        TerminalRequest::RevokeShare { token } => {
+            sharing::revoke_share(state, connection_id, token).await?;
         }
     }
     
@@ -752,17 +1420,25 @@ async fn create_terminal_session(
     session_id: String,
     cols: Option<u16>,
     rows: Option<u16>,
+    binary_output: bool,
 ) -> anyhow::Result<()> {
     // Check if session already exists
     {
-        let sessions = state.terminal_sessions.read().await;
-        if sessions.contains_key(&session_id) {
-            // Update connection ID for existing session
-            let mut sessions = state.terminal_sessions.write().await;
-            if let Some(session) = sessions.get_mut(&session_id) {
-                session.connection_id = connection_id;
+        let mut sessions = state.terminal_sessions.write().await;
+        if let Some(session) = sessions.get_mut(&session_id) {
+            // Another connection's session can't be taken over
+            if session.connection_id != connection_id {
+                drop(sessions);
+                let event = TerminalEvent::Error {
+                    session_id: Some(session_id),
+                    message: "Terminal session belongs to another connection".to_string(),
+                };
+                send_terminal_response(state, connection_id, event).await?;
+                return Ok(());
             }
-            send_terminal_response(state, &session_id, "created").await?;
+            session.binary_output = binary_output;
+            drop(sessions);
+            send_terminal_response(state, connection_id, TerminalEvent::Created { session_id: session_id.clone() }).await?;
             return Ok(());
         }
     }
@@ -771,7 +1447,8 @@ async fn create_terminal_session(
     let rows = rows.unwrap_or(24);
     
     // Determine working directory and shell
-    let cwd = state.exercises_path.clone();
+    let profile = profiles::connection_profile(state, connection_id).await;
+    let cwd = terminal_working_dir(state, connection_id, &profile).await?;
     let shell = if cfg!(windows) {
         "powershell.exe".to_string()
     } else {
@@ -793,7 +1470,7 @@ async fn create_terminal_session(
     let pty_pair = pty_system.openpty(pty_size)?;
     
     // Spawn shell process
-    let mut cmd = CommandBuilder::new(shell);
+    let mut cmd = CommandBuilder::new(&shell);
     cmd.cwd(&cwd);
     cmd.env("TERM", "xterm-color");
     
@@ -801,19 +1478,50 @@ async fn create_terminal_session(
     
     // Get reader and writer
     let reader = pty_pair.master.try_clone_reader()?;
-    let writer = pty_pair.master.take_writer()?;
+    let mut writer = pty_pair.master.take_writer()?;
     let master = pty_pair.master;
     
+    // Input is written by a dedicated thread so keystrokes keep their order and
+    // are never dropped while a previous write is still in progress
+    let (input_tx, mut input_rx) = mpsc::channel::<Vec<u8>>(TERMINAL_INPUT_QUEUE_SIZE);
+    thread::spawn(move || {
+        while let Some(input) = input_rx.blocking_recv() {
+            if writer.write_all(&input).and_then(|_| writer.flush()).is_err() {
+                break;
+            }
+        }
+    });
+    
+    // Start recording if enabled; a failure here shouldn't prevent the terminal from working
+    let recorder = if state.record_terminals {
+        match AsciicastRecorder::create(&recording::recordings_dir(state), &session_id, &shell, cols, rows) {
+            Ok(recorder) => Some(Arc::new(std::sync::Mutex::new(recorder))),
+            Err(e) => {
+                warn!("Failed to start recording for terminal {}: {}", session_id, e);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    
     // Create session
     let session = TerminalSession {
         session_id: session_id.clone(),
         connection_id,
+        binary_output,
     };
     
+    let scrollback = Arc::new(std::sync::Mutex::new(Scrollback::default()));
+    let (viewer_tx, _) = broadcast::channel(sharing::VIEWER_QUEUE_SIZE);
+    
     let pty_handle = PtyHandle {
-        writer: Arc::new(Mutex::new(writer)),
+        input_tx,
         child: Arc::new(Mutex::new(child)),
         master: Arc::new(Mutex::new(master)),
+        recorder: recorder.clone(),
+        scrollback: scrollback.clone(),
+        viewer_tx: viewer_tx.clone(),
     };
     
     // Store session and handle
@@ -831,12 +1539,13 @@ async fn create_terminal_session(
     let state_clone = state.clone();
     let session_id_clone = session_id.clone();
     tokio::spawn(async move {
-        // Move reader to blocking thread for reading
-        let (tx, mut rx) = tokio::sync::mpsc::channel(100);
+        // Move reader to blocking thread for reading. The queue is bounded, so when
+        // the client falls behind the thread blocks and the PTY stops being drained.
+        let (tx, mut rx) = mpsc::channel::<Vec<u8>>(TERMINAL_READ_QUEUE_SIZE);
         
         thread::spawn(move || {
             let mut reader = reader;
-            let mut buffer = [0; 1024];
+            let mut buffer = vec![0; TERMINAL_READ_BUFFER_BYTES];
             
             loop {
                 match reader.read(&mut buffer) {
@@ -853,44 +1562,63 @@ async fn create_terminal_session(
         });
         
         // Handle data in async context
-        while let Some(data) = rx.recv().await {
-            let data_str = String::from_utf8_lossy(&data).to_string();
+        let mut decoder = Utf8StreamDecoder::default();
+        while let Some(mut data) = rx.recv().await {
+            // Coalesce output that is already queued into a single frame
+            while data.len() < MAX_TERMINAL_FRAME_BYTES {
+                match rx.try_recv() {
+                    Ok(more) => data.extend_from_slice(&more),
+                    Err(_) => break,
+                }
+            }
             
-            let message = BroadcastMessage {
-                msg_type: "terminal".to_string(),
-                data: serde_json::json!({
-                    "action": "output",
-                    "sessionId": session_id_clone,
-                    "data": data_str
-                }),
-            };
+            let text = decoder.decode(&data);
             
-            let _ = state_clone.broadcast_tx.send(message);
+            if let Ok(mut scrollback) = scrollback.lock() {
+                scrollback.push(&data);
+            }
+            let _ = viewer_tx.send(Arc::from(data.as_slice()));
+            
+            if let Some(recorder) = &recorder {
+                if let Ok(mut recorder) = recorder.lock() {
+                    recorder.output(&text);
+                }
+            }
+            
+            send_terminal_output(&state_clone, &session_id_clone, &data, &text).await;
         }
         
-        // Send exit message when PTY closes
-        let exit_message = BroadcastMessage {
-            msg_type: "terminal".to_string(),
-            data: serde_json::json!({
-                "action": "exit",
-                "sessionId": session_id_clone
-            }),
-        };
+        let remainder = decoder.finish();
+        if !remainder.is_empty() {
+            send_terminal_output(&state_clone, &session_id_clone, remainder.as_bytes(), &remainder).await;
+        }
         
-        let _ = state_clone.broadcast_tx.send(exit_message);
+        // Send exit message when PTY closes
+        let owner = state_clone.terminal_sessions.read().await
+            .get(&session_id_clone)
+            .map(|session| session.connection_id);
+        if let Some(connection_id) = owner {
+            let _ = send_terminal_response(&state_clone, connection_id, TerminalEvent::Exit { session_id: session_id_clone.clone() }).await;
+        }
         
         // Clean up session
         {
             let mut sessions = state_clone.terminal_sessions.write().await;
             sessions.remove(&session_id_clone);
         }
+        sharing::remove_session_shares(&state_clone, &session_id_clone).await;
         {
             let mut handles = state_clone.pty_handles.write().await;
             handles.remove(&session_id_clone);
         }
     });
     
-    send_terminal_response(state, &session_id, "created").await?;
+    send_terminal_response(state, connection_id, TerminalEvent::Created { session_id: session_id.clone() }).await?;
+    
+    let event = LearnerEventKind::TerminalStarted {
+        terminal_session_id: session_id.clone(),
+    };
+    log_learner_event(state, &profile, event).await;
     
     if state.debug_websocket {
         info!("Terminal session {} created with PTY", session_id);
@@ -899,46 +1627,108 @@ async fn create_terminal_session(
     Ok(())
 }
 
+// Where a connection's shell starts: the profile's writable copy of the exercise
+// it is viewing, or else the exercises the profile edits
+async fn terminal_working_dir(state: &AppState, connection_id: ConnectionId, profile: &str) -> anyhow::Result<PathBuf> {
+    let current_exercise = state
+        .connections
+        .read()
+        .await
+        .get(&connection_id)
+        .and_then(|connection| connection.presence.current_exercise.clone());
+
+    let exercise = match current_exercise {
+        Some(exercise_id) => find_exercise(state, &exercise_id).await,
+        None => None,
+    };
+    match exercise {
+        Some(exercise) => state.profiles.writable_exercise_dir(profile, &exercise.path).await,
+        None => state.profiles.exercises_root(profile).await,
+    }
+}
+
 async fn check_terminal_session(
     state: &AppState,
     connection_id: ConnectionId,
     session_id: String,
+    binary_output: Option<bool>,
 ) -> anyhow::Result<()> {
-    let sessions = state.terminal_sessions.read().await;
-    let handles = state.pty_handles.read().await;
-    
-    if let (Some(_session), Some(_handle)) = (sessions.get(&session_id), handles.get(&session_id)) {
-        send_terminal_response(state, &session_id, "exists").await?;
-        
-        // Update connection ID for existing session
-        drop(sessions);
-        let mut sessions = state.terminal_sessions.write().await;
-        if let Some(session) = sessions.get_mut(&session_id) {
-            session.connection_id = connection_id;
+    let has_handle = state.pty_handles.read().await.contains_key(&session_id);
+    let mut sessions = state.terminal_sessions.write().await;
+    
+    match sessions.get_mut(&session_id) {
+        // Sessions of other connections are reported as missing
+        Some(session) if has_handle && session.connection_id == connection_id => {
+            if let Some(binary_output) = binary_output {
+                session.binary_output = binary_output;
+            }
+            drop(sessions);
+            send_terminal_response(state, connection_id, TerminalEvent::Exists { session_id: session_id.clone() }).await?;
+        }
+        _ => {
+            drop(sessions);
+            send_terminal_response(state, connection_id, TerminalEvent::NotFound { session_id: session_id.clone() }).await?;
         }
-    } else {
-        send_terminal_response(state, &session_id, "not_found").await?;
     }
     Ok(())
 }
 
+// Check that `session_id` belongs to `connection_id`, replying with an error if it doesn't
+async fn require_terminal_owner(
+    state: &AppState,
+    connection_id: ConnectionId,
+    session_id: &str,
+) -> anyhow::Result<bool> {
+    let owner = state
+        .terminal_sessions
+        .read()
+        .await
+        .get(session_id)
+        .map(|session| session.connection_id);
+
+    match owner {
+        Some(owner) if owner == connection_id => Ok(true),
+        Some(_) => {
+            warn!("Connection {} tried to use terminal session {} it doesn't own", connection_id, session_id);
+            let event = TerminalEvent::Error {
+                session_id: Some(session_id.to_string()),
+                message: "Terminal session belongs to another connection".to_string(),
+            };
+            send_terminal_response(state, connection_id, event).await?;
+            Ok(false)
+        }
+        None => {
+            warn!("Terminal session {} not found", session_id);
+            Ok(false)
+        }
+    }
+}
+
 async fn send_input_to_terminal(
     state: &AppState,
+    connection_id: ConnectionId,
     session_id: String,
     input: String,
 ) -> anyhow::Result<()> {
-    let handles = state.pty_handles.read().await;
-    if let Some(handle) = handles.get(&session_id) {
-        let writer = handle.writer.clone();
-        let input_clone = input.clone();
-        
-        // Move writing to blocking task since PTY writer is not async
-        tokio::task::spawn_blocking(move || {
-            if let Ok(mut writer) = writer.try_lock() {
-                let _ = writer.write_all(input_clone.as_bytes());
-                let _ = writer.flush();
+    if !require_terminal_owner(state, connection_id, &session_id).await? {
+        return Ok(());
+    }
+    
+    let handle_parts = state.pty_handles.read().await
+        .get(&session_id)
+        .map(|handle| (handle.input_tx.clone(), handle.recorder.clone()));
+    
+    if let Some((input_tx, recorder)) = handle_parts {
+        if let Some(recorder) = &recorder {
+            if let Ok(mut recorder) = recorder.lock() {
+                recorder.input(&input);
             }
-        }).await?;
+        }
+        
+        // Queue the input for the session's writer thread; waits if the queue is full
+        if input_tx.send(input.as_bytes().to_vec()).await.is_err() {
+            warn!("Terminal session {} is no longer accepting input", session_id);
+        }
         
         if state.debug_websocket {
             debug!("Sent input to terminal {}: {}", session_id, input);
@@ -951,25 +1741,35 @@ async fn send_input_to_terminal(
 
 async fn resize_terminal(
     state: &AppState,
+    connection_id: ConnectionId,
     session_id: String,
     cols: u16,
     rows: u16,
 ) -> anyhow::Result<()> {
+    if !require_terminal_owner(state, connection_id, &session_id).await? {
+        return Ok(());
+    }
+    
     let handles = state.pty_handles.read().await;
     if let Some(handle) = handles.get(&session_id) {
         let master = handle.master.clone();
         
+        if let Some(recorder) = &handle.recorder {
+            if let Ok(mut recorder) = recorder.lock() {
+                recorder.resize(cols, rows);
+            }
+        }
+        
         // Move resizing to blocking task since PTY operations are not async
         tokio::task::spawn_blocking(move || {
-            if let Ok(master) = master.try_lock() {
-                let new_size = PtySize {
-                    rows,
-                    cols,
-                    pixel_width: 0,
-                    pixel_height: 0,
-                };
-                let _ = master.resize(new_size);
-            }
+            let master = master.blocking_lock();
+            let new_size = PtySize {
+                rows,
+                cols,
+                pixel_width: 0,
+                pixel_height: 0,
+            };
+            let _ = master.resize(new_size);
         }).await?;
         
         if state.debug_websocket {
@@ -1003,6 +1803,7 @@ async fn destroy_terminal_session(
         let mut sessions = state.terminal_sessions.write().await;
         sessions.remove(&session_id);
     }
+    sharing::remove_session_shares(state, &session_id).await;
     
     if state.debug_websocket {
         info!("Terminal session {} destroyed", session_id);
@@ -1019,9 +1820,9 @@ async fn cleanup_terminal_sessions(
     
     {
         let sessions = state.terminal_sessions.read().await;
-        for (session_id, session) in sessions.iter() {
+        for session in sessions.values() {
             if session.connection_id == connection_id {
-                sessions_to_remove.push(session_id.clone());
+                sessions_to_remove.push(session.session_id.clone());
             }
         }
     }
@@ -1036,19 +1837,59 @@ async fn cleanup_terminal_sessions(
 
 async fn send_terminal_response(
     state: &AppState,
-    session_id: &str,
-    action: &str,
+    connection_id: ConnectionId,
+    event: TerminalEvent,
 ) -> anyhow::Result<()> {
-    let response = BroadcastMessage {
-        msg_type: "terminal".to_string(),
-        data: serde_json::json!({
-            "action": action,
-            "sessionId": session_id
-        }),
+    send_message(state, connection_id, &ServerMessage::Terminal(event)).await;
+    Ok(())
+}
+
+// Serialize a typed protocol message and queue it for a single connection
+async fn send_message(state: &AppState, connection_id: ConnectionId, message: &ServerMessage) -> bool {
+    send_to_connection(state, connection_id, Message::Text(message.to_json())).await
+}
+
+// Queue a message for a single connection, waiting while its queue is full
+async fn send_to_connection(state: &AppState, connection_id: ConnectionId, message: Message) -> bool {
+    let sender = state.connections.read().await
+        .get(&connection_id)
+        .map(|connection| connection.sender.clone());
+    
+    match sender {
+        Some(sender) => sender.send(message).await.is_ok(),
+        None => false,
+    }
+}
+
+// Deliver PTY output to the connection that owns the session
+async fn send_terminal_output(state: &AppState, session_id: &str, raw: &[u8], text: &str) {
+    let owner = state.terminal_sessions.read().await
+        .get(session_id)
+        .map(|session| (session.connection_id, session.binary_output));
+    
+    let Some((connection_id, binary_output)) = owner else {
+        return;
     };
     
-    let _ = state.broadcast_tx.send(response);
-    Ok(())
+    let message = if binary_output && session_id.len() <= u8::MAX as usize {
+        let mut frame = Vec::with_capacity(2 + session_id.len() + raw.len());
+        frame.push(BINARY_FRAME_TERMINAL_OUTPUT);
+        frame.push(session_id.len() as u8);
+        frame.extend_from_slice(session_id.as_bytes());
+        frame.extend_from_slice(raw);
+        Message::Binary(frame)
+    } else {
+        if text.is_empty() {
+            return;
+        }
+        let message = ServerMessage::Terminal(TerminalEvent::Output {
+            session_id: session_id.to_string(),
+            data: text.to_string(),
+        });
+        Message::Text(message.to_json())
+    };
+    
+    send_to_connection(state, connection_id, message).await;
 }
 
 // API handlers
@@ -1065,14 +1906,19 @@ async fn get_exercises(State(state): State<AppState>) -> Result<Json<Vec<Exercis
 async fn get_exercise(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
 ) -> Result<Json<ExerciseDetails>, StatusCode> {
     // The client sends chapter="ch01_getting_started" and exercise="ex01_hello_world"
     // We need to join them correctly to match the directory structure
-    let exercise_dir_path = state.exercises_path.join(&chapter).join(&exercise);
     let exercise_id = format!("{}/{}", chapter, exercise);
+    let exercise_dir_path = state.profiles.exercise_dir(&profile, &exercise_id);
+    let hint_preference = preferences::hint_preference(&state, &profile).await?;
     
     match load_exercise_details(&exercise_dir_path, &exercise_id).await {
-        Ok(details) => Ok(Json(details)),
+        Ok(mut details) => {
+            preferences::withhold_hints(&mut details, hint_preference);
+            Ok(Json(details))
+        }
         Err(e) => {
             error!("Error loading exercise {}/{}: {}", chapter, exercise, e);
             Err(StatusCode::NOT_FOUND)
@@ -1083,9 +1929,11 @@ async fn get_exercise(
 async fn save_exercise_code(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<SaveCodeRequest>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_path = writable_exercise_dir(&state, &profile, &chapter, &exercise).await?;
     let main_path = exercise_path.join("src").join("main.rs");
     
     warn!("LEGACY SAVE: Single-file save called for {}/{}, content_length={}", chapter, exercise, request.code.len());
@@ -1100,23 +1948,31 @@ async fn save_exercise_code(
         return Err(StatusCode::BAD_REQUEST);
     }
     
+    let scoped_path = profiles::scoped_exercise_path(&profile, &format!("{}/{}", chapter, exercise));
+    if collab::is_open(&state, &scoped_path, "src/main.rs").await {
+        info!("Skipping src/main.rs: managed by a collaborative editing session");
+        return Ok(Json(ApiResponse::success(())));
+    }
+    
     match fs::write(&main_path, &request.code).await {
         Ok(_) => {
+            let event = LearnerEventKind::FileSaved {
+                exercise: format!("{}/{}", chapter, exercise),
+                file: "src/main.rs".to_string(),
+                bytes: request.code.len(),
+            };
+            log_learner_event(&state, &profile, event).await;
+
             // Broadcast file change
             let exercise_name = match load_exercise_title(&exercise_path).await {
                 Ok(title) => title,
                 Err(_) => format!("{}/{}", chapter, exercise),
             };
             
-            let broadcast_msg = BroadcastMessage {
-                msg_type: "file_updated".to_string(),
-                data: serde_json::json!({
-                    "exercise": exercise_name,
-                    "file": "src/main.rs"
-                }),
-            };
-            
-            let _ = state.broadcast_tx.send(broadcast_msg);
+            let _ = state.broadcast_tx.send(ServerMessage::FileUpdated {
+                exercise: exercise_name,
+                file: "src/main.rs".to_string(),
+            });
             
             Ok(Json(ApiResponse::success(())))
         }
@@ -1130,9 +1986,11 @@ async fn save_exercise_code(
 async fn save_exercise_files(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<BatchSaveRequest>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_path = writable_exercise_dir(&state, &profile, &chapter, &exercise).await?;
     
     info!("Saving {} files for exercise {}/{}", request.files.len(), chapter, exercise);
     for (i, file) in request.files.iter().enumerate() {
@@ -1141,17 +1999,14 @@ async fn save_exercise_files(
     }
     
     // Validate and save each file
+    let scoped_path = profiles::scoped_exercise_path(&profile, &format!("{}/{}", chapter, exercise));
     for file in &request.files {
-        // Validate path to prevent directory traversal
-        if file.path.contains("..") || file.path.starts_with('/') {
-            error!("Invalid file path: {}", file.path);
-            return Err(StatusCode::BAD_REQUEST);
-        }
+        validate_exercise_file_path(&file.path)?;
         
-        // Only allow editing certain files
-        if !is_editable_file(&file.path) {
-            error!("File not editable: {}", file.path);
-            return Err(StatusCode::FORBIDDEN);
+        // Files open in a collaborative session are saved by that session
+        if collab::is_open(&state, &scoped_path, &file.path).await {
+            info!("Skipping {}: managed by a collaborative editing session", file.path);
+            continue;
         }
         
         let file_path = exercise_path.join(&file.path);
@@ -1171,6 +2026,12 @@ async fn save_exercise_files(
             return Err(StatusCode::INTERNAL_SERVER_ERROR);
         }
         info!("Successfully wrote {} bytes to {}", file.content.len(), file_path.display());
+        let event = LearnerEventKind::FileSaved {
+            exercise: format!("{}/{}", chapter, exercise),
+            file: file.path.clone(),
+            bytes: file.content.len(),
+        };
+        log_learner_event(&state, &profile, event).await;
     }
     
     // Broadcast file changes
@@ -1180,14 +2041,10 @@ async fn save_exercise_files(
     };
     
     for file in &request.files {
-        let broadcast_msg = BroadcastMessage {
-            msg_type: "file_updated".to_string(),
-            data: serde_json::json!({
-                "exercise": &exercise_name,
-                "file": &file.path
-            }),
-        };
-        let _ = state.broadcast_tx.send(broadcast_msg);
+        let _ = state.broadcast_tx.send(ServerMessage::FileUpdated {
+            exercise: exercise_name.clone(),
+            file: file.path.clone(),
+        });
     }
     
     Ok(Json(ApiResponse::success(())))
@@ -1196,20 +2053,26 @@ async fn save_exercise_files(
 async fn create_exercise_file(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<FileOperationRequest>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_path = writable_exercise_dir(&state, &profile, &chapter, &exercise).await?;
     
-    // Validate path
-    if request.path.contains("..") || request.path.starts_with('/') {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    validate_exercise_file_path(&request.path)?;
     
-    // Only allow creating files in src/ directory
-    if !request.path.starts_with("src/") || !request.path.ends_with(".rs") {
+    // New files are Rust sources
+    if !request.path.ends_with(".rs") {
         return Err(StatusCode::FORBIDDEN);
     }
     
+    // Files open in a collaborative session are managed by that session
+    let scoped_path = profiles::scoped_exercise_path(&profile, &format!("{}/{}", chapter, exercise));
+    if collab::is_open(&state, &scoped_path, &request.path).await {
+        info!("Refusing to create {}: managed by a collaborative editing session", request.path);
+        return Err(StatusCode::CONFLICT);
+    }
+    
     let file_path = exercise_path.join(&request.path);
     
     // Check if file already exists
@@ -1227,10 +2090,16 @@ async fn create_exercise_file(
     
     // Create file with default content
     let content = request.content.unwrap_or_else(|| "// New file\n".to_string());
-    if let Err(e) = fs::write(&file_path, content).await {
+    if let Err(e) = fs::write(&file_path, &content).await {
         error!("Error creating file: {}", e);
         return Err(StatusCode::INTERNAL_SERVER_ERROR);
     }
+    let event = LearnerEventKind::FileSaved {
+        exercise: format!("{}/{}", chapter, exercise),
+        file: request.path.clone(),
+        bytes: content.len(),
+    };
+    log_learner_event(&state, &profile, event).await;
     
     Ok(Json(ApiResponse::success(())))
 }
@@ -1238,23 +2107,24 @@ async fn create_exercise_file(
 async fn delete_exercise_file(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<FileOperationRequest>,
 ) -> Result<Json<ApiResponse<()>>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_path = writable_exercise_dir(&state, &profile, &chapter, &exercise).await?;
     
-    // Validate path
-    if request.path.contains("..") || request.path.starts_with('/') {
-        return Err(StatusCode::BAD_REQUEST);
-    }
+    validate_exercise_file_path(&request.path)?;
     
     // Don't allow deleting critical files
     if request.path == "src/main.rs" || request.path == "Cargo.toml" {
         return Err(StatusCode::FORBIDDEN);
     }
     
-    // Only allow deleting files in src/ directory
-    if !request.path.starts_with("src/") {
-        return Err(StatusCode::FORBIDDEN);
+    // Files open in a collaborative session are managed by that session
+    let scoped_path = profiles::scoped_exercise_path(&profile, &format!("{}/{}", chapter, exercise));
+    if collab::is_open(&state, &scoped_path, &request.path).await {
+        info!("Refusing to delete {}: managed by a collaborative editing session", request.path);
+        return Err(StatusCode::CONFLICT);
     }
     
     let file_path = exercise_path.join(&request.path);
@@ -1268,6 +2138,36 @@ async fn delete_exercise_file(
     Ok(Json(ApiResponse::success(())))
 }
 
+// Where a profile's edits to an exercise go; other profiles get a private copy on first write
+async fn writable_exercise_dir(state: &AppState, profile: &str, chapter: &str, exercise: &str) -> Result<PathBuf, StatusCode> {
+    state
+        .profiles
+        .writable_exercise_dir(profile, &format!("{}/{}", chapter, exercise))
+        .await
+        .map_err(|e| {
+            error!("Error preparing {}/{} for profile {}: {}", chapter, exercise, profile, e);
+            StatusCode::NOT_FOUND
+        })
+}
+
+// Path checks for every way of changing exercise files (HTTP saves, creating and
+// deleting files, collaborative editing and imports)
+fn validate_exercise_file_path(path: &str) -> Result<(), StatusCode> {
+    // Validate path to prevent directory traversal
+    if path.contains("..") || path.starts_with('/') {
+        error!("Invalid file path: {}", path);
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    
+    // Only allow editing certain files
+    if !is_editable_file(path) {
+        error!("File not editable: {}", path);
+        return Err(StatusCode::FORBIDDEN);
+    }
+    
+    Ok(())
+}
+
 // Helper function to check if a file is editable
 fn is_editable_file(path: &str) -> bool {
     // Allow editing Cargo.toml and any file in src/
@@ -1278,11 +2178,19 @@ fn is_editable_file(path: &str) -> bool {
 async fn test_exercise(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
 ) -> Result<Json<CargoResult>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_id = format!("{}/{}", chapter, exercise);
+    let exercise_path = state.profiles.exercise_dir(&profile, &exercise_id);
     
+    let started = Instant::now();
     match run_cargo_command("test", &exercise_path, vec!["--", "--nocapture"]).await {
-        Ok(result) => Ok(Json(result)),
+        Ok(result) => {
+            test_runs::record_test_run(&state, &profile, &exercise_id, &result, started.elapsed()).await;
+            error_codes::record_error_codes(&state, &profile, &exercise_id, CodeAction::Test, &result).await;
+            Ok(Json(result))
+        }
         Err(e) => {
             error!("Error running tests for {}/{}: {}", chapter, exercise, e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1293,11 +2201,22 @@ async fn test_exercise(
 async fn run_exercise(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
 ) -> Result<Json<CargoResult>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_path = state.profiles.exercise_dir(&profile, &format!("{}/{}", chapter, exercise));
     
     match run_cargo_command("run", &exercise_path, vec![]).await {
-        Ok(result) => Ok(Json(result)),
+        Ok(result) => {
+            let event = LearnerEventKind::CodeRun {
+                exercise_id: format!("{}/{}", chapter, exercise),
+                action: CodeAction::Run,
+                success: result.success,
+            };
+            log_learner_event(&state, &profile, event).await;
+            error_codes::record_error_codes(&state, &profile, &format!("{}/{}", chapter, exercise), CodeAction::Run, &result).await;
+            Ok(Json(result))
+        }
         Err(e) => {
             error!("Error running exercise {}/{}: {}", chapter, exercise, e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1308,11 +2227,22 @@ async fn run_exercise(
 async fn check_exercise(
     AxumPath((chapter, exercise)): AxumPath<(String, String)>,
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
 ) -> Result<Json<CargoResult>, StatusCode> {
-    let exercise_path = state.exercises_path.join(&chapter).join(&exercise);
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let exercise_path = state.profiles.exercise_dir(&profile, &format!("{}/{}", chapter, exercise));
     
     match run_cargo_command("clippy", &exercise_path, vec!["--", "-W", "clippy::all"]).await {
-        Ok(result) => Ok(Json(result)),
+        Ok(result) => {
+            let event = LearnerEventKind::CodeRun {
+                exercise_id: format!("{}/{}", chapter, exercise),
+                action: CodeAction::Check,
+                success: result.success,
+            };
+            log_learner_event(&state, &profile, event).await;
+            error_codes::record_error_codes(&state, &profile, &format!("{}/{}", chapter, exercise), CodeAction::Check, &result).await;
+            Ok(Json(result))
+        }
         Err(e) => {
             error!("Error running clippy for {}/{}: {}", chapter, exercise, e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1320,8 +2250,19 @@ async fn check_exercise(
     }
 }
 
-async fn get_progress(State(state): State<AppState>) -> Result<Json<ProgressData>, StatusCode> {
-    match ensure_progress_file(&state.progress_path, &state.exercises_path).await {
+// Progress store of the given profile
+async fn profile_progress(state: &AppState, profile: &str) -> Result<ProgressHandle, StatusCode> {
+    state.profiles.progress(profile).await.map_err(|e| {
+        error!("Error opening progress for profile {}: {}", profile, e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+async fn get_progress(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+) -> Result<Json<ProgressData>, StatusCode> {
+    match profile_progress(&state, &profile).await?.snapshot().await {
         Ok(progress) => Ok(Json(progress)),
         Err(e) => {
             error!("Error loading progress: {}", e);
@@ -1332,13 +2273,38 @@ async fn get_progress(State(state): State<AppState>) -> Result<Json<ProgressData
 
 async fn complete_exercise(
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<CompleteExerciseRequest>,
 ) -> Result<Json<ApiResponse<ProgressData>>, StatusCode> {
-    match update_exercise_completion(&state.progress_path, &state.exercises_path, &request).await {
-        Ok(progress) => Ok(Json(ApiResponse::success_with_extra(
-            progress,
-            serde_json::json!({"message": "Exercise completed successfully"})
-        ))),
+    // The server completes exercises itself when their tests pass
+    if !state.legacy_client_completion {
+        warn!(
+            "Rejected completion of {} posted by the client; start with --legacy-client-completion to accept it",
+            request.exercise_id
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    // Bring the measured time up to date first
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let session_id = state.activity.flush(&state.profiles, &profile).await;
+    let progress = profile_progress(&state, &profile).await?;
+    let exercise_id = request.exercise_id.clone();
+    match update_exercise_completion(&progress, request, session_id).await {
+        Ok((progress, completed)) => {
+            if completed {
+                if let Some(message) = exercise_completed_message(&profile, &progress, &exercise_id) {
+                    let _ = state.broadcast_tx.send(message);
+                }
+                if let Some(event) = event_log::completion_event(&progress, &exercise_id) {
+                    log_learner_event(&state, &profile, event).await;
+                }
+            }
+            Ok(Json(ApiResponse::success_with_extra(
+                progress,
+                serde_json::json!({"message": "Exercise completed successfully"})
+            )))
+        }
         Err(e) => {
             error!("Error updating progress: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1348,10 +2314,30 @@ async fn complete_exercise(
 
 async fn track_hint_usage(
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<HintRequest>,
 ) -> Result<Json<ApiResponse<ProgressData>>, StatusCode> {
-    match update_hint_usage(&state.progress_path, &state.exercises_path, &request).await {
-        Ok(progress) => Ok(Json(ApiResponse::success(progress))),
+    // Hard mode and conceptual-only hints are enforced here, not just in the UI
+    let hint_preference = preferences::hint_preference(&state, &profile).await?;
+    if !hint_preference.allows(request.hint_level) {
+        warn!(
+            "Rejected hint level {} for {}: the profile's hint preference is {:?}",
+            request.hint_level, request.exercise_id, hint_preference
+        );
+        return Err(StatusCode::FORBIDDEN);
+    }
+
+    state.activity.record(&state.profiles, &profile, Activity::Interaction).await;
+    let progress = profile_progress(&state, &profile).await?;
+    let event = LearnerEventKind::HintUnlocked {
+        exercise_id: request.exercise_id.clone(),
+        level: request.hint_level,
+    };
+    match update_hint_usage(&progress, request).await {
+        Ok(progress) => {
+            log_learner_event(&state, &profile, event).await;
+            Ok(Json(ApiResponse::success(progress)))
+        }
         Err(e) => {
             error!("Error tracking hint usage: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1361,10 +2347,23 @@ async fn track_hint_usage(
 
 async fn track_exercise_view(
     State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
     Json(request): Json<ViewRequest>,
 ) -> Result<Json<ApiResponse<ProgressData>>, StatusCode> {
-    match update_exercise_view(&state.progress_path, &state.exercises_path, &request).await {
-        Ok(progress) => Ok(Json(ApiResponse::success(progress))),
+    if let Some(connection_id) = request.connection_id {
+        presence::record_exercise_view(&state, connection_id, &request.exercise_id).await;
+    }
+    state.activity.record(&state.profiles, &profile, Activity::View(request.exercise_id.clone())).await;
+    
+    let progress = profile_progress(&state, &profile).await?;
+    let event = LearnerEventKind::ExerciseViewed {
+        exercise_id: request.exercise_id.clone(),
+    };
+    match update_exercise_view(&progress, request.exercise_id).await {
+        Ok(progress) => {
+            log_learner_event(&state, &profile, event).await;
+            Ok(Json(ApiResponse::success(progress)))
+        }
         Err(e) => {
             error!("Error tracking exercise view: {}", e);
             Err(StatusCode::INTERNAL_SERVER_ERROR)
@@ -1506,7 +2505,7 @@ async fn get_book_by_url(
     match fetch_book_content(url).await {
         Ok((content, title)) => {
             // Extract chapter identifier from URL for response
-            let chapter = url.split('/').last()
+            let chapter = url.split('/').next_back()
                 .and_then(|filename| filename.strip_suffix(".html"))
                 .unwrap_or("unknown")
                 .to_string();
@@ -1522,7 +2521,7 @@ async fn get_book_by_url(
         Err(e) => {
             warn!("Failed to fetch book content from URL {}: {}", url, e);
             // Fallback to URL-only response
-            let chapter = url.split('/').last()
+            let chapter = url.split('/').next_back()
                 .and_then(|filename| filename.strip_suffix(".html"))
                 .unwrap_or("unknown")
                 .to_string();
@@ -1850,6 +2849,7 @@ async fn discover_chapters(exercises_path: &std::path::Path) -> anyhow::Result<(
         
         // Count exercises in this chapter and get chapter title
         let mut exercise_count = 0;
+        let mut exercise_numbers = Vec::new();
         let mut chapter_title = format!("Chapter {}", chapter_number);
         
         for exercise_entry in WalkDir::new(chapter_entry.path()).max_depth(1) {
@@ -1861,6 +2861,7 @@ async fn discover_chapters(exercises_path: &std::path::Path) -> anyhow::Result<(
             let exercise_name = exercise_entry.file_name().to_string_lossy();
             if exercise_name.starts_with("ex") {
                 exercise_count += 1;
+                exercise_numbers.extend(chapter_stats::exercise_number(&exercise_name));
                 
                 // Try to get chapter title from first exercise metadata
                 if exercise_count == 1 {
@@ -1881,6 +2882,7 @@ async fn discover_chapters(exercises_path: &std::path::Path) -> anyhow::Result<(
         
         if exercise_count > 0 {
             total_exercises += exercise_count;
+            exercise_numbers.sort_unstable();
             
             let chapter_info = ChapterInfo {
                 chapter_number,
@@ -1889,6 +2891,9 @@ async fn discover_chapters(exercises_path: &std::path::Path) -> anyhow::Result<(
                 total_exercises: exercise_count,
                 completion_percentage: 0.0,
                 time_spent_minutes: 0,
+                first_started: None,
+                completed_at: None,
+                exercises: exercise_numbers,
             };
             
             chapters.insert(chapter_number, chapter_info);
@@ -1898,31 +2903,25 @@ async fn discover_chapters(exercises_path: &std::path::Path) -> anyhow::Result<(
     Ok((chapters, total_exercises))
 }
 
-async fn count_total_exercises(exercises_path: &std::path::Path) -> anyhow::Result<u32> {
-    let (_, total) = discover_chapters(exercises_path).await?;
-    Ok(if total > 0 { total } else { 50 })
-}
-
-async fn ensure_progress_file(
-    progress_path: &std::path::Path,
+// Take the stored progress, or build a fresh one, and bring its exercise catalog
+// up to date. Returns whether the result differs from what is stored.
+async fn prepare_progress(
+    stored: Option<ProgressData>,
     exercises_path: &std::path::Path,
-) -> anyhow::Result<ProgressData> {
-    // Ensure the progress directory exists
-    if let Some(parent) = progress_path.parent() {
-        fs::create_dir_all(parent).await?;
-    }
-    
+    user_id: &str,
+) -> anyhow::Result<(ProgressData, bool)> {
     let (discovered_chapters, total_exercises) = discover_chapters(exercises_path).await?;
     
-    if !progress_path.exists() {
-        info!("Creating new progress file: {:?}", progress_path);
+    let Some(mut progress) = stored else {
+        info!("Creating new progress");
         info!("Detected {} total exercises across {} chapters", total_exercises, discovered_chapters.len());
         
         // Convert discovered chapters to JSON Value
         let chapters_json = serde_json::to_value(&discovered_chapters)?;
         
         let default_progress = ProgressData {
-            user_id: "default".to_string(),
+            schema_version: progress_schema::PROGRESS_SCHEMA_VERSION,
+            user_id: user_id.to_string(),
             created_at: Utc::now().to_rfc3339(),
             overall_progress: 0.0,
             chapters_completed: 0,
@@ -1934,34 +2933,19 @@ async fn ensure_progress_file(
             chapters: chapters_json,
             exercise_history: Vec::new(),
             achievements: Vec::new(),
+            activity_sessions: Vec::new(),
             session_stats: SessionStats {
                 exercises_viewed: 0,
                 exercises_completed: 0,
                 hints_used: 0,
                 time_spent: 0,
             },
+            resets: Vec::new(),
+            preferences: UserPreferences::default(),
         };
         
-        let content = serde_json::to_string_pretty(&default_progress)?;
-        fs::write(progress_path, content).await?;
-        info!("Progress file created successfully");
-        return Ok(default_progress);
-    }
-    
-    // File exists, load and validate it
-    let content = fs::read_to_string(progress_path).await?;
-    let mut progress: ProgressData = serde_json::from_str(&content)?;
-    
-    // Ensure all required properties exist (for backwards compatibility)
-    if progress.session_stats.exercises_viewed == 0 && progress.session_stats.exercises_completed == 0 && progress.session_stats.hints_used == 0 && progress.session_stats.time_spent == 0 {
-        // Initialize default session stats if they're all zero (likely missing)
-        progress.session_stats = SessionStats {
-            exercises_viewed: 0,
-            exercises_completed: 0,
-            hints_used: 0,
-            time_spent: 0,
-        };
-    }
+        return Ok((default_progress, true));
+    };
     
     // Update total exercises count and chapters if needed
     let mut should_save = false;
@@ -1973,163 +2957,191 @@ async fn ensure_progress_file(
     }
     
     // Update chapters if empty or if we have new chapters discovered
-    if progress.chapters.as_object().map_or(true, |obj| obj.is_empty()) || discovered_chapters.len() > 0 {
+    if progress.chapters.as_object().is_none_or(|obj| obj.is_empty()) || !discovered_chapters.is_empty() {
+        // Compare with the statistics filled in, so unchanged chapters aren't rewritten
+        let mut discovered_chapters = discovered_chapters;
+        chapter_stats::apply_chapter_stats(&mut discovered_chapters, &progress.exercise_history);
         let chapters_json = serde_json::to_value(&discovered_chapters)?;
-        progress.chapters = chapters_json;
-        should_save = true;
-        info!("Updated chapters structure with {} chapters", discovered_chapters.len());
-    }
-    
-    if should_save {
-        let content = serde_json::to_string_pretty(&progress)?;
-        fs::write(progress_path, content).await?;
-        info!("Progress file updated successfully");
+        if chapters_json != progress.chapters {
+            progress.chapters = chapters_json;
+            should_save = true;
+            info!("Updated chapters structure with {} chapters", discovered_chapters.len());
+        }
     }
     
-    Ok(progress)
+    Ok((progress, should_save))
 }
 
+// `session_id` is the active time session the exercise was completed in, if the
+// server has seen the learner working
+// Returns whether the exercise was newly completed
 async fn update_exercise_completion(
-    progress_path: &std::path::Path,
-    exercises_path: &std::path::Path,
-    request: &CompleteExerciseRequest,
-) -> anyhow::Result<ProgressData> {
-    let mut progress = ensure_progress_file(progress_path, exercises_path).await?;
-    
+    progress: &ProgressHandle,
+    request: CompleteExerciseRequest,
+    session_id: Option<String>,
+) -> anyhow::Result<(ProgressData, bool)> {
+    let completed = Arc::new(AtomicBool::new(false));
+    let progress = progress
+        .update({
+            let completed = completed.clone();
+            move |progress| {
+                let changed = record_completion(
+                    progress,
+                    &request.exercise_id,
+                    request.time_taken_minutes,
+                    session_id,
+                    CompletionSource::Client,
+                );
+                completed.store(changed, Ordering::Relaxed);
+                changed
+            }
+        })
+        .await?;
+    Ok((progress, completed.load(Ordering::Relaxed)))
+}
+
+// `exercise_completed` for a completion just recorded in `progress`
+fn exercise_completed_message(profile: &str, progress: &ProgressData, exercise_id: &str) -> Option<ServerMessage> {
+    let entry = progress
+        .exercise_history
+        .iter()
+        .find(|entry| entry.exercise_id == exercise_id && entry.completed_at.is_some())?;
+    Some(ServerMessage::ExerciseCompleted {
+        profile: profile.to_string(),
+        exercise_id: exercise_id.to_string(),
+        completed_by: entry.completed_by.unwrap_or(CompletionSource::Client),
+        time_taken_minutes: entry.time_taken_minutes,
+    })
+}
+
+// Mark an exercise completed; returns false when it already was
+fn record_completion(
+    progress: &mut ProgressData,
+    exercise_id: &str,
+    time_taken_minutes: Option<u32>,
+    session_id: Option<String>,
+    completed_by: CompletionSource,
+) -> bool {
     // Check if already completed to avoid duplicates
-    let existing_entry = progress.exercise_history.iter().find(|entry| entry.exercise_id == request.exercise_id);
-    let is_already_completed = existing_entry.map_or(false, |entry| entry.completed_at.is_some());
+    let existing_entry = progress.exercise_history.iter().find(|entry| entry.exercise_id == exercise_id);
+    let is_already_completed = existing_entry.is_some_and(|entry| entry.completed_at.is_some());
     
-    info!("Checking completion for {}: already completed: {}", request.exercise_id, is_already_completed);
+    info!("Checking completion for {}: already completed: {}", exercise_id, is_already_completed);
     
     if is_already_completed {
-        info!("Exercise {} already completed", request.exercise_id);
-        return Ok(progress);
+        info!("Exercise {} already completed", exercise_id);
+        return false;
     }
     
-    // Update progress
+    // Update progress; `total_time_minutes` is derived from the history
     progress.exercises_completed += 1;
     progress.session_stats.exercises_completed += 1;
-    progress.total_time_minutes += request.time_taken_minutes.unwrap_or(0);
     progress.overall_progress = progress.exercises_completed as f64 / progress.total_exercises as f64;
     
-    // Update or add to exercise history
-    if let Some(entry) = progress.exercise_history.iter_mut().find(|entry| entry.exercise_id == request.exercise_id) {
-        entry.completed_at = Some(Utc::now().to_rfc3339());
-        entry.time_taken_minutes = request.time_taken_minutes;
-        entry.status = "completed".to_string();
-        entry.session_id = Some(format!("session_{}", Utc::now().timestamp_millis()));
-    } else {
-        progress.exercise_history.push(ExerciseHistoryEntry {
-            exercise_id: request.exercise_id.clone(),
-            viewed_at: None,
-            completed_at: Some(Utc::now().to_rfc3339()),
-            time_taken_minutes: request.time_taken_minutes,
-            status: "completed".to_string(),
-            session_id: Some(format!("session_{}", Utc::now().timestamp_millis())),
-            hints_used: None,
-        });
-    }
+    let session_id = session_id.unwrap_or_else(|| format!("session_{}", Utc::now().timestamp_millis()));
     
-    // Save updated progress
-    let content = serde_json::to_string_pretty(&progress)?;
-    fs::write(progress_path, content).await?;
+    // Update or add to exercise history
+    let entry = history_entry_mut(progress, exercise_id, "completed");
+    entry.completed_at = Some(Utc::now().to_rfc3339());
+    entry.status = "completed".to_string();
+    entry.session_id = Some(session_id);
+    entry.completed_by = Some(completed_by);
+    // Prefer the time the server measured over what the client reports
+    entry.time_taken_minutes = match entry.active_seconds {
+        Some(seconds) if seconds > 0 => Some(activity::entry_minutes(entry)),
+        _ => time_taken_minutes,
+    };
+    let (attempts, test_failures) = test_runs::attempt_counts(entry);
+    entry.attempts = Some(attempts);
+    entry.test_failures = Some(test_failures);
     
     info!(
-        "Exercise completed: {} in {} minutes",
-        request.exercise_id,
-        request.time_taken_minutes.unwrap_or(0)
+        "Exercise completed ({:?}): {} in {} minutes after {} test runs ({} failed)",
+        completed_by,
+        exercise_id,
+        entry.time_taken_minutes.unwrap_or(0),
+        attempts,
+        test_failures
     );
     info!(
         "Total exercises completed: {}/{}",
         progress.exercises_completed,
         progress.total_exercises
     );
-    
-    Ok(progress)
+    true
 }
 
 async fn update_hint_usage(
-    progress_path: &std::path::Path,
-    exercises_path: &std::path::Path,
-    request: &HintRequest,
+    progress: &ProgressHandle,
+    request: HintRequest,
 ) -> anyhow::Result<ProgressData> {
-    let mut progress = ensure_progress_file(progress_path, exercises_path).await?;
-    
+    progress.update(move |progress| {
+        record_hint(progress, &request.exercise_id, request.hint_level, &Utc::now().to_rfc3339());
+        true
+    })
+    .await
+}
+
+// `at` is when the hint was opened; also replayed from the event log
+fn record_hint(progress: &mut ProgressData, exercise_id: &str, hint_level: u32, at: &str) {
     // Update hint usage stats
     progress.session_stats.hints_used += 1;
     
     // Add to exercise history if not already tracked for this exercise
-    if let Some(entry) = progress.exercise_history.iter_mut().find(|entry| entry.exercise_id == request.exercise_id) {
-        if let Some(ref mut hints_used) = entry.hints_used {
-            if !hints_used.contains(&request.hint_level) {
-                hints_used.push(request.hint_level);
-            }
-        } else {
-            entry.hints_used = Some(vec![request.hint_level]);
-        }
-    } else {
-        progress.exercise_history.push(ExerciseHistoryEntry {
-            exercise_id: request.exercise_id.clone(),
-            viewed_at: Some(Utc::now().to_rfc3339()),
-            completed_at: None,
-            time_taken_minutes: None,
-            status: "in_progress".to_string(),
-            session_id: None,
-            hints_used: Some(vec![request.hint_level]),
-        });
+    let is_new = !progress.exercise_history.iter().any(|entry| entry.exercise_id == exercise_id);
+    let entry = history_entry_mut(progress, exercise_id, "in_progress");
+    if is_new {
+        entry.viewed_at = Some(at.to_string());
+    }
+    let hints_used = entry.hints_used.get_or_insert_with(Vec::new);
+    if !hints_used.contains(&hint_level) {
+        hints_used.push(hint_level);
     }
     
-    // Save updated progress
-    let content = serde_json::to_string_pretty(&progress)?;
-    fs::write(progress_path, content).await?;
-    
-    info!("Hint used: {}, level {}", request.exercise_id, request.hint_level);
-    Ok(progress)
+    info!("Hint used: {}, level {}", exercise_id, hint_level);
 }
 
 async fn update_exercise_view(
-    progress_path: &std::path::Path,
-    exercises_path: &std::path::Path,
-    request: &ViewRequest,
+    progress: &ProgressHandle,
+    exercise_id: String,
 ) -> anyhow::Result<ProgressData> {
-    let mut progress = ensure_progress_file(progress_path, exercises_path).await?;
-    
+    progress.update(move |progress| {
+        record_view(progress, &exercise_id, &Utc::now().to_rfc3339());
+        true
+    })
+    .await
+}
+
+// `at` is when the exercise was opened; also replayed from the event log
+fn record_view(progress: &mut ProgressData, exercise_id: &str, at: &str) {
     // Update view stats
     progress.session_stats.exercises_viewed += 1;
     
     // Check if already viewed
-    if !progress.exercise_history.iter().any(|entry| entry.exercise_id == request.exercise_id) {
-        progress.exercise_history.push(ExerciseHistoryEntry {
-            exercise_id: request.exercise_id.clone(),
-            viewed_at: Some(Utc::now().to_rfc3339()),
-            completed_at: None,
-            time_taken_minutes: None,
-            status: "viewed".to_string(),
-            session_id: None,
-            hints_used: None,
-        });
+    if !progress.exercise_history.iter().any(|entry| entry.exercise_id == exercise_id) {
+        history_entry_mut(progress, exercise_id, "viewed").viewed_at = Some(at.to_string());
     }
     
-    // Save updated progress
-    let content = serde_json::to_string_pretty(&progress)?;
-    fs::write(progress_path, content).await?;
-    
-    info!("Exercise viewed: {}", request.exercise_id);
-    Ok(progress)
+    info!("Exercise viewed: {}", exercise_id);
 }
 
-async fn initialize_progress_system(state: &AppState) -> anyhow::Result<()> {
-    match ensure_progress_file(&state.progress_path, &state.exercises_path).await {
-        Ok(_) => {
-            info!("📊 Progress system initialized");
-            Ok(())
-        }
-        Err(e) => {
-            error!("Failed to initialize progress system: {}", e);
-            Err(e)
+// The history entry of `exercise_id`, added with `status` if there is none. An
+// exercise that was only viewed moves on to `status`, e.g. once it is tested.
+fn history_entry_mut<'a>(progress: &'a mut ProgressData, exercise_id: &str, status: &str) -> &'a mut ExerciseHistoryEntry {
+    let entry = match progress.exercise_history.iter().position(|entry| entry.exercise_id == exercise_id) {
+        Some(index) => &mut progress.exercise_history[index],
+        None => {
+            progress.exercise_history.push(ExerciseHistoryEntry {
+                exercise_id: exercise_id.to_string(),
+                ..Default::default()
+            });
+            progress.exercise_history.last_mut().expect("entry was just added")
         }
+    };
+    if entry.status.is_empty() || entry.status == "viewed" {
+        entry.status = status.to_string();
     }
+    entry
 }
 
 fn should_ignore_path(path: &std::path::Path) -> bool {
@@ -2179,7 +3191,7 @@ async fn setup_file_watcher(state: AppState) -> anyhow::Result<()> {
                     for path in event.paths {
                         if let Ok(relative_path) = path.strip_prefix(&exercises_path) {
                             // Skip ignored paths (build artifacts, hidden files, etc.)
-                            if should_ignore_path(&relative_path) {
+                            if should_ignore_path(relative_path) {
                                 continue;
                             }
                             
@@ -2199,15 +3211,10 @@ async fn setup_file_watcher(state: AppState) -> anyhow::Result<()> {
                                     exercise_dir.replace('_', " ").replacen("ex", "", 1)
                                 };
                                 
-                                let broadcast_msg = BroadcastMessage {
-                                    msg_type: "file_changed".to_string(),
-                                    data: serde_json::json!({
-                                        "exercise": exercise_name,
-                                        "file": relative_path.to_string_lossy()
-                                    }),
-                                };
-                                
-                                let _ = broadcast_tx.send(broadcast_msg);
+                                let _ = broadcast_tx.send(ServerMessage::FileChanged {
+                                    exercise: exercise_name,
+                                    file: relative_path.to_string_lossy().to_string(),
+                                });
                             }
                         }
                     }
@@ -2319,7 +3326,7 @@ async fn ensure_exercises_available(exercises_path: PathBuf) -> anyhow::Result<P
     // Ask user how they want to select the directory
     let selection_method = Select::new()
         .with_prompt("How would you like to select the directory?")
-        .items(&vec!["Browse for directory", "Enter path manually"])
+        .items(&["Browse for directory", "Enter path manually"])
         .default(0)
         .interact()?;
     
@@ -2600,15 +3607,13 @@ async fn browse_for_directory(start_path: PathBuf) -> anyhow::Result<Option<Path
         
         match std::fs::read_dir(&current_path) {
             Ok(read_dir) => {
-                for entry in read_dir {
-                    if let Ok(entry) = entry {
-                        if let Ok(file_type) = entry.file_type() {
-                            if file_type.is_dir() {
-                                if let Some(name) = entry.file_name().to_str() {
-                                    // Skip hidden directories
-                                    if !name.starts_with('.') {
-                                        dirs.push(name.to_string());
-                                    }
+                for entry in read_dir.flatten() {
+                    if let Ok(file_type) = entry.file_type() {
+                        if file_type.is_dir() {
+                            if let Some(name) = entry.file_name().to_str() {
+                                // Skip hidden directories
+                                if !name.starts_with('.') {
+                                    dirs.push(name.to_string());
                                 }
                             }
                         }
@@ -0,0 +1,351 @@
+// Learner preferences
+//
+// The exercise framework's `UserPreferences`, kept in the profile's progress as
+// `preferences` and edited with `GET`/`PUT /api/preferences`. A `PUT` only
+// changes the fields it contains; every change is announced to the profile's
+// clients as `preferences_changed` and written to the event log.
+//
+// The server enforces the hint preference rather than leaving it to the UI:
+// exercise details served with `disabled` (hard mode) carry no hints at all,
+// with `minimal` only the conceptual first level. `progressive` and `detailed`
+// get every level. Recording a hint level the preference withholds is refused
+// with 403. `auto_advance` is only read by the UI, which opens the next
+// exercise after a completion instead of offering to.
+
+use axum::{extract::State, http::StatusCode, Json};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use tracing::{error, info};
+
+use crate::{
+    event_log::LearnerEventKind, log_learner_event, profile_progress, profiles::ActiveProfile, AppState,
+    ExerciseDetails, ServerMessage,
+};
+
+const MAX_SETTING_LENGTH: usize = 32;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(default)]
+pub struct UserPreferences {
+    pub difficulty_preference: DifficultyPreference,
+    pub hint_usage: HintPreference,
+    pub theme: String,
+    pub language: String,
+    pub notifications_enabled: bool,
+    pub auto_advance: bool,
+    pub practice_reminders: bool,
+}
+
+impl Default for UserPreferences {
+    fn default() -> Self {
+        Self {
+            difficulty_preference: DifficultyPreference::default(),
+            hint_usage: HintPreference::default(),
+            theme: "rust".to_string(),
+            language: "en".to_string(),
+            notifications_enabled: true,
+            auto_advance: false,
+            practice_reminders: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DifficultyPreference {
+    // Chosen from the learner's performance
+    #[default]
+    Adaptive,
+    // Gradually harder
+    Progressive,
+    // A fixed difficulty
+    Consistent,
+    // Harder exercises first
+    Challenge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HintPreference {
+    // Conceptual hints only
+    Minimal,
+    // All three levels, one after another
+    #[default]
+    Progressive,
+    // Implementation hints welcome
+    Detailed,
+    // No hints (hard mode)
+    Disabled,
+}
+
+impl HintPreference {
+    // Highest hint level served; `None` serves every level
+    pub fn max_level(self) -> Option<u32> {
+        match self {
+            HintPreference::Disabled => Some(0),
+            HintPreference::Minimal => Some(1),
+            HintPreference::Progressive | HintPreference::Detailed => None,
+        }
+    }
+
+    pub fn allows(self, level: u32) -> bool {
+        self.max_level().is_none_or(|max| level <= max)
+    }
+}
+
+// Fields of `PUT /api/preferences`; missing ones are left as they are
+#[derive(Debug, Default, Deserialize)]
+pub struct PreferencesUpdate {
+    difficulty_preference: Option<DifficultyPreference>,
+    hint_usage: Option<HintPreference>,
+    theme: Option<String>,
+    language: Option<String>,
+    notifications_enabled: Option<bool>,
+    auto_advance: Option<bool>,
+    practice_reminders: Option<bool>,
+}
+
+impl PreferencesUpdate {
+    fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("theme", &self.theme), ("language", &self.language)] {
+            if let Some(value) = value {
+                if value.trim().is_empty() || value.len() > MAX_SETTING_LENGTH {
+                    return Err(format!("{} must be 1 to {} characters", name, MAX_SETTING_LENGTH));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn apply(self, preferences: &mut UserPreferences) {
+        if let Some(difficulty_preference) = self.difficulty_preference {
+            preferences.difficulty_preference = difficulty_preference;
+        }
+        if let Some(hint_usage) = self.hint_usage {
+            preferences.hint_usage = hint_usage;
+        }
+        if let Some(theme) = self.theme {
+            preferences.theme = theme.trim().to_string();
+        }
+        if let Some(language) = self.language {
+            preferences.language = language.trim().to_string();
+        }
+        if let Some(notifications_enabled) = self.notifications_enabled {
+            preferences.notifications_enabled = notifications_enabled;
+        }
+        if let Some(auto_advance) = self.auto_advance {
+            preferences.auto_advance = auto_advance;
+        }
+        if let Some(practice_reminders) = self.practice_reminders {
+            preferences.practice_reminders = practice_reminders;
+        }
+    }
+}
+
+// Drop the hints the preference withholds from exercise details
+pub fn withhold_hints(details: &mut ExerciseDetails, preference: HintPreference) {
+    let Some(max_level) = preference.max_level() else {
+        return;
+    };
+    details.hints = hints_up_to(&details.hints, max_level);
+    if let Some(available) = details.metadata.hints.get_mut("available") {
+        if let Some(count) = available.as_u64() {
+            *available = serde_json::Value::from(count.min(max_level as u64));
+        }
+    }
+}
+
+// `hints.md` up to the first "## Level <n>:" section above `max_level`;
+// nothing at all for level 0
+fn hints_up_to(hints: &str, max_level: u32) -> String {
+    if max_level == 0 {
+        return String::new();
+    }
+    let mut kept = String::new();
+    for line in hints.lines() {
+        let level = line
+            .strip_prefix("## Level ")
+            .and_then(|rest| rest.split(':').next())
+            .and_then(|level| level.trim().parse::<u32>().ok());
+        if level.is_some_and(|level| level > max_level) {
+            break;
+        }
+        kept.push_str(line);
+        kept.push('\n');
+    }
+    kept.truncate(kept.trim_end().len());
+    kept.push('\n');
+    kept
+}
+
+// The profile's hint preference, for the handlers that serve or record hints
+pub async fn hint_preference(state: &AppState, profile: &str) -> Result<HintPreference, StatusCode> {
+    let progress = profile_progress(state, profile).await?;
+    progress
+        .snapshot()
+        .await
+        .map(|progress| progress.preferences.hint_usage)
+        .map_err(|e| {
+            error!("Error loading preferences for profile {}: {}", profile, e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+// API handlers
+pub async fn get_preferences(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+) -> Result<Json<UserPreferences>, StatusCode> {
+    let progress = profile_progress(&state, &profile).await?;
+    match progress.snapshot().await {
+        Ok(progress) => Ok(Json(progress.preferences)),
+        Err(e) => {
+            error!("Error loading preferences for profile {}: {}", profile, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn update_preferences(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Json(request): Json<PreferencesUpdate>,
+) -> Result<Json<UserPreferences>, (StatusCode, String)> {
+    request.validate().map_err(|message| (StatusCode::BAD_REQUEST, message))?;
+
+    let progress = profile_progress(&state, &profile)
+        .await
+        .map_err(|status| (status, "Failed to open progress".to_string()))?;
+    let changed = Arc::new(AtomicBool::new(false));
+    let updated = {
+        let changed = changed.clone();
+        progress
+            .update(move |progress| {
+                let mut preferences = progress.preferences.clone();
+                request.apply(&mut preferences);
+                if preferences == progress.preferences {
+                    return false;
+                }
+                progress.preferences = preferences;
+                changed.store(true, Ordering::Relaxed);
+                true
+            })
+            .await
+    };
+    let preferences = match updated {
+        Ok(progress) => progress.preferences,
+        Err(e) => {
+            error!("Error saving preferences for profile {}: {}", profile, e);
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, "Failed to save preferences".to_string()));
+        }
+    };
+
+    if changed.load(Ordering::Relaxed) {
+        info!("Preferences of profile {} changed: {:?}", profile, preferences);
+        let _ = state.broadcast_tx.send(ServerMessage::PreferencesChanged {
+            profile: profile.clone(),
+            preferences: preferences.clone(),
+        });
+        let event = LearnerEventKind::PreferencesChanged {
+            preferences: preferences.clone(),
+        };
+        log_learner_event(&state, &profile, event).await;
+    }
+    Ok(Json(preferences))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HINTS: &str = "# Hints for Hello World
+
+## Level 1: Conceptual Hint
+Think about what `println!` does.
+
+## Level 2: Strategic Hint
+Print the exact text the test expects.
+
+## Level 3: Implementation Hint
+```rust
+println!(\"Hello, world!\");
+```
+";
+
+    fn details() -> ExerciseDetails {
+        let metadata = serde_json::from_value(serde_json::json!({
+            "id": "ch01-ex01-hello-world",
+            "title": "Hello World",
+            "description": "Print a greeting",
+            "chapter": 1,
+            "exercise_number": 1,
+            "difficulty": "beginner",
+            "estimated_time_minutes": 5,
+            "concepts": [],
+            "prerequisites": [],
+            "exercise_type": "code_completion",
+            "hints": {"available": 3}
+        }))
+        .expect("valid metadata");
+        ExerciseDetails {
+            metadata,
+            main_content: String::new(),
+            readme: String::new(),
+            hints: HINTS.to_string(),
+            path: "ch01_getting_started/ex01_hello_world".to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn hints_are_cut_before_the_first_withheld_level() {
+        let level_1 = hints_up_to(HINTS, 1);
+        assert!(level_1.starts_with("# Hints for Hello World"));
+        assert!(level_1.contains("## Level 1: Conceptual Hint"));
+        assert!(!level_1.contains("## Level 2"));
+        assert!(level_1.ends_with("Think about what `println!` does.\n"));
+
+        let level_2 = hints_up_to(HINTS, 2);
+        assert!(level_2.contains("Print the exact text"));
+        assert!(!level_2.contains("## Level 3"));
+
+        assert_eq!(hints_up_to(HINTS, 3).trim_end(), HINTS.trim_end());
+        assert_eq!(hints_up_to(HINTS, 0), "");
+    }
+
+    #[test]
+    fn only_level_headings_end_the_kept_hints() {
+        let hints = "## Level 1: Idea\nSee ## Level 2: below\n## Level two: not a number\n## Level 2:Tight\nsecret\n";
+        let kept = hints_up_to(hints, 1);
+        assert!(kept.contains("See ## Level 2: below"));
+        assert!(kept.contains("## Level two: not a number"));
+        assert!(!kept.contains("secret"));
+    }
+
+    #[test]
+    fn hints_are_withheld_per_preference() {
+        let available = |details: &ExerciseDetails| details.metadata.hints["available"].clone();
+
+        for preference in [HintPreference::Progressive, HintPreference::Detailed] {
+            let mut served = details();
+            withhold_hints(&mut served, preference);
+            assert_eq!(served.hints, HINTS);
+            assert_eq!(available(&served), 3);
+        }
+
+        let mut minimal = details();
+        withhold_hints(&mut minimal, HintPreference::Minimal);
+        assert!(minimal.hints.contains("## Level 1"));
+        assert!(!minimal.hints.contains("## Level 2"));
+        assert_eq!(available(&minimal), 1);
+
+        let mut disabled = details();
+        withhold_hints(&mut disabled, HintPreference::Disabled);
+        assert_eq!(disabled.hints, "");
+        assert_eq!(available(&disabled), 0);
+    }
+}
@@ -0,0 +1,116 @@
+// Connection presence
+//
+// Tracks who is connected over WebSocket: when they connected, when they last
+// sent anything, the last time the socket answered at all (including pongs to
+// the server's pings) and which exercise they are looking at. Served at
+// `/api/presence` for a local dashboard.
+
+use axum::{extract::State, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+use crate::{AppState, ConnectionId};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionPresence {
+    pub connected_at: DateTime<Utc>,
+    // Last message sent by the client
+    pub last_activity: DateTime<Utc>,
+    // Last frame of any kind, pongs included
+    pub last_seen: DateTime<Utc>,
+    pub current_exercise: Option<String>,
+    pub client: Option<String>,
+    pub protocol_version: Option<u32>,
+    // Learner profile the connection was opened with
+    pub profile: String,
+}
+
+impl ConnectionPresence {
+    pub fn new(profile: String) -> Self {
+        let now = Utc::now();
+        Self {
+            connected_at: now,
+            last_activity: now,
+            last_seen: now,
+            current_exercise: None,
+            client: None,
+            protocol_version: None,
+            profile,
+        }
+    }
+}
+
+async fn update_presence(state: &AppState, connection_id: ConnectionId, update: impl FnOnce(&mut ConnectionPresence)) {
+    if let Some(connection) = state.connections.write().await.get_mut(&connection_id) {
+        update(&mut connection.presence);
+    }
+}
+
+pub async fn record_activity(state: &AppState, connection_id: ConnectionId) {
+    update_presence(state, connection_id, |presence| {
+        let now = Utc::now();
+        presence.last_activity = now;
+        presence.last_seen = now;
+    })
+    .await;
+}
+
+pub async fn record_seen(state: &AppState, connection_id: ConnectionId) {
+    update_presence(state, connection_id, |presence| presence.last_seen = Utc::now()).await;
+}
+
+pub async fn record_hello(state: &AppState, connection_id: ConnectionId, protocol_version: u32, client: Option<String>) {
+    update_presence(state, connection_id, |presence| {
+        presence.protocol_version = Some(protocol_version);
+        presence.client = client;
+    })
+    .await;
+}
+
+pub async fn record_exercise_view(state: &AppState, connection_id: ConnectionId, exercise_id: &str) {
+    update_presence(state, connection_id, |presence| {
+        presence.current_exercise = Some(exercise_id.to_string());
+    })
+    .await;
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceEntry {
+    connection_id: String,
+    #[serde(flatten)]
+    presence: ConnectionPresence,
+    // Only a count: session ids would let anyone address other people's shells
+    terminal_sessions: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PresenceResponse {
+    count: usize,
+    connections: Vec<PresenceEntry>,
+}
+
+// API handler
+pub async fn list_presence(State(state): State<AppState>) -> Json<PresenceResponse> {
+    let sessions = state.terminal_sessions.read().await;
+    let mut connections: Vec<PresenceEntry> = state
+        .connections
+        .read()
+        .await
+        .iter()
+        .map(|(connection_id, connection)| PresenceEntry {
+            connection_id: connection_id.to_string(),
+            presence: connection.presence.clone(),
+            terminal_sessions: sessions
+                .values()
+                .filter(|session| session.connection_id == *connection_id)
+                .count(),
+        })
+        .collect();
+
+    connections.sort_by_key(|entry| entry.presence.connected_at);
+
+    Json(PresenceResponse {
+        count: connections.len(),
+        connections,
+    })
+}
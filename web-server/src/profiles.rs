@@ -0,0 +1,615 @@
+// Learner profiles
+//
+// Several people can share one installation, each with their own progress and
+// their own copy of the exercises they edit. Every request picks a profile
+// with the `X-Rust-Tour-Profile` header or the `rust_tour_profile` cookie
+// (set by `POST /api/profiles/switch`); without either it uses `default`.
+//
+// The `default` profile keeps the original layout: `progress/user_progress.json`
+// and edits written straight into the exercises directory. Other profiles live
+// in `progress/profiles/<name>/`, with their progress next to a `profile.json`
+// and, under `exercises/`, a private copy of each exercise they have saved.
+// Until a profile edits an exercise it reads and runs the shared one.
+//
+// Before the default profile first edits an exercise, the exercise is copied to
+// `progress/pristine/`, so a reset can put the original sources back. Other
+// profiles get theirs back by dropping their copy.
+//
+// At startup the `tests/` directory of every exercise is also copied once to
+// `progress/original-tests/`. That is what edited tests are detected against,
+// including edits made outside the server, where no pristine copy exists.
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, State},
+    http::{header, request::Parts, HeaderMap, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{fs, sync::Mutex};
+use tracing::{error, info};
+use uuid::Uuid;
+use walkdir::WalkDir;
+
+use crate::{
+    progress_store::{ProgressHandle, ProgressOptions},
+    ApiResponse, AppState, ConnectionId,
+};
+
+pub const DEFAULT_PROFILE: &str = "default";
+pub const PROFILE_HEADER: &str = "x-rust-tour-profile";
+pub const PROFILE_COOKIE: &str = "rust_tour_profile";
+const PROFILE_COOKIE_MAX_AGE: u64 = 365 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProfileInfo {
+    pub name: String,
+    pub display_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+// Opens each profile's progress store on first use and keeps it open
+#[derive(Clone)]
+pub struct ProfileRegistry {
+    options: ProgressOptions,
+    // `progress/user_progress.json`, the default profile's file
+    default_progress_path: PathBuf,
+    exercises_path: PathBuf,
+    stores: Arc<Mutex<HashMap<String, ProgressHandle>>>,
+}
+
+impl ProfileRegistry {
+    pub async fn open(
+        options: ProgressOptions,
+        default_progress_path: PathBuf,
+        exercises_path: PathBuf,
+    ) -> anyhow::Result<Self> {
+        let registry = Self {
+            options,
+            default_progress_path,
+            exercises_path,
+            stores: Arc::new(Mutex::new(HashMap::new())),
+        };
+        // Open the default store eagerly so a locked or corrupt progress file stops startup
+        registry.progress(DEFAULT_PROFILE).await?;
+        registry.snapshot_tests().await?;
+        Ok(registry)
+    }
+
+    fn profiles_dir(&self) -> PathBuf {
+        self.default_progress_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("profiles")
+    }
+
+    fn profile_dir(&self, name: &str) -> PathBuf {
+        self.profiles_dir().join(name)
+    }
+
+    // The JSON progress path for a profile; other backends sit next to it
+    pub fn progress_path(&self, name: &str) -> PathBuf {
+        if name == DEFAULT_PROFILE {
+            self.default_progress_path.clone()
+        } else {
+            self.profile_dir(name).join("user_progress.json")
+        }
+    }
+
+    // Original copies of the exercises the default profile has edited
+    fn pristine_dir(&self) -> PathBuf {
+        self.default_progress_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("pristine")
+    }
+
+    // Test files of every exercise as they were the first time the server saw it
+    fn original_tests_dir(&self) -> PathBuf {
+        self.default_progress_path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("original-tests")
+    }
+
+    // Copy the tests of exercises that have no snapshot yet
+    async fn snapshot_tests(&self) -> anyhow::Result<()> {
+        let (exercises, snapshots) = (self.exercises_path.clone(), self.original_tests_dir());
+        let copied = tokio::task::spawn_blocking(move || snapshot_missing_tests(&exercises, &snapshots)).await??;
+        if copied > 0 {
+            info!("Kept the original tests of {} exercises", copied);
+        }
+        Ok(())
+    }
+
+    // Where a profile keeps its edited exercises; `None` for the default profile,
+    // which edits the shared exercises directly
+    fn exercises_overlay(&self, name: &str) -> Option<PathBuf> {
+        (name != DEFAULT_PROFILE).then(|| self.profile_dir(name).join("exercises"))
+    }
+
+    pub fn options(&self) -> &ProgressOptions {
+        &self.options
+    }
+
+    pub fn exercises_path(&self) -> &Path {
+        &self.exercises_path
+    }
+
+    // "chapter/exercise" paths the profile has its own copy of
+    pub async fn edited_exercises(&self, name: &str) -> anyhow::Result<Vec<String>> {
+        let Some(overlay) = self.exercises_overlay(name) else {
+            return Ok(Vec::new());
+        };
+        if !overlay.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut edited = Vec::new();
+        let mut chapters = fs::read_dir(&overlay).await?;
+        while let Some(chapter) = chapters.next_entry().await? {
+            if !chapter.file_type().await?.is_dir() {
+                continue;
+            }
+            let mut exercises = fs::read_dir(chapter.path()).await?;
+            while let Some(exercise) = exercises.next_entry().await? {
+                let name = exercise.file_name().to_string_lossy().to_string();
+                // Skip unfinished copies
+                if exercise.file_type().await?.is_dir() && !name.starts_with('.') {
+                    edited.push(format!("{}/{}", chapter.file_name().to_string_lossy(), name));
+                }
+            }
+        }
+        edited.sort();
+        Ok(edited)
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        name == DEFAULT_PROFILE || self.profile_dir(name).join("profile.json").exists()
+    }
+
+    pub async fn progress(&self, name: &str) -> anyhow::Result<ProgressHandle> {
+        let mut stores = self.stores.lock().await;
+        if let Some(store) = stores.get(name) {
+            return Ok(store.clone());
+        }
+
+        let store = ProgressHandle::open(
+            self.options.clone(),
+            self.progress_path(name),
+            &self.exercises_path,
+            name,
+        )
+        .await?;
+        stores.insert(name.to_string(), store.clone());
+        Ok(store)
+    }
+
+    pub async fn list(&self) -> anyhow::Result<Vec<ProfileInfo>> {
+        let mut profiles = vec![ProfileInfo {
+            name: DEFAULT_PROFILE.to_string(),
+            display_name: "Default".to_string(),
+            created_at: None,
+        }];
+
+        let profiles_dir = self.profiles_dir();
+        if profiles_dir.exists() {
+            let mut entries = fs::read_dir(&profiles_dir).await?;
+            while let Some(entry) = entries.next_entry().await? {
+                let info_path = entry.path().join("profile.json");
+                let Ok(content) = fs::read_to_string(&info_path).await else {
+                    continue;
+                };
+                match serde_json::from_str::<ProfileInfo>(&content) {
+                    Ok(info) => profiles.push(info),
+                    Err(e) => error!("Ignoring invalid profile file {}: {}", info_path.display(), e),
+                }
+            }
+        }
+
+        profiles[1..].sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(profiles)
+    }
+
+    pub async fn info(&self, name: &str) -> anyhow::Result<Option<ProfileInfo>> {
+        Ok(self.list().await?.into_iter().find(|profile| profile.name == name))
+    }
+
+    async fn create(&self, name: &str, display_name: Option<String>) -> anyhow::Result<ProfileInfo> {
+        let dir = self.profile_dir(name);
+        fs::create_dir_all(&dir).await?;
+
+        let info = ProfileInfo {
+            name: name.to_string(),
+            display_name: display_name.unwrap_or_else(|| name.to_string()),
+            created_at: Some(Utc::now().to_rfc3339()),
+        };
+        fs::write(dir.join("profile.json"), serde_json::to_string_pretty(&info)?).await?;
+        info!("Created profile {}", name);
+        Ok(info)
+    }
+
+    // Directory holding the exercises the profile writes to
+    pub async fn exercises_root(&self, profile: &str) -> anyhow::Result<PathBuf> {
+        match self.exercises_overlay(profile) {
+            Some(overlay) => {
+                fs::create_dir_all(&overlay).await?;
+                Ok(overlay)
+            }
+            None => Ok(self.exercises_path.clone()),
+        }
+    }
+
+    // Directory of `exercise_path` ("chapter/exercise") as the profile sees it
+    pub fn exercise_dir(&self, profile: &str, exercise_path: &str) -> PathBuf {
+        if let Some(overlay) = self.exercises_overlay(profile) {
+            let copy = overlay.join(exercise_path);
+            if copy.exists() {
+                return copy;
+            }
+        }
+        self.exercises_path.join(exercise_path)
+    }
+
+    // Directory to write `exercise_path` to, copying the shared exercise into the
+    // profile first if this is its first edit
+    pub async fn writable_exercise_dir(&self, profile: &str, exercise_path: &str) -> anyhow::Result<PathBuf> {
+        let source = self.exercises_path.join(exercise_path);
+        let Some(overlay) = self.exercises_overlay(profile) else {
+            let pristine = self.pristine_dir().join(exercise_path);
+            if source.is_dir() && !pristine.exists() {
+                copy_exercise_once(&source, &pristine).await?;
+                info!("Kept the original sources of {}", exercise_path);
+            }
+            return Ok(source);
+        };
+
+        let copy = overlay.join(exercise_path);
+        if copy.exists() {
+            return Ok(copy);
+        }
+
+        if !source.is_dir() {
+            anyhow::bail!("Unknown exercise: {}", exercise_path);
+        }
+
+        copy_exercise_once(&source, &copy).await?;
+        info!("Profile {} now has its own copy of {}", profile, exercise_path);
+        Ok(copy)
+    }
+
+    // Whether the profile changed the exercise's sources; `None` when the default
+    // profile has no pristine copy to compare with, e.g. when the learner works
+    // in their own editor
+    pub async fn is_edited(&self, profile: &str, exercise_path: &str) -> anyhow::Result<Option<bool>> {
+        if let Some(overlay) = self.exercises_overlay(profile) {
+            return Ok(Some(overlay.join(exercise_path).exists()));
+        }
+
+        let pristine = self.pristine_dir().join(exercise_path);
+        if !pristine.exists() {
+            return Ok(None);
+        }
+        let target = self.exercises_path.join(exercise_path);
+        let differing = tokio::task::spawn_blocking(move || differing_files(&pristine, &target)).await??;
+        Ok(Some(!differing.is_empty()))
+    }
+
+    // Test files of the exercise that differ from the original, so passing tests
+    // can't be had by editing them. Fails when there is nothing to compare with,
+    // e.g. for an exercise added after startup that was never written to.
+    pub async fn modified_tests(&self, profile: &str, exercise_path: &str) -> anyhow::Result<Vec<String>> {
+        let snapshot = self.original_tests_dir().join(exercise_path);
+        let original = if snapshot.is_dir() {
+            snapshot
+        } else {
+            let pristine = self.pristine_dir().join(exercise_path);
+            if !pristine.is_dir() {
+                anyhow::bail!("No original copy of {} to compare its tests with", exercise_path);
+            }
+            pristine.join("tests")
+        };
+        let current = self.exercise_dir(profile, exercise_path).join("tests");
+        let differing = tokio::task::spawn_blocking(move || differing_files(&original, &current)).await??;
+        Ok(differing.into_iter().map(|file| format!("tests/{}", file)).collect())
+    }
+
+    // Put back the original sources of `exercise_path` for the profile. Returns
+    // the files that were restored, empty when it was never edited.
+    pub async fn restore_exercise(&self, profile: &str, exercise_path: &str) -> anyhow::Result<Vec<String>> {
+        if let Some(overlay) = self.exercises_overlay(profile) {
+            let copy = overlay.join(exercise_path);
+            if !copy.exists() {
+                return Ok(Vec::new());
+            }
+            let listed = copy.clone();
+            let files = tokio::task::spawn_blocking(move || exercise_files(&listed)).await??;
+            fs::remove_dir_all(&copy).await?;
+            info!("Profile {} dropped its copy of {}", profile, exercise_path);
+            return Ok(files);
+        }
+
+        let pristine = self.pristine_dir().join(exercise_path);
+        if !pristine.exists() {
+            return Ok(Vec::new());
+        }
+        let target = self.exercises_path.join(exercise_path);
+        let files = tokio::task::spawn_blocking(move || restore_files(&pristine, &target)).await??;
+        info!("Restored the original sources of {}", exercise_path);
+        Ok(files)
+    }
+}
+
+// Copy next to the destination and rename, so a half-finished copy is never used
+async fn copy_exercise_once(source: &Path, destination: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent).await?;
+    }
+    let staging = destination.with_file_name(format!(".copy-{}", Uuid::new_v4()));
+    let (from, to) = (source.to_path_buf(), staging.clone());
+    tokio::task::spawn_blocking(move || copy_exercise(&from, &to)).await??;
+    if let Err(e) = fs::rename(&staging, destination).await {
+        let _ = fs::remove_dir_all(&staging).await;
+        // Another request made the copy first
+        if !destination.exists() {
+            return Err(e.into());
+        }
+    }
+    Ok(())
+}
+
+// Copy `tests/` of each "chapter/exercise" under `exercises` that has no
+// snapshot under `snapshots` yet; exercises without tests get an empty one
+fn snapshot_missing_tests(exercises: &Path, snapshots: &Path) -> anyhow::Result<usize> {
+    let mut copied = 0;
+    if !exercises.is_dir() {
+        return Ok(copied);
+    }
+    for chapter in std::fs::read_dir(exercises)? {
+        let chapter = chapter?;
+        if !chapter.file_type()?.is_dir() {
+            continue;
+        }
+        for exercise in std::fs::read_dir(chapter.path())? {
+            let exercise = exercise?;
+            if !exercise.file_type()?.is_dir() || !exercise.path().join("Cargo.toml").exists() {
+                continue;
+            }
+            let snapshot = snapshots.join(chapter.file_name()).join(exercise.file_name());
+            if snapshot.exists() {
+                continue;
+            }
+            // Copy next to the destination and rename, like `copy_exercise_once`
+            let staging = snapshot.with_file_name(format!(".copy-{}", Uuid::new_v4()));
+            std::fs::create_dir_all(&staging)?;
+            let tests = exercise.path().join("tests");
+            if tests.is_dir() {
+                copy_exercise(&tests, &staging)?;
+            }
+            std::fs::rename(&staging, &snapshot)?;
+            copied += 1;
+        }
+    }
+    Ok(copied)
+}
+
+// Files of an exercise relative to its directory, without build output
+// (`target/` and the `Cargo.lock` the first build writes)
+fn exercise_files(dir: &Path) -> anyhow::Result<Vec<String>> {
+    let mut files = Vec::new();
+    if !dir.exists() {
+        return Ok(files);
+    }
+    for entry in WalkDir::new(dir).into_iter().filter_entry(|entry| entry.file_name() != "target") {
+        let entry = entry?;
+        if entry.file_type().is_file() && entry.path() != dir.join("Cargo.lock") {
+            files.push(entry.path().strip_prefix(dir)?.to_string_lossy().replace('\\', "/"));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+// Files of `target` that differ from `pristine`: changed, deleted or added
+fn differing_files(pristine: &Path, target: &Path) -> anyhow::Result<Vec<String>> {
+    let original = exercise_files(pristine)?;
+    let mut differing: Vec<String> = exercise_files(target)?
+        .into_iter()
+        .filter(|file| !original.contains(file))
+        .collect();
+    for file in original {
+        if std::fs::read(target.join(&file)).ok() != Some(std::fs::read(pristine.join(&file))?) {
+            differing.push(file);
+        }
+    }
+    differing.sort();
+    Ok(differing)
+}
+
+// Make `target` match `pristine` again: rewrite changed files, bring back
+// deleted ones and remove the ones the learner added
+fn restore_files(pristine: &Path, target: &Path) -> anyhow::Result<Vec<String>> {
+    let differing = differing_files(pristine, target)?;
+    for file in &differing {
+        let (from, to) = (pristine.join(file), target.join(file));
+        if from.exists() {
+            if let Some(parent) = to.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::copy(&from, &to)?;
+        } else {
+            std::fs::remove_file(&to)?;
+        }
+    }
+    Ok(differing)
+}
+
+// Copy an exercise without its build output
+fn copy_exercise(from: &Path, to: &Path) -> anyhow::Result<()> {
+    for entry in WalkDir::new(from).into_iter().filter_entry(|entry| entry.file_name() != "target") {
+        let entry = entry?;
+        let target = to.join(entry.path().strip_prefix(from)?);
+        if entry.file_type().is_dir() {
+            std::fs::create_dir_all(&target)?;
+        } else if entry.file_type().is_file() {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    Ok(())
+}
+
+// Profile names end up in paths and cookies
+pub fn is_valid_profile_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 32
+        && name.starts_with(|c: char| c.is_ascii_lowercase() || c.is_ascii_digit())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_')
+}
+
+fn requested_profile(headers: &HeaderMap) -> Option<String> {
+    if let Some(name) = headers.get(PROFILE_HEADER).and_then(|value| value.to_str().ok()) {
+        return Some(name.trim().to_string());
+    }
+
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|cookies| cookies.split(';'))
+        .filter_map(|cookie| cookie.trim().split_once('='))
+        .find(|(name, _)| *name == PROFILE_COOKIE)
+        .map(|(_, value)| value.trim().to_string())
+}
+
+// The profile a request acts for
+pub struct ActiveProfile(pub String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for ActiveProfile {
+    type Rejection = (StatusCode, String);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let Some(name) = requested_profile(&parts.headers) else {
+            return Ok(ActiveProfile(DEFAULT_PROFILE.to_string()));
+        };
+
+        if !is_valid_profile_name(&name) {
+            return Err((StatusCode::BAD_REQUEST, format!("Invalid profile name: {}", name)));
+        }
+        if !state.profiles.exists(&name) {
+            return Err((StatusCode::NOT_FOUND, format!("Unknown profile: {}", name)));
+        }
+        Ok(ActiveProfile(name))
+    }
+}
+
+// Profile a WebSocket connection was opened with
+pub async fn connection_profile(state: &AppState, connection_id: ConnectionId) -> String {
+    state
+        .connections
+        .read()
+        .await
+        .get(&connection_id)
+        .map(|connection| connection.presence.profile.clone())
+        .unwrap_or_else(|| DEFAULT_PROFILE.to_string())
+}
+
+// Key for per-exercise server state, such as collaborative documents, that must
+// not be shared between profiles
+pub fn scoped_exercise_path(profile: &str, exercise_path: &str) -> String {
+    if profile == DEFAULT_PROFILE {
+        exercise_path.to_string()
+    } else {
+        format!("~{}/{}", profile, exercise_path)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProfilesResponse {
+    active: String,
+    profiles: Vec<ProfileInfo>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateProfileRequest {
+    name: String,
+    #[serde(default)]
+    display_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SwitchProfileRequest {
+    name: String,
+}
+
+// API handlers
+pub async fn list_profiles(
+    State(state): State<AppState>,
+    ActiveProfile(active): ActiveProfile,
+) -> Result<Json<ProfilesResponse>, StatusCode> {
+    match state.profiles.list().await {
+        Ok(profiles) => Ok(Json(ProfilesResponse { active, profiles })),
+        Err(e) => {
+            error!("Error listing profiles: {}", e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+pub async fn create_profile(
+    State(state): State<AppState>,
+    Json(request): Json<CreateProfileRequest>,
+) -> Result<Json<ApiResponse<ProfileInfo>>, StatusCode> {
+    if !is_valid_profile_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    if state.profiles.exists(&request.name) {
+        return Err(StatusCode::CONFLICT);
+    }
+
+    match state.profiles.create(&request.name, request.display_name).await {
+        Ok(info) => Ok(Json(ApiResponse::success(info))),
+        Err(e) => {
+            error!("Error creating profile {}: {}", request.name, e);
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+// Remember the profile in a cookie for the browser's later requests
+pub async fn switch_profile(
+    State(state): State<AppState>,
+    Json(request): Json<SwitchProfileRequest>,
+) -> Result<Response, StatusCode> {
+    if !is_valid_profile_name(&request.name) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+
+    let info = match state.profiles.info(&request.name).await {
+        Ok(Some(info)) => info,
+        Ok(None) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => {
+            error!("Error loading profile {}: {}", request.name, e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let cookie = format!(
+        "{}={}; Path=/; Max-Age={}; SameSite=Lax",
+        PROFILE_COOKIE, info.name, PROFILE_COOKIE_MAX_AGE
+    );
+    let cookie = HeaderValue::from_str(&cookie).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    info!("Switched to profile {}", info.name);
+    Ok(([(header::SET_COOKIE, cookie)], Json(ApiResponse::success(info))).into_response())
+}
@@ -0,0 +1,298 @@
+// Progress change events
+//
+// Every change the progress task saves is announced to the profile's clients
+// as one `progress_changed` message: what changed, worked out by comparing the
+// progress before and after the mutation, and the new aggregate numbers. Tabs
+// and devices of the same learner stay in sync without reloading, whichever
+// of them (or the server itself, for test runs and reconciling) made the
+// change. Because the changes are derived from the saved progress rather than
+// reported by each handler, no mutation can forget to announce itself.
+//
+// Changes that don't map to one of the kinds below (activity time, imports of
+// sessions) still send the message, with the new totals and an empty list.
+
+use schemars::JsonSchema;
+use serde::Serialize;
+use std::collections::HashMap;
+
+use crate::{reset::ResetScope, CompletionSource, ExerciseHistoryEntry, ProgressData};
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ProgressChange {
+    ExerciseViewed {
+        exercise_id: String,
+        viewed_at: String,
+    },
+    ExerciseCompleted {
+        exercise_id: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        completed_by: Option<CompletionSource>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        time_taken_minutes: Option<u32>,
+    },
+    // Hint levels opened for the first time
+    HintUsed {
+        exercise_id: String,
+        levels: Vec<u32>,
+    },
+    TestRunRecorded {
+        exercise_id: String,
+        compiled: bool,
+        passed: u32,
+        failed: u32,
+    },
+    ProgressReset {
+        #[serde(flatten)]
+        scope: ResetScope,
+        exercises: Vec<String>,
+    },
+    AchievementUnlocked {
+        achievement_id: String,
+        points: u32,
+    },
+}
+
+// The numbers the progress display shows
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct ProgressTotals {
+    pub overall_progress: f64,
+    pub exercises_completed: u32,
+    pub total_exercises: u32,
+    pub chapters_completed: u32,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub total_time_minutes: u32,
+    pub achievements_earned: u32,
+    pub achievement_points: u32,
+}
+
+impl ProgressTotals {
+    pub fn of(progress: &ProgressData) -> Self {
+        Self {
+            overall_progress: progress.overall_progress,
+            exercises_completed: progress.exercises_completed,
+            total_exercises: progress.total_exercises,
+            chapters_completed: progress.chapters_completed,
+            current_streak: progress.current_streak,
+            longest_streak: progress.longest_streak,
+            total_time_minutes: progress.total_time_minutes,
+            achievements_earned: progress.achievements.len() as u32,
+            achievement_points: progress.achievements.iter().map(|achievement| achievement.points).sum(),
+        }
+    }
+}
+
+// What changed from `previous` to `current`; `None` when the client has nothing to update
+pub fn progress_delta(previous: &ProgressData, current: &ProgressData) -> Option<(Vec<ProgressChange>, ProgressTotals)> {
+    let mut changes = Vec::new();
+
+    // A reset removes entries; announce it rather than each entry it cleared
+    for reset in current.resets.iter().skip(previous.resets.len()) {
+        changes.push(ProgressChange::ProgressReset {
+            scope: reset.scope.clone(),
+            exercises: reset.exercises.clone(),
+        });
+    }
+
+    let before: HashMap<&str, &ExerciseHistoryEntry> = previous
+        .exercise_history
+        .iter()
+        .map(|entry| (entry.exercise_id.as_str(), entry))
+        .collect();
+    for entry in &current.exercise_history {
+        entry_changes(before.get(entry.exercise_id.as_str()).copied(), entry, &mut changes);
+    }
+
+    for achievement in &current.achievements {
+        if !previous.achievements.iter().any(|earned| earned.id == achievement.id) {
+            changes.push(ProgressChange::AchievementUnlocked {
+                achievement_id: achievement.id.clone(),
+                points: achievement.points,
+            });
+        }
+    }
+
+    let totals = ProgressTotals::of(current);
+    if changes.is_empty() && totals == ProgressTotals::of(previous) {
+        return None;
+    }
+    Some((changes, totals))
+}
+
+fn entry_changes(before: Option<&ExerciseHistoryEntry>, entry: &ExerciseHistoryEntry, changes: &mut Vec<ProgressChange>) {
+    let exercise_id = &entry.exercise_id;
+
+    if let Some(viewed_at) = &entry.viewed_at {
+        if before.is_none_or(|before| before.viewed_at.as_ref() != Some(viewed_at)) {
+            changes.push(ProgressChange::ExerciseViewed {
+                exercise_id: exercise_id.clone(),
+                viewed_at: viewed_at.clone(),
+            });
+        }
+    }
+
+    let hints_before = before.and_then(|before| before.hints_used.as_deref()).unwrap_or_default();
+    let levels: Vec<u32> = entry
+        .hints_used
+        .iter()
+        .flatten()
+        .filter(|level| !hints_before.contains(level))
+        .copied()
+        .collect();
+    if !levels.is_empty() {
+        changes.push(ProgressChange::HintUsed {
+            exercise_id: exercise_id.clone(),
+            levels,
+        });
+    }
+
+    // Only the most recent runs are kept, so the count tells how many are new
+    let runs_before = before.map_or(0, |before| before.test_run_count);
+    let new_runs = entry.test_run_count.saturating_sub(runs_before) as usize;
+    for run in &entry.test_runs[entry.test_runs.len().saturating_sub(new_runs)..] {
+        changes.push(ProgressChange::TestRunRecorded {
+            exercise_id: exercise_id.clone(),
+            compiled: run.compiled,
+            passed: run.passed,
+            failed: run.failed,
+        });
+    }
+
+    if entry.completed_at.is_some() && before.is_none_or(|before| before.completed_at.is_none()) {
+        changes.push(ProgressChange::ExerciseCompleted {
+            exercise_id: exercise_id.clone(),
+            completed_by: entry.completed_by,
+            time_taken_minutes: entry.time_taken_minutes,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        record_completion, record_hint, record_view,
+        test_runs::{add_test_run, TestRun, RECENT_TEST_RUNS},
+    };
+
+    fn progress() -> ProgressData {
+        serde_json::from_value(serde_json::json!({
+            "schema_version": crate::progress_schema::PROGRESS_SCHEMA_VERSION,
+            "user_id": "learner",
+        }))
+        .unwrap()
+    }
+
+    fn run(passed: u32, failed: u32) -> TestRun {
+        TestRun {
+            ran_at: "2026-10-18T09:00:00Z".to_string(),
+            compiled: true,
+            passed,
+            failed,
+            failing_tests: Vec::new(),
+            duration_ms: 0,
+        }
+    }
+
+    fn recorded_runs(changes: &[ProgressChange]) -> Vec<(u32, u32)> {
+        changes
+            .iter()
+            .filter_map(|change| match change {
+                ProgressChange::TestRunRecorded { passed, failed, .. } => Some((*passed, *failed)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn announces_a_first_view_once() {
+        let previous = progress();
+        let mut current = previous.clone();
+        record_view(&mut current, "ch01-ex01-hello", "2026-10-18T09:00:00Z");
+
+        let (changes, _) = progress_delta(&previous, &current).unwrap();
+        assert!(matches!(
+            changes.as_slice(),
+            [ProgressChange::ExerciseViewed { exercise_id, viewed_at }]
+                if exercise_id == "ch01-ex01-hello" && viewed_at == "2026-10-18T09:00:00Z"
+        ));
+
+        // Viewing it again changes nothing the client shows
+        let mut again = current.clone();
+        record_view(&mut again, "ch01-ex01-hello", "2026-10-18T10:00:00Z");
+        assert!(progress_delta(&current, &again).is_none());
+    }
+
+    #[test]
+    fn announces_only_newly_opened_hint_levels() {
+        let mut previous = progress();
+        record_view(&mut previous, "ch01-ex01-hello", "2026-10-18T09:00:00Z");
+        record_hint(&mut previous, "ch01-ex01-hello", 1, "2026-10-18T09:05:00Z");
+        let mut current = previous.clone();
+        record_hint(&mut current, "ch01-ex01-hello", 1, "2026-10-18T09:06:00Z");
+        record_hint(&mut current, "ch01-ex01-hello", 2, "2026-10-18T09:07:00Z");
+
+        let (changes, _) = progress_delta(&previous, &current).unwrap();
+        assert!(matches!(
+            changes.as_slice(),
+            [ProgressChange::HintUsed { exercise_id, levels }] if exercise_id == "ch01-ex01-hello" && levels == &[2]
+        ));
+    }
+
+    #[test]
+    fn announces_each_new_test_run_in_order() {
+        let mut previous = progress();
+        add_test_run(&mut previous, "ch01-ex01-hello".to_string(), run(0, 3));
+        let mut current = previous.clone();
+        add_test_run(&mut current, "ch01-ex01-hello".to_string(), run(1, 2));
+        add_test_run(&mut current, "ch01-ex01-hello".to_string(), run(3, 0));
+
+        let (changes, _) = progress_delta(&previous, &current).unwrap();
+        assert_eq!(recorded_runs(&changes), vec![(1, 2), (3, 0)]);
+    }
+
+    #[test]
+    fn announces_a_completion_with_the_new_totals() {
+        let mut previous = progress();
+        record_view(&mut previous, "ch01-ex01-hello", "2026-10-18T09:00:00Z");
+        let mut current = previous.clone();
+        assert!(record_completion(
+            &mut current,
+            "ch01-ex01-hello",
+            Some(5),
+            None,
+            CompletionSource::Tests
+        ));
+
+        let (changes, totals) = progress_delta(&previous, &current).unwrap();
+        assert!(matches!(
+            changes.as_slice(),
+            [ProgressChange::ExerciseCompleted {
+                exercise_id,
+                completed_by: Some(CompletionSource::Tests),
+                time_taken_minutes: Some(5),
+            }] if exercise_id == "ch01-ex01-hello"
+        ));
+        assert_eq!(totals.exercises_completed, 1);
+
+        // An exercise that was already completed is not announced again
+        let mut again = current.clone();
+        assert!(!record_completion(&mut again, "ch01-ex01-hello", None, None, CompletionSource::Tests));
+        assert!(progress_delta(&current, &again).is_none());
+    }
+
+    #[test]
+    fn runs_of_an_exercise_at_the_cap_are_still_announced() {
+        let mut previous = progress();
+        for _ in 0..RECENT_TEST_RUNS {
+            add_test_run(&mut previous, "ch01-ex01-hello".to_string(), run(0, 1));
+        }
+        let mut current = previous.clone();
+        add_test_run(&mut current, "ch01-ex01-hello".to_string(), run(1, 0));
+        assert_eq!(current.exercise_history[0].test_runs.len(), RECENT_TEST_RUNS);
+
+        let (changes, _) = progress_delta(&previous, &current).unwrap();
+        assert_eq!(recorded_runs(&changes), vec![(1, 0)]);
+    }
+}
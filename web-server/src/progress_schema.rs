@@ -0,0 +1,406 @@
+// Progress schema versions
+//
+// Stored progress carries a `schema_version`. Older layouts are upgraded on
+// load by a chain of migrations, each taking the JSON one version forward, so a
+// file from any earlier release ends up in the current shape:
+//
+//   0  the exercise framework's `UserProgress` (typed chapters, `preferences`,
+//      `analytics`, hint counts instead of levels). It has no version field
+//      and is recognised by its shape.
+//   1  the web server's format before versioning: no `schema_version` field,
+//      achievements as whatever JSON the browser sent.
+//   2  typed achievements (`earned_at` instead of the browser's `unlocked_at`).
+//   3  learner `preferences`. Nothing to convert, but a release that reads
+//      version 2 would drop them on its next save.
+//   4  only the most recent test runs per exercise, with `test_run_count` and
+//      `failed_test_runs` counting all of them.
+//   5  error codes counted per code (`ErrorCodeStats`) instead of one record
+//      per run that hit any.
+//
+// Progress written by a newer release is refused instead of being read and
+// silently rewritten without the fields this release doesn't know.
+
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use tracing::{info, warn};
+
+use crate::{
+    error_codes::{ErrorCodeRecord, ErrorCodeStats},
+    test_runs::{TestRun, RECENT_TEST_RUNS},
+};
+
+pub const PROGRESS_SCHEMA_VERSION: u32 = 5;
+
+// Migration from the version it is listed under to the next one
+type Migration = fn(&mut Map<String, Value>);
+
+const MIGRATIONS: &[(u32, Migration)] = &[
+    (0, from_framework_format),
+    (1, to_typed_achievements),
+    (2, with_preferences),
+    (3, to_counted_test_runs),
+    (4, to_counted_error_codes),
+];
+
+// Version of stored progress, inferring it for files from before versioning
+pub fn stored_version(progress: &Value) -> anyhow::Result<u32> {
+    let Some(object) = progress.as_object() else {
+        anyhow::bail!("Progress is not a JSON object");
+    };
+    match object.get("schema_version") {
+        Some(version) => version
+            .as_u64()
+            .map(|version| version as u32)
+            .ok_or_else(|| anyhow::anyhow!("Invalid schema_version: {}", version)),
+        None if is_framework_format(object) => Ok(0),
+        None => Ok(1),
+    }
+}
+
+fn is_framework_format(object: &Map<String, Value>) -> bool {
+    if object.contains_key("preferences") || object.contains_key("analytics") {
+        return true;
+    }
+    // The framework counts hints per exercise; the web server lists hint levels
+    object
+        .get("exercise_history")
+        .and_then(Value::as_array)
+        .is_some_and(|history| history.iter().any(|entry| entry.get("hints_used").is_some_and(Value::is_number)))
+}
+
+// Bring stored progress up to the current schema. `source` names where it came
+// from for log and error messages. Returns the upgraded JSON and the version it
+// was stored with.
+pub fn upgrade(mut progress: Value, source: &str) -> anyhow::Result<(Value, u32)> {
+    let version = stored_version(&progress)?;
+    if version > PROGRESS_SCHEMA_VERSION {
+        anyhow::bail!(
+            "{} was written by a newer version of Rust Tour (progress schema {}, this version supports up to {}). \
+             Upgrade rust-tour to use it; it was left untouched.",
+            source,
+            version,
+            PROGRESS_SCHEMA_VERSION
+        );
+    }
+
+    let object = progress.as_object_mut().expect("checked by stored_version");
+    for (from, migration) in MIGRATIONS {
+        if *from >= version {
+            migration(object);
+        }
+    }
+    object.insert("schema_version".to_string(), Value::from(PROGRESS_SCHEMA_VERSION));
+
+    if version < PROGRESS_SCHEMA_VERSION {
+        info!("Upgraded {} from progress schema {} to {}", source, version, PROGRESS_SCHEMA_VERSION);
+    }
+    Ok((progress, version))
+}
+
+// 0 -> 1: the framework's `UserProgress` to the web server's layout
+fn from_framework_format(progress: &mut Map<String, Value>) {
+    // No equivalent (yet); the original file is kept as a backup
+    for field in ["last_updated", "analytics"] {
+        progress.remove(field);
+    }
+
+    // Preferences are kept; their enum values are snake_case here
+    if let Some(preferences) = progress.get_mut("preferences").and_then(Value::as_object_mut) {
+        for field in ["difficulty_preference", "hint_usage"] {
+            if let Some(value) = preferences.get(field).and_then(Value::as_str) {
+                let value = value.to_lowercase();
+                preferences.insert(field.to_string(), Value::from(value));
+            }
+        }
+    }
+
+    // Framework history entries are completions with a hint count; the
+    // levels used weren't recorded, but hints are revealed in order
+    if let Some(history) = progress.get_mut("exercise_history").and_then(Value::as_array_mut) {
+        for entry in history.iter_mut().filter_map(Value::as_object_mut) {
+            if let Some(count) = entry.get("hints_used").and_then(Value::as_u64) {
+                let levels: Vec<Value> = (1..=count).map(Value::from).collect();
+                if levels.is_empty() {
+                    entry.remove("hints_used");
+                } else {
+                    entry.insert("hints_used".to_string(), Value::Array(levels));
+                }
+            }
+            if !entry.contains_key("status") {
+                let status = if entry.get("completed_at").is_some_and(|at| !at.is_null()) {
+                    "completed"
+                } else {
+                    "viewed"
+                };
+                entry.insert("status".to_string(), Value::from(status));
+            }
+            // `attempts` and `test_failures` mean the same here; the runs themselves weren't kept
+            for field in [
+                "chapter",
+                "test_passes",
+                "code_quality_score",
+                "concepts_learned",
+            ] {
+                entry.remove(field);
+            }
+        }
+    }
+
+    // Chapter statistics are recomputed from the history; drop what has no counterpart
+    if let Some(chapters) = progress.get_mut("chapters").and_then(Value::as_object_mut) {
+        for chapter in chapters.values_mut().filter_map(Value::as_object_mut) {
+            chapter.remove("concept_mastery");
+        }
+    }
+
+    // Framework achievements already have an `earned_at`; `category` has no counterpart
+    if let Some(achievements) = progress.get_mut("achievements").and_then(Value::as_array_mut) {
+        for achievement in achievements.iter_mut().filter_map(Value::as_object_mut) {
+            achievement.remove("category");
+        }
+    }
+}
+
+// 1 -> 2: achievements become `Achievement` records
+fn to_typed_achievements(progress: &mut Map<String, Value>) {
+    let Some(achievements) = progress.get_mut("achievements").and_then(Value::as_array_mut) else {
+        return;
+    };
+    achievements.retain_mut(|achievement| {
+        let Some(object) = achievement.as_object_mut().filter(|object| object.get("id").is_some_and(Value::is_string))
+        else {
+            warn!("Dropping achievement without an id: {}", achievement);
+            return false;
+        };
+        if let Some(unlocked_at) = object.remove("unlocked_at") {
+            object.entry("earned_at").or_insert(unlocked_at);
+        }
+        true
+    });
+}
+
+// 2 -> 3: `preferences` was added with a default, so older progress needs no change
+fn with_preferences(_progress: &mut Map<String, Value>) {}
+
+// 3 -> 4: count the test runs and keep only the most recent
+fn to_counted_test_runs(progress: &mut Map<String, Value>) {
+    let Some(history) = progress.get_mut("exercise_history").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for entry in history.iter_mut().filter_map(Value::as_object_mut) {
+        let Some(runs) = entry.get_mut("test_runs").and_then(Value::as_array_mut) else {
+            continue;
+        };
+        let failed = runs
+            .iter()
+            .filter(|run| serde_json::from_value::<TestRun>((*run).clone()).is_ok_and(|run| !run.succeeded()))
+            .count();
+        let count = runs.len();
+        runs.drain(..count.saturating_sub(RECENT_TEST_RUNS));
+        entry.insert("test_run_count".to_string(), Value::from(count));
+        entry.insert("failed_test_runs".to_string(), Value::from(failed));
+    }
+}
+
+// 4 -> 5: add up the error code records per code
+fn to_counted_error_codes(progress: &mut Map<String, Value>) {
+    let Some(history) = progress.get_mut("exercise_history").and_then(Value::as_array_mut) else {
+        return;
+    };
+    for entry in history.iter_mut().filter_map(Value::as_object_mut) {
+        let Some(records) = entry.get("error_codes").and_then(Value::as_array) else {
+            continue;
+        };
+        let mut stats: BTreeMap<String, ErrorCodeStats> = BTreeMap::new();
+        for record in records {
+            let Ok(record) = serde_json::from_value::<ErrorCodeRecord>(record.clone()) else {
+                warn!("Dropping invalid error code record: {}", record);
+                continue;
+            };
+            for (code, count) in record.codes {
+                stats
+                    .entry(code)
+                    .or_insert_with(|| ErrorCodeStats::new(&record.at))
+                    .add(&record.at, count);
+            }
+        }
+        let stats = serde_json::to_value(stats).expect("error code stats serialize");
+        entry.insert("error_codes".to_string(), stats);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ProgressData;
+    use serde_json::json;
+
+    fn upgrade_to_progress(stored: Value) -> (ProgressData, u32) {
+        let (upgraded, version) = upgrade(stored, "the test fixture").expect("progress upgrades");
+        assert_eq!(upgraded["schema_version"], PROGRESS_SCHEMA_VERSION);
+        (serde_json::from_value(upgraded).expect("upgraded progress deserializes"), version)
+    }
+
+    fn test_run(ran_at: &str, failed: u32) -> Value {
+        json!({"ran_at": ran_at, "compiled": true, "passed": 3 - failed, "failed": failed, "duration_ms": 500})
+    }
+
+    #[test]
+    fn framework_progress_is_upgraded() {
+        let stored = json!({
+            "user_id": "learner",
+            "last_updated": "2026-01-02T00:00:00Z",
+            "preferences": {"difficulty_preference": "Challenge", "hint_usage": "Disabled"},
+            "analytics": {"total_time_minutes": 42},
+            "exercise_history": [{
+                "exercise_id": "ch01-ex01-hello-world",
+                "chapter": 1,
+                "completed_at": "2026-01-01T10:00:00Z",
+                "hints_used": 2,
+                "attempts": 3,
+                "test_failures": 1,
+                "test_passes": 2,
+                "concepts_learned": ["printing"]
+            }],
+            "chapters": {"1": {"completed": 1, "concept_mastery": {"printing": 1.0}}},
+            "achievements": [{"id": "first_steps", "category": "milestone", "earned_at": "2026-01-01T10:00:00Z"}]
+        });
+        assert_eq!(stored_version(&stored).unwrap(), 0);
+
+        let (progress, version) = upgrade_to_progress(stored);
+        assert_eq!(version, 0);
+        assert_eq!(progress.preferences.difficulty_preference, crate::preferences::DifficultyPreference::Challenge);
+        assert_eq!(progress.preferences.hint_usage, crate::preferences::HintPreference::Disabled);
+
+        let entry = &progress.exercise_history[0];
+        assert_eq!(entry.hints_used, Some(vec![1, 2]));
+        assert_eq!(entry.status, "completed");
+        assert_eq!((entry.attempts, entry.test_failures), (Some(3), Some(1)));
+        assert_eq!(progress.chapters["1"].get("concept_mastery"), None);
+        assert_eq!(progress.achievements[0].id, "first_steps");
+    }
+
+    #[test]
+    fn unversioned_web_server_progress_is_upgraded() {
+        let stored = json!({
+            "user_id": "learner",
+            "exercise_history": [{
+                "exercise_id": "ch01-ex01-hello-world",
+                "status": "viewed",
+                "viewed_at": "2026-01-01T09:00:00Z",
+                "hints_used": [1]
+            }],
+            "achievements": [
+                {"id": "first_steps", "title": "First Steps", "unlocked_at": "2026-01-01T10:00:00Z"},
+                {"title": "Without an id"}
+            ]
+        });
+        assert_eq!(stored_version(&stored).unwrap(), 1);
+
+        let (progress, version) = upgrade_to_progress(stored);
+        assert_eq!(version, 1);
+        assert_eq!(progress.exercise_history[0].hints_used, Some(vec![1]));
+        assert_eq!(progress.achievements.len(), 1);
+        assert_eq!(progress.achievements[0].earned_at, "2026-01-01T10:00:00Z");
+    }
+
+    #[test]
+    fn version_2_test_runs_and_error_codes_are_counted() {
+        let runs: Vec<Value> = (0..25)
+            .map(|minute| test_run(&format!("2026-01-01T10:{:02}:00Z", minute), (minute < 8) as u32))
+            .collect();
+        let stored = json!({
+            "schema_version": 2,
+            "user_id": "learner",
+            "exercise_history": [{
+                "exercise_id": "ch04-ex01-ownership-basics",
+                "status": "in_progress",
+                "test_runs": runs,
+                "error_codes": [
+                    {"at": "2026-01-01T10:00:00Z", "action": "check", "codes": {"E0382": 2}},
+                    {"at": "2026-01-02T10:00:00Z", "action": "test", "codes": {"E0382": 1, "E0499": 1}}
+                ]
+            }]
+        });
+
+        let (progress, version) = upgrade_to_progress(stored);
+        assert_eq!(version, 2);
+        assert_eq!(progress.preferences, crate::preferences::UserPreferences::default());
+
+        let entry = &progress.exercise_history[0];
+        assert_eq!(entry.test_runs.len(), RECENT_TEST_RUNS);
+        assert_eq!(entry.test_runs[0].ran_at, "2026-01-01T10:05:00Z");
+        assert_eq!((entry.test_run_count, entry.failed_test_runs), (25, 8));
+
+        let moved = &entry.error_codes["E0382"];
+        assert_eq!(moved.count, 3);
+        assert_eq!(moved.first_seen, "2026-01-01T10:00:00Z");
+        assert_eq!(moved.last_seen, "2026-01-02T10:00:00Z");
+        assert_eq!(moved.daily.get("2026-01-01"), Some(&2));
+        assert_eq!(entry.error_codes["E0499"].count, 1);
+    }
+
+    #[test]
+    fn version_3_keeps_preferences() {
+        let stored = json!({
+            "schema_version": 3,
+            "user_id": "learner",
+            "preferences": {"hint_usage": "minimal", "auto_advance": true},
+            "exercise_history": [{
+                "exercise_id": "ch01-ex01-hello-world",
+                "status": "in_progress",
+                "test_runs": [test_run("2026-01-01T10:00:00Z", 1)]
+            }]
+        });
+        // A version field means it isn't the framework's format, despite `preferences`
+        assert_eq!(stored_version(&stored).unwrap(), 3);
+
+        let (progress, version) = upgrade_to_progress(stored);
+        assert_eq!(version, 3);
+        assert_eq!(progress.preferences.hint_usage, crate::preferences::HintPreference::Minimal);
+        assert!(progress.preferences.auto_advance);
+        let entry = &progress.exercise_history[0];
+        assert_eq!((entry.test_run_count, entry.failed_test_runs), (1, 1));
+    }
+
+    #[test]
+    fn version_4_error_codes_are_counted() {
+        let stored = json!({
+            "schema_version": 4,
+            "user_id": "learner",
+            "exercise_history": [{
+                "exercise_id": "ch04-ex01-ownership-basics",
+                "status": "in_progress",
+                "error_codes": [
+                    {"at": "2026-01-01T10:00:00Z", "action": "run", "codes": {"clippy::needless_return": 1}},
+                    {"at": "2026-01-01T11:00:00Z", "action": "run", "codes": {"clippy::needless_return": 2}}
+                ]
+            }]
+        });
+
+        let (progress, version) = upgrade_to_progress(stored);
+        assert_eq!(version, 4);
+        let stats = &progress.exercise_history[0].error_codes["clippy::needless_return"];
+        assert_eq!(stats.count, 3);
+        assert_eq!(stats.daily.get("2026-01-01"), Some(&3));
+    }
+
+    #[test]
+    fn current_progress_is_left_as_it_is() {
+        let stored = json!({"schema_version": PROGRESS_SCHEMA_VERSION, "user_id": "learner"});
+        let (upgraded, version) = upgrade(stored.clone(), "the test fixture").unwrap();
+        assert_eq!(version, PROGRESS_SCHEMA_VERSION);
+        assert_eq!(upgraded, stored);
+    }
+
+    #[test]
+    fn newer_progress_is_refused() {
+        let stored = json!({"schema_version": PROGRESS_SCHEMA_VERSION + 1, "user_id": "learner"});
+        let error = upgrade(stored, "user_progress.json").unwrap_err().to_string();
+        assert!(error.contains("user_progress.json was written by a newer version"));
+        assert!(error.contains(&format!("progress schema {}", PROGRESS_SCHEMA_VERSION + 1)));
+
+        assert!(stored_version(&json!({"schema_version": "2"})).is_err());
+        assert!(stored_version(&json!([])).is_err());
+    }
+}
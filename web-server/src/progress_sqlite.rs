@@ -0,0 +1,571 @@
+// SQLite progress backend
+//
+// Stores progress in `user_progress.db` instead of one JSON document. Each
+// exercise the learner touched is a row in `exercises`, hint levels are rows in
+// `hint_usage`, and every view, completion, hint and reset is appended to
+// `events`. `attempts` holds test runs, one row per `cargo test`, of which only
+// the most recent are loaded into the history entry, and `error_code_stats` how
+// often each rustc and clippy code occurred per exercise. The remaining summary
+// fields (counters, chapter catalog, achievements, resets) are kept as a single
+// JSON row in `progress_summary`.
+//
+// A save only touches the history rows that changed since the previous save,
+// and rewrites the summary row only when it differs from the saved one.
+
+use chrono::Utc;
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+use tracing::info;
+
+use crate::{
+    progress_schema,
+    progress_store::{read_json_progress, ProgressStore},
+    error_codes::ErrorCodeStats,
+    test_runs::{TestRun, RECENT_TEST_RUNS},
+    ExerciseHistoryEntry, ProgressData,
+};
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS progress_summary (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        data TEXT NOT NULL,
+        updated_at TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS exercises (
+        exercise_id TEXT PRIMARY KEY,
+        position INTEGER NOT NULL,
+        status TEXT NOT NULL,
+        viewed_at TEXT,
+        completed_at TEXT,
+        time_taken_minutes INTEGER,
+        session_id TEXT,
+        active_seconds INTEGER,
+        attempts INTEGER,
+        test_failures INTEGER,
+        completed_by TEXT,
+        test_run_count INTEGER,
+        failed_test_runs INTEGER
+    );
+    CREATE TABLE IF NOT EXISTS hint_usage (
+        exercise_id TEXT NOT NULL,
+        hint_level INTEGER NOT NULL,
+        used_at TEXT NOT NULL,
+        PRIMARY KEY (exercise_id, hint_level)
+    );
+    CREATE TABLE IF NOT EXISTS attempts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        exercise_id TEXT NOT NULL,
+        action TEXT NOT NULL,
+        success INTEGER NOT NULL,
+        created_at TEXT NOT NULL,
+        duration_ms INTEGER,
+        details TEXT
+    );
+    CREATE INDEX IF NOT EXISTS attempts_exercise ON attempts (exercise_id, created_at);
+    CREATE TABLE IF NOT EXISTS error_code_stats (
+        exercise_id TEXT NOT NULL,
+        code TEXT NOT NULL,
+        count INTEGER NOT NULL,
+        first_seen TEXT NOT NULL,
+        last_seen TEXT NOT NULL,
+        daily TEXT NOT NULL,
+        PRIMARY KEY (exercise_id, code)
+    );
+    CREATE TABLE IF NOT EXISTS events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        kind TEXT NOT NULL,
+        exercise_id TEXT,
+        created_at TEXT NOT NULL,
+        data TEXT
+    );
+    CREATE INDEX IF NOT EXISTS events_created_at ON events (created_at);
+";
+
+// The `details` column of a test run in `attempts`
+#[derive(Serialize, Deserialize)]
+struct TestRunDetails {
+    #[serde(default)]
+    compiled: bool,
+    #[serde(default)]
+    passed: u32,
+    #[serde(default)]
+    failed: u32,
+    #[serde(default)]
+    failing_tests: Vec<String>,
+}
+
+pub struct SqliteProgressStore {
+    conn: Connection,
+    // History as of the last save, to work out which rows changed
+    saved: HashMap<String, (usize, ExerciseHistoryEntry)>,
+    // Resets already recorded in `events`
+    saved_resets: usize,
+    // `progress_summary` as last read or written
+    saved_summary: Option<String>,
+}
+
+impl SqliteProgressStore {
+    pub fn open(db_path: &Path) -> anyhow::Result<Self> {
+        let mut conn = Connection::open(db_path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "FULL")?;
+        conn.execute_batch(SCHEMA)?;
+        add_column_if_missing(&conn, "exercises", "active_seconds", "INTEGER")?;
+        add_column_if_missing(&conn, "exercises", "attempts", "INTEGER")?;
+        add_column_if_missing(&conn, "exercises", "test_failures", "INTEGER")?;
+        add_column_if_missing(&conn, "exercises", "completed_by", "TEXT")?;
+        add_column_if_missing(&conn, "exercises", "test_run_count", "INTEGER")?;
+        add_column_if_missing(&conn, "exercises", "failed_test_runs", "INTEGER")?;
+        aggregate_error_code_records(&mut conn)?;
+        Ok(Self {
+            conn,
+            saved: HashMap::new(),
+            saved_resets: 0,
+            saved_summary: None,
+        })
+    }
+
+    // Open the database and, when it's empty, import an existing JSON progress
+    // file. The JSON file is renamed afterwards so it isn't imported twice.
+    pub fn open_migrating(db_path: &Path, json_path: &Path) -> anyhow::Result<Self> {
+        let mut store = Self::open(db_path)?;
+        if store.has_summary()? {
+            return Ok(store);
+        }
+
+        let Some(progress) = read_json_progress(json_path)? else {
+            return Ok(store);
+        };
+
+        info!(
+            "Migrating {} ({} exercises) into {}",
+            json_path.display(),
+            progress.exercise_history.len(),
+            db_path.display()
+        );
+        store.save(&progress)?;
+        fs::rename(json_path, migrated_path(json_path))?;
+        info!("Progress migrated; the JSON file was kept as {}", migrated_path(json_path).display());
+
+        Ok(store)
+    }
+
+    fn has_summary(&self) -> anyhow::Result<bool> {
+        let found = self
+            .conn
+            .query_row("SELECT 1 FROM progress_summary WHERE id = 1", [], |_| Ok(()))
+            .optional()?;
+        Ok(found.is_some())
+    }
+
+    fn load_history(&self) -> anyhow::Result<Vec<ExerciseHistoryEntry>> {
+        let mut hints: HashMap<String, Vec<u32>> = HashMap::new();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT exercise_id, hint_level FROM hint_usage ORDER BY rowid")?;
+        let rows = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, u32>(1)?)))?;
+        for row in rows {
+            let (exercise_id, level) = row?;
+            hints.entry(exercise_id).or_default().push(level);
+        }
+
+        let mut test_runs: HashMap<String, Vec<TestRun>> = HashMap::new();
+        let mut stmt = self.conn.prepare(
+            "SELECT exercise_id, created_at, duration_ms, details FROM attempts WHERE action = 'test' ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<u64>>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })?;
+        for row in rows {
+            let (exercise_id, ran_at, duration_ms, details) = row?;
+            let details: TestRunDetails = serde_json::from_str(details.as_deref().unwrap_or("{}"))?;
+            test_runs.entry(exercise_id).or_default().push(TestRun {
+                ran_at,
+                compiled: details.compiled,
+                passed: details.passed,
+                failed: details.failed,
+                failing_tests: details.failing_tests,
+                duration_ms: duration_ms.unwrap_or(0),
+            });
+        }
+
+        let mut error_codes: HashMap<String, BTreeMap<String, ErrorCodeStats>> = HashMap::new();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT exercise_id, code, count, first_seen, last_seen, daily FROM error_code_stats")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, String>(4)?,
+                row.get::<_, String>(5)?,
+            ))
+        })?;
+        for row in rows {
+            let (exercise_id, code, count, first_seen, last_seen, daily) = row?;
+            error_codes.entry(exercise_id).or_default().insert(
+                code,
+                ErrorCodeStats {
+                    count,
+                    first_seen,
+                    last_seen,
+                    daily: serde_json::from_str(&daily)?,
+                },
+            );
+        }
+
+        let mut stmt = self.conn.prepare(
+            "SELECT exercise_id, status, viewed_at, completed_at, time_taken_minutes, session_id, active_seconds,
+                    attempts, test_failures, completed_by, test_run_count, failed_test_runs
+             FROM exercises ORDER BY position",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let counts: (Option<u32>, Option<u32>) = (row.get(10)?, row.get(11)?);
+            let entry = ExerciseHistoryEntry {
+                exercise_id: row.get(0)?,
+                status: row.get(1)?,
+                viewed_at: row.get(2)?,
+                completed_at: row.get(3)?,
+                time_taken_minutes: row.get(4)?,
+                session_id: row.get(5)?,
+                hints_used: None,
+                active_seconds: row.get(6)?,
+                test_runs: Vec::new(),
+                test_run_count: 0,
+                failed_test_runs: 0,
+                error_codes: BTreeMap::new(),
+                attempts: row.get(7)?,
+                test_failures: row.get(8)?,
+                completed_by: row
+                    .get::<_, Option<String>>(9)?
+                    .and_then(|source| serde_json::from_value(serde_json::Value::String(source)).ok()),
+            };
+            Ok((entry, counts))
+        })?;
+
+        let mut history = Vec::new();
+        for row in rows {
+            let (mut entry, (run_count, failed_runs)) = row?;
+            entry.hints_used = hints.remove(&entry.exercise_id);
+            let mut runs = test_runs.remove(&entry.exercise_id).unwrap_or_default();
+            // Databases from before the counts were stored have every run in `attempts`
+            entry.test_run_count = run_count.unwrap_or(runs.len() as u32);
+            entry.failed_test_runs = failed_runs.unwrap_or(runs.iter().filter(|run| !run.succeeded()).count() as u32);
+            runs.drain(..runs.len().saturating_sub(RECENT_TEST_RUNS));
+            entry.test_runs = runs;
+            entry.error_codes = error_codes.remove(&entry.exercise_id).unwrap_or_default();
+            history.push(entry);
+        }
+        Ok(history)
+    }
+}
+
+// Databases from before error codes were counted have one row per run that hit
+// any; add them up and drop the table
+fn aggregate_error_code_records(conn: &mut Connection) -> anyhow::Result<()> {
+    let exists = conn
+        .query_row("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'error_codes'", [], |_| Ok(()))
+        .optional()?
+        .is_some();
+    if !exists {
+        return Ok(());
+    }
+
+    let tx = conn.transaction()?;
+    let mut stats: HashMap<(String, String), ErrorCodeStats> = HashMap::new();
+    {
+        let mut stmt = tx.prepare("SELECT exercise_id, created_at, codes FROM error_codes ORDER BY id")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?, row.get::<_, String>(2)?))
+        })?;
+        for row in rows {
+            let (exercise_id, at, codes) = row?;
+            let codes: BTreeMap<String, u32> = serde_json::from_str(&codes)?;
+            for (code, count) in codes {
+                stats
+                    .entry((exercise_id.clone(), code))
+                    .or_insert_with(|| ErrorCodeStats::new(&at))
+                    .add(&at, count);
+            }
+        }
+    }
+    for ((exercise_id, code), stats) in &stats {
+        insert_error_code_stats(&tx, exercise_id, code, stats)?;
+    }
+    tx.execute_batch("DROP TABLE error_codes")?;
+    tx.commit()?;
+    info!("Counted the error codes of {} exercise and code pairs", stats.len());
+    Ok(())
+}
+
+fn insert_error_code_stats(tx: &Transaction, exercise_id: &str, code: &str, stats: &ErrorCodeStats) -> anyhow::Result<()> {
+    tx.execute(
+        "INSERT INTO error_code_stats (exercise_id, code, count, first_seen, last_seen, daily)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            exercise_id,
+            code,
+            stats.count,
+            stats.first_seen,
+            stats.last_seen,
+            serde_json::to_string(&stats.daily)?,
+        ],
+    )?;
+    Ok(())
+}
+
+// Columns added after a table was first created
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> anyhow::Result<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))?
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .any(|name| name == column);
+    if !exists {
+        conn.execute_batch(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+    }
+    Ok(())
+}
+
+fn migrated_path(json_path: &Path) -> PathBuf {
+    let mut name = json_path.as_os_str().to_owned();
+    name.push(".migrated");
+    PathBuf::from(name)
+}
+
+impl ProgressStore for SqliteProgressStore {
+    fn load(&mut self) -> anyhow::Result<Option<ProgressData>> {
+        let summary: Option<String> = self
+            .conn
+            .query_row("SELECT data FROM progress_summary WHERE id = 1", [], |row| row.get(0))
+            .optional()?;
+        let Some(summary) = summary else {
+            return Ok(None);
+        };
+
+        let mut saved_summary = summary;
+        let summary: serde_json::Value = serde_json::from_str(&saved_summary)?;
+        let (summary, version) = progress_schema::upgrade(summary, "the progress database")?;
+        if version < progress_schema::PROGRESS_SCHEMA_VERSION {
+            saved_summary = summary.to_string();
+            self.conn.execute(
+                "UPDATE progress_summary SET data = ?1, updated_at = ?2 WHERE id = 1",
+                params![saved_summary, Utc::now().to_rfc3339()],
+            )?;
+        }
+        self.saved_summary = Some(saved_summary);
+
+        let mut progress: ProgressData = serde_json::from_value(summary)?;
+        progress.exercise_history = self.load_history()?;
+        self.saved = snapshot(&progress.exercise_history);
+        self.saved_resets = progress.resets.len();
+        Ok(Some(progress))
+    }
+
+    fn save(&mut self, progress: &ProgressData) -> anyhow::Result<()> {
+        let now = Utc::now().to_rfc3339();
+        let tx = self.conn.transaction()?;
+
+        // Everything except the history, which lives in its own tables
+        let mut summary = serde_json::to_value(progress)?;
+        if let Some(object) = summary.as_object_mut() {
+            object.remove("exercise_history");
+        }
+        let summary = summary.to_string();
+        if self.saved_summary.as_ref() != Some(&summary) {
+            tx.execute(
+                "INSERT INTO progress_summary (id, data, updated_at) VALUES (1, ?1, ?2)
+                 ON CONFLICT (id) DO UPDATE SET data = excluded.data, updated_at = excluded.updated_at",
+                params![summary, now],
+            )?;
+        }
+
+        for (position, entry) in progress.exercise_history.iter().enumerate() {
+            let previous = self.saved.get(&entry.exercise_id);
+            if previous.is_some_and(|(saved_position, saved)| *saved_position == position && saved == entry) {
+                continue;
+            }
+            save_entry(&tx, position, entry, previous.map(|(_, saved)| saved), &now)?;
+        }
+
+        for exercise_id in self.saved.keys() {
+            if !progress.exercise_history.iter().any(|entry| &entry.exercise_id == exercise_id) {
+                tx.execute("DELETE FROM exercises WHERE exercise_id = ?1", params![exercise_id])?;
+                tx.execute("DELETE FROM hint_usage WHERE exercise_id = ?1", params![exercise_id])?;
+                tx.execute("DELETE FROM attempts WHERE exercise_id = ?1", params![exercise_id])?;
+                tx.execute("DELETE FROM error_code_stats WHERE exercise_id = ?1", params![exercise_id])?;
+            }
+        }
+
+        for reset in progress.resets.iter().skip(self.saved_resets) {
+            tx.execute(
+                "INSERT INTO events (kind, exercise_id, created_at, data) VALUES ('progress_reset', NULL, ?1, ?2)",
+                params![reset.reset_at, serde_json::to_string(reset)?],
+            )?;
+        }
+
+        tx.commit()?;
+        self.saved = snapshot(&progress.exercise_history);
+        self.saved_resets = progress.resets.len();
+        self.saved_summary = Some(summary);
+        Ok(())
+    }
+}
+
+fn snapshot(history: &[ExerciseHistoryEntry]) -> HashMap<String, (usize, ExerciseHistoryEntry)> {
+    history
+        .iter()
+        .enumerate()
+        .map(|(position, entry)| (entry.exercise_id.clone(), (position, entry.clone())))
+        .collect()
+}
+
+// Upsert one exercise row, sync its hint levels and record what happened as events
+fn save_entry(
+    tx: &Transaction,
+    position: usize,
+    entry: &ExerciseHistoryEntry,
+    previous: Option<&ExerciseHistoryEntry>,
+    now: &str,
+) -> anyhow::Result<()> {
+    tx.execute(
+        "INSERT INTO exercises (exercise_id, position, status, viewed_at, completed_at, time_taken_minutes, session_id, active_seconds,
+                                attempts, test_failures, completed_by, test_run_count, failed_test_runs)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT (exercise_id) DO UPDATE SET
+             position = excluded.position,
+             status = excluded.status,
+             viewed_at = excluded.viewed_at,
+             completed_at = excluded.completed_at,
+             time_taken_minutes = excluded.time_taken_minutes,
+             session_id = excluded.session_id,
+             active_seconds = excluded.active_seconds,
+             attempts = excluded.attempts,
+             test_failures = excluded.test_failures,
+             completed_by = excluded.completed_by,
+             test_run_count = excluded.test_run_count,
+             failed_test_runs = excluded.failed_test_runs",
+        params![
+            entry.exercise_id,
+            position as i64,
+            entry.status,
+            entry.viewed_at,
+            entry.completed_at,
+            entry.time_taken_minutes,
+            entry.session_id,
+            entry.active_seconds,
+            entry.attempts,
+            entry.test_failures,
+            entry
+                .completed_by
+                .map(serde_json::to_value)
+                .transpose()?
+                .and_then(|source| source.as_str().map(str::to_string)),
+            entry.test_run_count,
+            entry.failed_test_runs,
+        ],
+    )?;
+
+    // Test runs are only ever appended, dropping the oldest from the entry while
+    // `attempts` keeps them; anything else (an import) rewrites them
+    let previous_runs = previous.map(|previous| previous.test_runs.as_slice()).unwrap_or_default();
+    let previous_count = previous.map_or(0, |previous| previous.test_run_count);
+    let kept = entry
+        .test_run_count
+        .checked_sub(previous_count)
+        .and_then(|added| entry.test_runs.len().checked_sub(added as usize));
+    let new_runs = match kept {
+        Some(kept) if previous_runs.ends_with(&entry.test_runs[..kept]) => &entry.test_runs[kept..],
+        _ => {
+            tx.execute("DELETE FROM attempts WHERE exercise_id = ?1", params![entry.exercise_id])?;
+            &entry.test_runs
+        }
+    };
+    for run in new_runs {
+        let details = TestRunDetails {
+            compiled: run.compiled,
+            passed: run.passed,
+            failed: run.failed,
+            failing_tests: run.failing_tests.clone(),
+        };
+        tx.execute(
+            "INSERT INTO attempts (exercise_id, action, success, created_at, duration_ms, details)
+             VALUES (?1, 'test', ?2, ?3, ?4, ?5)",
+            params![
+                entry.exercise_id,
+                run.succeeded(),
+                run.ran_at,
+                run.duration_ms,
+                serde_json::to_string(&details)?,
+            ],
+        )?;
+    }
+
+    // Error code counts are few per exercise; rewrite them when any changed
+    if previous.is_none_or(|previous| previous.error_codes != entry.error_codes) {
+        tx.execute("DELETE FROM error_code_stats WHERE exercise_id = ?1", params![entry.exercise_id])?;
+        for (code, stats) in &entry.error_codes {
+            insert_error_code_stats(tx, &entry.exercise_id, code, stats)?;
+        }
+    }
+
+    if previous.is_none() {
+        if let Some(viewed_at) = &entry.viewed_at {
+            record_event(tx, "exercise_viewed", &entry.exercise_id, viewed_at, None)?;
+        }
+    }
+
+    let was_completed = previous.is_some_and(|previous| previous.completed_at.is_some());
+    if let (false, Some(completed_at)) = (was_completed, &entry.completed_at) {
+        let data = serde_json::json!({ "time_taken_minutes": entry.time_taken_minutes });
+        record_event(tx, "exercise_completed", &entry.exercise_id, completed_at, Some(data))?;
+    }
+
+    let hints = entry.hints_used.as_deref().unwrap_or_default();
+    let previous_hints = previous
+        .and_then(|previous| previous.hints_used.as_deref())
+        .unwrap_or_default();
+    for level in hints.iter().filter(|level| !previous_hints.contains(level)) {
+        tx.execute(
+            "INSERT OR IGNORE INTO hint_usage (exercise_id, hint_level, used_at) VALUES (?1, ?2, ?3)",
+            params![entry.exercise_id, level, now],
+        )?;
+        let data = serde_json::json!({ "hint_level": level });
+        record_event(tx, "hint_used", &entry.exercise_id, now, Some(data))?;
+    }
+    for level in previous_hints.iter().filter(|level| !hints.contains(level)) {
+        tx.execute(
+            "DELETE FROM hint_usage WHERE exercise_id = ?1 AND hint_level = ?2",
+            params![entry.exercise_id, level],
+        )?;
+    }
+
+    Ok(())
+}
+
+fn record_event(
+    tx: &Transaction,
+    kind: &str,
+    exercise_id: &str,
+    created_at: &str,
+    data: Option<serde_json::Value>,
+) -> anyhow::Result<()> {
+    tx.execute(
+        "INSERT INTO events (kind, exercise_id, created_at, data) VALUES (?1, ?2, ?3, ?4)",
+        params![kind, exercise_id, created_at, data.map(|data| data.to_string())],
+    )?;
+    Ok(())
+}
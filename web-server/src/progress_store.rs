@@ -0,0 +1,442 @@
+// Progress store
+//
+// A single task owns the learner's `ProgressData`. Handlers send it mutations
+// over a channel, so concurrent requests are applied one at a time instead of
+// racing on read-modify-write of the stored progress. Persistence goes through
+// the `ProgressStore` trait: either the JSON file (`user_progress.json`) or a
+// SQLite database (`user_progress.db`, see `progress_sqlite`). An advisory lock
+// on `user_progress.lock` stops a second server instance from using the same
+// progress directory, whichever backend it picks.
+//
+// The JSON backend writes every change to a temporary file, fsyncs it and
+// renames it over the original, so a crash leaves either the old or the new
+// file and never a truncated one. A few rotating backups are kept next to it.
+
+use fs2::FileExt;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{broadcast, mpsc, oneshot};
+use tracing::{error, info, warn};
+
+use crate::{
+    achievements::AchievementCatalog,
+    activity::update_time_totals,
+    chapter_stats::update_chapter_stats,
+    event_log::EventLog,
+    prepare_progress,
+    progress_changes::progress_delta,
+    progress_schema,
+    progress_sqlite::SqliteProgressStore,
+    protocol::ServerMessage,
+    streaks::{update_streaks, StreakConfig},
+    ProgressData,
+};
+
+const COMMAND_QUEUE_SIZE: usize = 64;
+// Number of `user_progress.json.bak.N` files to keep
+pub const BACKUP_COUNT: usize = 3;
+// Take at most one backup per interval, so frequent small updates don't
+// rotate away every older snapshot
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+type Mutation = Box<dyn FnOnce(&mut ProgressData) -> bool + Send>;
+
+// Where progress is persisted. Implementations do blocking I/O and are only
+// called from the store task, through `spawn_blocking`.
+pub trait ProgressStore: Send {
+    // Saved progress, or `None` when nothing has been stored yet
+    fn load(&mut self) -> anyhow::Result<Option<ProgressData>>;
+    fn save(&mut self, progress: &ProgressData) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProgressBackend {
+    Json,
+    Sqlite,
+}
+
+type SharedStore = Arc<Mutex<Box<dyn ProgressStore>>>;
+
+// Settings shared by the progress tasks of every profile
+#[derive(Clone)]
+pub struct ProgressOptions {
+    pub backend: ProgressBackend,
+    pub streaks: StreakConfig,
+    pub achievements: Arc<AchievementCatalog>,
+    // Where progress changes and achievement unlocks are announced
+    pub broadcast_tx: broadcast::Sender<ServerMessage>,
+    // Where learner events are appended, see `event_log`
+    pub event_log: EventLog,
+}
+
+enum Command {
+    Snapshot(oneshot::Sender<ProgressData>),
+    Update {
+        mutation: Mutation,
+        reply: oneshot::Sender<anyhow::Result<ProgressData>>,
+    },
+}
+
+// Cheap handle to the progress task, shared through `AppState`
+#[derive(Clone)]
+pub struct ProgressHandle {
+    tx: mpsc::Sender<Command>,
+}
+
+impl ProgressHandle {
+    // Lock the progress directory, load the progress and start the owning task.
+    // `progress_path` is the JSON file; the SQLite database sits next to it.
+    pub async fn open(
+        options: ProgressOptions,
+        progress_path: PathBuf,
+        exercises_path: &Path,
+        user_id: &str,
+    ) -> anyhow::Result<Self> {
+        if let Some(parent) = progress_path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+
+        let lock = acquire_lock(&progress_path)?;
+        let store: Box<dyn ProgressStore> = match options.backend {
+            ProgressBackend::Json => Box::new(JsonProgressStore::new(progress_path)),
+            ProgressBackend::Sqlite => {
+                let json_path = progress_path.clone();
+                let db_path = sqlite_path(&progress_path);
+                Box::new(
+                    tokio::task::spawn_blocking(move || SqliteProgressStore::open_migrating(&db_path, &json_path))
+                        .await??,
+                )
+            }
+        };
+        let store: SharedStore = Arc::new(Mutex::new(store));
+
+        let stored = with_store(&store, |store| store.load()).await?;
+        let (mut progress, mut needs_save) = prepare_progress(stored, exercises_path, user_id).await?;
+        // Stored statistics are only a cache of what the history says
+        needs_save |= refresh_derived(&mut progress, &options);
+        // Nobody is connected yet, so catching up on achievements is silent
+        let unlocked = options.achievements.evaluate(&mut progress);
+        for achievement in &unlocked {
+            info!("Achievement {} earned by {}", achievement.id, user_id);
+        }
+        needs_save |= !unlocked.is_empty();
+        if needs_save {
+            let snapshot = progress.clone();
+            with_store(&store, move |store| store.save(&snapshot)).await?;
+            info!("Progress file updated successfully");
+        }
+
+        let (tx, rx) = mpsc::channel(COMMAND_QUEUE_SIZE);
+        tokio::spawn(run(progress, store, options, user_id.to_string(), lock, rx));
+
+        Ok(Self { tx })
+    }
+
+    // Current progress
+    pub async fn snapshot(&self) -> anyhow::Result<ProgressData> {
+        let (reply, response) = oneshot::channel();
+        self.tx
+            .send(Command::Snapshot(reply))
+            .await
+            .map_err(|_| anyhow::anyhow!("Progress store is not running"))?;
+        Ok(response.await?)
+    }
+
+    // Apply a mutation and persist the result. The mutation returns whether it
+    // changed anything; when it didn't, nothing is written.
+    pub async fn update<F>(&self, mutation: F) -> anyhow::Result<ProgressData>
+    where
+        F: FnOnce(&mut ProgressData) -> bool + Send + 'static,
+    {
+        let (reply, response) = oneshot::channel();
+        self.tx
+            .send(Command::Update {
+                mutation: Box::new(mutation),
+                reply,
+            })
+            .await
+            .map_err(|_| anyhow::anyhow!("Progress store is not running"))?;
+        response.await?
+    }
+}
+
+pub fn sqlite_path(progress_path: &Path) -> PathBuf {
+    progress_path.with_extension("db")
+}
+
+// Run a blocking store call off the async runtime
+async fn with_store<T, F>(store: &SharedStore, f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&mut dyn ProgressStore) -> anyhow::Result<T> + Send + 'static,
+{
+    let store = store.clone();
+    tokio::task::spawn_blocking(move || {
+        let mut store = store.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        f(store.as_mut())
+    })
+    .await?
+}
+
+// Recompute everything derived from the exercise history; returns whether
+// anything changed
+pub fn refresh_derived(progress: &mut ProgressData, options: &ProgressOptions) -> bool {
+    let chapters_changed = update_chapter_stats(progress);
+    let streaks_changed = update_streaks(progress, &options.streaks);
+    let time_changed = update_time_totals(progress);
+    chapters_changed || streaks_changed || time_changed
+}
+
+// `_lock` is held for the lifetime of the task; it's released when the file is closed
+async fn run(
+    mut progress: ProgressData,
+    store: SharedStore,
+    options: ProgressOptions,
+    profile: String,
+    _lock: File,
+    mut rx: mpsc::Receiver<Command>,
+) {
+    while let Some(command) = rx.recv().await {
+        match command {
+            Command::Snapshot(reply) => {
+                // A streak can lapse without any update; saved with the next change
+                update_streaks(&mut progress, &options.streaks);
+                let _ = reply.send(progress.clone());
+            }
+            Command::Update { mutation, reply } => {
+                let previous = progress.clone();
+                if !mutation(&mut progress) {
+                    let _ = reply.send(Ok(progress.clone()));
+                    continue;
+                }
+                refresh_derived(&mut progress, &options);
+                let unlocked = options.achievements.evaluate(&mut progress);
+
+                let snapshot = progress.clone();
+                let result = match with_store(&store, move |store| store.save(&snapshot)).await {
+                    Ok(()) => {
+                        for achievement in unlocked {
+                            info!("Achievement {} earned by {}", achievement.id, profile);
+                            let _ = options.broadcast_tx.send(ServerMessage::AchievementUnlocked {
+                                profile: profile.clone(),
+                                achievement: Box::new(achievement),
+                            });
+                        }
+                        // After the unlocks, so clients have shown them before reloading
+                        if let Some((changes, totals)) = progress_delta(&previous, &progress) {
+                            let _ = options.broadcast_tx.send(ServerMessage::ProgressChanged {
+                                profile: profile.clone(),
+                                changes,
+                                totals,
+                            });
+                        }
+                        Ok(progress.clone())
+                    }
+                    Err(e) => {
+                        // Keep memory in line with what is on disk
+                        error!("Failed to save progress: {}", e);
+                        progress = previous;
+                        Err(e)
+                    }
+                };
+                let _ = reply.send(result);
+            }
+        }
+    }
+}
+
+pub struct JsonProgressStore {
+    path: PathBuf,
+    last_backup: Option<Instant>,
+}
+
+impl JsonProgressStore {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, last_backup: None }
+    }
+}
+
+impl ProgressStore for JsonProgressStore {
+    fn load(&mut self) -> anyhow::Result<Option<ProgressData>> {
+        read_json_progress(&self.path)
+    }
+
+    fn save(&mut self, progress: &ProgressData) -> anyhow::Result<()> {
+        let backup_due = self
+            .last_backup
+            .is_none_or(|last| last.elapsed() >= BACKUP_INTERVAL);
+        if backup_due && rotate_backups(&self.path)? {
+            self.last_backup = Some(Instant::now());
+        }
+
+        let content = serde_json::to_vec_pretty(progress)?;
+        write_atomic(&self.path, &content)
+    }
+}
+
+// Read a progress file, upgrading it in place if it has an older schema. The
+// original is kept next to it as `user_progress.json.v<N>`.
+pub fn read_json_progress(path: &Path) -> anyhow::Result<Option<ProgressData>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let content = fs::read_to_string(path)?;
+    let stored: serde_json::Value = serde_json::from_str(&content)?;
+    let (upgraded, version) = progress_schema::upgrade(stored, &path.display().to_string())?;
+    let progress: ProgressData = serde_json::from_value(upgraded)?;
+
+    if version < progress_schema::PROGRESS_SCHEMA_VERSION {
+        let original = pre_migration_path(path, version);
+        if !original.exists() {
+            fs::copy(path, &original)?;
+        }
+        write_atomic(path, &serde_json::to_vec_pretty(&progress)?)?;
+        info!("Kept the schema {} original as {}", version, original.display());
+    }
+    Ok(Some(progress))
+}
+
+fn pre_migration_path(path: &Path, version: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".v{}", version));
+    PathBuf::from(name)
+}
+
+fn acquire_lock(progress_path: &Path) -> anyhow::Result<File> {
+    let lock_path = progress_path.with_extension("lock");
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)?;
+
+    if lock.try_lock_exclusive().is_err() {
+        anyhow::bail!(
+            "Progress directory {} is in use by another Rust Tour server (lock file: {})",
+            progress_path.parent().unwrap_or(Path::new(".")).display(),
+            lock_path.display()
+        );
+    }
+
+    Ok(lock)
+}
+
+pub fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".bak.{}", index));
+    PathBuf::from(name)
+}
+
+// Shift `.bak.1` .. `.bak.N-1` up by one and copy the current file to `.bak.1`
+fn rotate_backups(path: &Path) -> anyhow::Result<bool> {
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    for index in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, index);
+        if from.exists() {
+            fs::rename(&from, backup_path(path, index + 1))?;
+        }
+    }
+    fs::copy(path, backup_path(path, 1))?;
+    Ok(true)
+}
+
+// Write through a temporary file in the same directory, then rename it over the target
+fn write_atomic(path: &Path, content: &[u8]) -> anyhow::Result<()> {
+    let mut tmp_name = path.as_os_str().to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_name);
+
+    let mut tmp = File::create(&tmp_path)?;
+    tmp.write_all(content)?;
+    tmp.sync_all()?;
+    drop(tmp);
+
+    if let Err(e) = fs::rename(&tmp_path, path) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(e.into());
+    }
+
+    // Make the rename itself durable
+    #[cfg(unix)]
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        if let Err(e) = File::open(parent).and_then(|dir| dir.sync_all()) {
+            warn!("Failed to sync progress directory: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_log::EventLog;
+
+    fn options() -> ProgressOptions {
+        let (broadcast_tx, _) = broadcast::channel(16);
+        ProgressOptions {
+            backend: ProgressBackend::Json,
+            streaks: StreakConfig::default(),
+            achievements: Arc::new(AchievementCatalog::load(None).unwrap()),
+            broadcast_tx,
+            event_log: EventLog::new(1),
+        }
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file_without_leaving_a_temporary_one() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user_progress.json");
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+        let files: Vec<_> = fs::read_dir(dir.path()).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert_eq!(files, vec!["user_progress.json"]);
+    }
+
+    #[test]
+    fn rotate_backups_keeps_the_most_recent_copies() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("user_progress.json");
+        assert!(!rotate_backups(&path).unwrap());
+
+        for version in 1..=BACKUP_COUNT + 2 {
+            write_atomic(&path, format!("version {}", version).as_bytes()).unwrap();
+            assert!(rotate_backups(&path).unwrap());
+        }
+
+        let latest = BACKUP_COUNT + 2;
+        for index in 1..=BACKUP_COUNT {
+            let backup = fs::read_to_string(backup_path(&path, index)).unwrap();
+            assert_eq!(backup, format!("version {}", latest + 1 - index));
+        }
+        assert!(!backup_path(&path, BACKUP_COUNT + 1).exists());
+        assert_eq!(fs::read_to_string(&path).unwrap(), format!("version {}", latest));
+    }
+
+    #[tokio::test]
+    async fn a_second_open_of_the_same_directory_is_refused() {
+        let dir = tempfile::tempdir().unwrap();
+        let progress_path = dir.path().join("progress").join("user_progress.json");
+        let exercises_path = dir.path().join("exercises");
+
+        let first = ProgressHandle::open(options(), progress_path.clone(), &exercises_path, "learner")
+            .await
+            .unwrap();
+        let Err(e) = ProgressHandle::open(options(), progress_path.clone(), &exercises_path, "learner").await else {
+            panic!("the progress directory was opened twice");
+        };
+        assert!(e.to_string().contains("in use by another Rust Tour server"), "{}", e);
+        // The first one still works
+        assert_eq!(first.snapshot().await.unwrap().user_id, "learner");
+    }
+}
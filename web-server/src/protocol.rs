@@ -0,0 +1,453 @@
+// WebSocket protocol
+//
+// Every message is a JSON object whose `type` field selects the variant. The
+// server greets each connection with `hello`; clients answer with their own
+// `hello` carrying the protocol version they speak. Clients that skip the
+// handshake are treated as version 1. Malformed messages are answered with an
+// `error` message instead of being dropped silently.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::sync::LazyLock;
+
+use crate::{
+    collab::{Participant, SelectionRange, TextOperation},
+    achievements::Achievement,
+    preferences::UserPreferences,
+    progress_changes::{ProgressChange, ProgressTotals},
+    CargoResult, CompletionSource, ProgressData,
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// Messages sent from the browser to the server
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Hello {
+        protocol_version: u32,
+        #[serde(default)]
+        client: Option<String>,
+    },
+    Terminal(TerminalRequest),
+    Collab(CollabRequest),
+    Heartbeat {
+        #[serde(default)]
+        timestamp: Option<u64>,
+    },
+    // Record that the learner opened an exercise
+    ExerciseView {
+        exercise_id: String,
+    },
+    // Run, test or check an exercise; the result is sent back as `code_execution_result`
+    CodeExecution {
+        exercise_id: String,
+        action: CodeAction,
+    },
+    // Ask the server to fan the current progress out to every connected client
+    ProgressUpdate {
+        #[serde(default)]
+        exercise_id: Option<String>,
+    },
+}
+
+impl ClientMessage {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            ClientMessage::Hello { .. } => "hello",
+            ClientMessage::Terminal(_) => "terminal",
+            ClientMessage::Collab(_) => "collab",
+            ClientMessage::Heartbeat { .. } => "heartbeat",
+            ClientMessage::ExerciseView { .. } => "exercise_view",
+            ClientMessage::CodeExecution { .. } => "code_execution",
+            ClientMessage::ProgressUpdate { .. } => "progress_update",
+        }
+    }
+}
+
+// The `type` of every client message, read from the schema derived from
+// `ClientMessage` so it follows the serde attributes of its variants
+static CLIENT_MESSAGE_TYPES: LazyLock<Vec<String>> = LazyLock::new(|| {
+    let schema = serde_json::to_value(schemars::schema_for!(ClientMessage)).unwrap_or_default();
+    schema["oneOf"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|variant| variant["properties"]["type"]["enum"][0].as_str())
+        .map(str::to_string)
+        .collect()
+});
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TerminalRequest {
+    Create {
+        #[serde(rename = "sessionId", default)]
+        session_id: Option<String>,
+        #[serde(default)]
+        cols: Option<u16>,
+        #[serde(default)]
+        rows: Option<u16>,
+        // Receive output as binary frames instead of JSON text messages
+        #[serde(default)]
+        binary: bool,
+    },
+    Check {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        #[serde(default)]
+        binary: Option<bool>,
+    },
+    Input {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        input: String,
+    },
+    Resize {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        cols: u16,
+        rows: u16,
+    },
+    Destroy {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+    },
+    Share {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        #[serde(default)]
+        ttl_seconds: Option<u64>,
+    },
+    RevokeShare {
+        token: String,
+    },
+}
+
+// Collaborative editing; positions and lengths are UTF-16 code units
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CollabRequest {
+    Join {
+        exercise_id: String,
+        file: String,
+    },
+    Leave {
+        document_id: String,
+    },
+    // An edit made on top of `revision`, the last revision the client has seen
+    Operation {
+        document_id: String,
+        revision: u64,
+        operation: TextOperation,
+    },
+    Selection {
+        document_id: String,
+        revision: u64,
+        ranges: Vec<SelectionRange>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CodeAction {
+    Run,
+    Test,
+    Check,
+}
+
+// Messages sent from the server to the browser
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Hello {
+        protocol_version: u32,
+        min_protocol_version: u32,
+        server_version: String,
+        connection_id: String,
+    },
+    HelloAck {
+        protocol_version: u32,
+    },
+    Error {
+        code: ErrorCode,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        request_type: Option<String>,
+    },
+    HeartbeatResponse {
+        timestamp: u64,
+        server_time: u64,
+    },
+    Terminal(TerminalEvent),
+    Collab(CollabEvent),
+    FileUpdated {
+        exercise: String,
+        file: String,
+    },
+    FileChanged {
+        exercise: String,
+        file: String,
+    },
+    CodeExecutionResult {
+        exercise_id: String,
+        action: CodeAction,
+        #[serde(flatten)]
+        result: CargoResult,
+    },
+    ProgressUpdated {
+        // Only delivered to clients of this learner profile
+        profile: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        exercise_id: Option<String>,
+        progress: Box<ProgressData>,
+    },
+    // Sent after every saved change to the profile's progress, see `progress_changes`
+    ProgressChanged {
+        profile: String,
+        changes: Vec<ProgressChange>,
+        totals: ProgressTotals,
+    },
+    // The profile's preferences were changed, see `preferences`
+    PreferencesChanged {
+        profile: String,
+        preferences: UserPreferences,
+    },
+    // An exercise of the profile was marked completed
+    ExerciseCompleted {
+        profile: String,
+        exercise_id: String,
+        completed_by: CompletionSource,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        time_taken_minutes: Option<u32>,
+    },
+    // The profile's learner just earned an achievement
+    AchievementUnlocked {
+        profile: String,
+        achievement: Box<Achievement>,
+    },
+    // Server-Sent Events only: missed events can't be replayed, reload state over REST
+    Resync {
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    // Not JSON, or missing a string `type` field
+    MalformedMessage,
+    UnknownMessageType,
+    // Known type with missing or mistyped fields
+    InvalidMessage,
+    UnsupportedProtocolVersion,
+    InvalidExercise,
+    ExecutionFailed,
+    Internal,
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum TerminalEvent {
+    Created {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+    },
+    Exists {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+    },
+    NotFound {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+    },
+    Output {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        data: String,
+    },
+    Exit {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+    },
+    Error {
+        #[serde(rename = "sessionId", skip_serializing_if = "Option::is_none")]
+        session_id: Option<String>,
+        message: String,
+    },
+    Shared {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        token: String,
+        #[serde(rename = "expiresAt")]
+        expires_at: String,
+        #[serde(rename = "viewerPath")]
+        viewer_path: String,
+    },
+    ShareRevoked {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        token: String,
+    },
+    ViewStarted {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        #[serde(rename = "readOnly")]
+        read_only: bool,
+        #[serde(rename = "expiresAt")]
+        expires_at: String,
+    },
+    ViewEnded {
+        #[serde(rename = "sessionId")]
+        session_id: String,
+        reason: String,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum CollabEvent {
+    Joined {
+        document_id: String,
+        revision: u64,
+        content: String,
+        participants: Vec<Participant>,
+    },
+    // The sender's operation was applied as `revision`
+    Ack {
+        document_id: String,
+        revision: u64,
+    },
+    Operation {
+        document_id: String,
+        revision: u64,
+        connection_id: String,
+        operation: TextOperation,
+    },
+    Selection {
+        document_id: String,
+        connection_id: String,
+        ranges: Vec<SelectionRange>,
+    },
+    ParticipantJoined {
+        document_id: String,
+        participant: Participant,
+    },
+    ParticipantLeft {
+        document_id: String,
+        connection_id: String,
+    },
+    Saved {
+        document_id: String,
+        revision: u64,
+    },
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        document_id: Option<String>,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    pub fn error(code: ErrorCode, message: impl Into<String>, request_type: Option<&str>) -> Self {
+        ServerMessage::Error {
+            code,
+            message: message.into(),
+            request_type: request_type.map(str::to_string),
+        }
+    }
+
+    // Profile a message is private to, if any
+    pub fn profile(&self) -> Option<&str> {
+        match self {
+            ServerMessage::ProgressUpdated { profile, .. }
+            | ServerMessage::ProgressChanged { profile, .. }
+            | ServerMessage::PreferencesChanged { profile, .. }
+            | ServerMessage::ExerciseCompleted { profile, .. }
+            | ServerMessage::AchievementUnlocked { profile, .. } => Some(profile),
+            _ => None,
+        }
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+// Parse a client message, classifying failures so the client gets a useful error
+pub fn parse_client_message(text: &str) -> Result<ClientMessage, ServerMessage> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(|e| {
+        ServerMessage::error(ErrorCode::MalformedMessage, format!("Invalid JSON: {}", e), None)
+    })?;
+
+    let Some(msg_type) = value.get("type").and_then(|t| t.as_str()).map(str::to_string) else {
+        return Err(ServerMessage::error(
+            ErrorCode::MalformedMessage,
+            "Message must be a JSON object with a string \"type\" field",
+            None,
+        ));
+    };
+
+    if !CLIENT_MESSAGE_TYPES.contains(&msg_type) {
+        return Err(ServerMessage::error(
+            ErrorCode::UnknownMessageType,
+            format!("Unknown message type: {}", msg_type),
+            Some(&msg_type),
+        ));
+    }
+
+    serde_json::from_value(value).map_err(|e| {
+        ServerMessage::error(
+            ErrorCode::InvalidMessage,
+            format!("Invalid {} message: {}", msg_type, e),
+            Some(&msg_type),
+        )
+    })
+}
+
+pub fn is_supported_version(version: u32) -> bool {
+    (MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&version)
+}
+
+// JSON Schema for both directions of the protocol
+pub fn protocol_schema() -> serde_json::Value {
+    serde_json::json!({
+        "protocol_version": PROTOCOL_VERSION,
+        "min_protocol_version": MIN_PROTOCOL_VERSION,
+        "client_message": schemars::schema_for!(ClientMessage),
+        "server_message": schemars::schema_for!(ServerMessage),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_message_types_match_type_names() {
+        let samples = [
+            r#"{"type": "hello", "protocol_version": 1}"#,
+            r#"{"type": "terminal", "action": "create"}"#,
+            r#"{"type": "collab", "action": "leave", "document_id": "d"}"#,
+            r#"{"type": "heartbeat"}"#,
+            r#"{"type": "exercise_view", "exercise_id": "e"}"#,
+            r#"{"type": "code_execution", "exercise_id": "e", "action": "run"}"#,
+            r#"{"type": "progress_update"}"#,
+        ];
+        let names: Vec<&str> = samples
+            .iter()
+            .map(|sample| parse_client_message(sample).expect("sample parses").type_name())
+            .collect();
+        assert_eq!(*CLIENT_MESSAGE_TYPES, names);
+    }
+
+    #[test]
+    fn unknown_and_invalid_messages_are_told_apart() {
+        let unknown = parse_client_message(r#"{"type": "shutdown"}"#).unwrap_err();
+        assert!(matches!(unknown, ServerMessage::Error { code: ErrorCode::UnknownMessageType, .. }));
+
+        let invalid = parse_client_message(r#"{"type": "exercise_view"}"#).unwrap_err();
+        assert!(matches!(invalid, ServerMessage::Error { code: ErrorCode::InvalidMessage, .. }));
+    }
+}
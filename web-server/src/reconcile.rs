@@ -0,0 +1,250 @@
+// Reconcile progress with the exercises on disk
+//
+// Progress only knows about completions reported through the web UI, so an
+// exercise solved in another editor, or progress lost with its file, shows as
+// not done. Reconciling runs the tests of every exercise the profile has edited,
+// a few at a time, and:
+//
+//   - marks exercises whose tests all pass as completed, with
+//     `completed_by: "reconcile"`
+//   - reports completed exercises whose tests fail now as regressed, without
+//     taking the completion away
+//   - skips exercises whose sources were never changed
+//   - doesn't complete exercises whose tests differ from the originals, or
+//     that have no original tests to compare with
+//
+// The default profile can only tell untouched exercises apart once it has a
+// pristine copy (see `profiles`); without one the exercise is tested.
+//
+// Available as `POST /api/progress/reconcile` and `rust-tour reconcile`.
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, sync::Arc, time::Instant};
+use tokio::{sync::Semaphore, task::JoinSet};
+use tracing::{error, info};
+
+use crate::{
+    event_log::{self, completion_event},
+    exercise_completed_message,
+    profiles::{ActiveProfile, ProfileRegistry},
+    protocol::ServerMessage,
+    record_completion, run_cargo_command, scan_exercises,
+    test_runs::TestRun,
+    AppState, CompletionSource, ProgressData,
+};
+
+// Test runs at a time; cargo already uses every core while compiling
+pub fn default_jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|cores| (cores.get() / 2).max(1))
+        .unwrap_or(1)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Regression {
+    pub exercise_id: String,
+    pub failing_tests: Vec<String>,
+    pub compiled: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ReconcileError {
+    pub exercise_id: String,
+    pub error: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ReconcileReport {
+    // Tests pass; now marked completed
+    pub verified: Vec<String>,
+    // Already completed and the tests still pass
+    pub still_passing: Vec<String>,
+    // Completed before, but the tests fail now
+    pub regressed: Vec<Regression>,
+    // Not completed and the tests don't pass
+    pub failing: Vec<String>,
+    // Tests pass, but the test files differ from the original; not completed
+    pub tests_modified: Vec<String>,
+    // Sources never changed, not tested
+    pub untouched: Vec<String>,
+    // The tests couldn't be run
+    pub errors: Vec<ReconcileError>,
+}
+
+pub async fn reconcile(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    jobs: usize,
+) -> anyhow::Result<(ReconcileReport, ProgressData)> {
+    let progress = profiles.progress(profile).await?;
+    let completed: HashSet<String> = progress
+        .snapshot()
+        .await?
+        .exercise_history
+        .into_iter()
+        .filter(|entry| entry.completed_at.is_some())
+        .map(|entry| entry.exercise_id)
+        .collect();
+
+    let mut report = ReconcileReport::default();
+    let semaphore = Arc::new(Semaphore::new(jobs.max(1)));
+    let mut runs = JoinSet::new();
+    for exercise in scan_exercises(profiles.exercises_path()).await? {
+        let exercise_id = exercise.metadata.id;
+        if profiles.is_edited(profile, &exercise.path).await? == Some(false) {
+            report.untouched.push(exercise_id);
+            continue;
+        }
+
+        let dir = profiles.exercise_dir(profile, &exercise.path);
+        let semaphore = semaphore.clone();
+        runs.spawn(async move {
+            let _permit = semaphore.acquire_owned().await.expect("semaphore is never closed");
+            let started = Instant::now();
+            let run = run_cargo_command("test", &dir, vec![])
+                .await
+                .map(|result| TestRun::from_output(&result, started.elapsed()));
+            (exercise_id, exercise.path, run)
+        });
+    }
+
+    info!("Reconciling profile {}: testing {} exercises, {} at a time", profile, runs.len(), jobs.max(1));
+    while let Some(joined) = runs.join_next().await {
+        let (exercise_id, exercise_path, run) = joined?;
+        let was_completed = completed.contains(&exercise_id);
+        match run {
+            Ok(run) if run.proves_completion() => {
+                if was_completed {
+                    report.still_passing.push(exercise_id);
+                } else {
+                    match profiles.modified_tests(profile, &exercise_path).await {
+                        Ok(modified) if modified.is_empty() => report.verified.push(exercise_id),
+                        Ok(_) => report.tests_modified.push(exercise_id),
+                        // Without an original to compare with the pass can't be trusted
+                        Err(e) => report.errors.push(ReconcileError {
+                            exercise_id,
+                            error: e.to_string(),
+                        }),
+                    }
+                }
+            }
+            Ok(run) if was_completed => report.regressed.push(Regression {
+                exercise_id,
+                failing_tests: run.failing_tests,
+                compiled: run.compiled,
+            }),
+            Ok(_) => report.failing.push(exercise_id),
+            Err(e) => report.errors.push(ReconcileError {
+                exercise_id,
+                error: e.to_string(),
+            }),
+        }
+    }
+
+    report.verified.sort();
+    report.still_passing.sort();
+    report.regressed.sort_by(|a, b| a.exercise_id.cmp(&b.exercise_id));
+    report.failing.sort();
+    report.tests_modified.sort();
+    report.untouched.sort();
+    report.errors.sort_by(|a, b| a.exercise_id.cmp(&b.exercise_id));
+
+    let verified = report.verified.clone();
+    let progress = progress
+        .update(move |progress| {
+            let mut changed = false;
+            for exercise_id in &verified {
+                changed |= record_completion(progress, exercise_id, None, None, CompletionSource::Reconcile);
+            }
+            changed
+        })
+        .await?;
+    for exercise_id in &report.verified {
+        if let Some(event) = completion_event(&progress, exercise_id) {
+            event_log::append_event(profiles, profile, None, event).await;
+        }
+    }
+
+    info!(
+        "Reconciled profile {}: {} verified, {} regressed, {} failing, {} untouched, {} errors",
+        profile,
+        report.verified.len(),
+        report.regressed.len(),
+        report.failing.len(),
+        report.untouched.len(),
+        report.errors.len()
+    );
+    Ok((report, progress))
+}
+
+// `rust-tour reconcile`, run instead of the server
+pub async fn reconcile_command(profiles: &ProfileRegistry, profile: &str, jobs: usize) -> anyhow::Result<()> {
+    println!("🔍 Running the tests of the exercises in profile '{}'...", profile);
+    let (report, _) = reconcile(profiles, profile, jobs).await?;
+
+    println!("✅ Verified and marked completed: {}", report.verified.len());
+    for exercise_id in &report.verified {
+        println!("   - {}", exercise_id);
+    }
+    println!("   Already completed and passing: {}", report.still_passing.len());
+    if !report.regressed.is_empty() {
+        println!("⚠️  Completed but failing now: {}", report.regressed.len());
+        for regression in &report.regressed {
+            if regression.compiled {
+                println!("   - {} ({})", regression.exercise_id, regression.failing_tests.join(", "));
+            } else {
+                println!("   - {} (does not compile)", regression.exercise_id);
+            }
+        }
+    }
+    println!("   Not passing yet: {}", report.failing.len());
+    for exercise_id in &report.tests_modified {
+        println!("⚠️  {} passes, but its test files were changed; not completed", exercise_id);
+    }
+    println!("   Untouched, not tested: {}", report.untouched.len());
+    for failure in &report.errors {
+        println!("❌ {}: {}", failure.exercise_id, failure.error);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReconcileRequest {
+    #[serde(default)]
+    jobs: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReconcileResponse {
+    #[serde(flatten)]
+    report: ReconcileReport,
+    progress: ProgressData,
+}
+
+// API handler
+pub async fn reconcile_progress(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Json(request): Json<ReconcileRequest>,
+) -> Result<Json<ReconcileResponse>, (StatusCode, String)> {
+    let jobs = request.jobs.unwrap_or_else(default_jobs);
+    let (report, progress) = reconcile(&state.profiles, &profile, jobs).await.map_err(|e| {
+        error!("Error reconciling progress for profile {}: {}", profile, e);
+        (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to reconcile: {}", e))
+    })?;
+
+    if !report.verified.is_empty() {
+        let _ = state.broadcast_tx.send(ServerMessage::ProgressUpdated {
+            profile: profile.clone(),
+            exercise_id: None,
+            progress: Box::new(progress.clone()),
+        });
+        for exercise_id in &report.verified {
+            if let Some(message) = exercise_completed_message(&profile, &progress, exercise_id) {
+                let _ = state.broadcast_tx.send(message);
+            }
+        }
+    }
+    Ok(Json(ReconcileResponse { report, progress }))
+}
@@ -0,0 +1,392 @@
+// Terminal session recording in asciicast v2 format
+//
+// Recordings are written next to the progress file (under `progress/recordings/`)
+// when the server is started with `--record-terminals`. Each file starts with a
+// JSON header line followed by one `[elapsed, code, data]` event per line, where
+// code is "o" for output, "i" for input and "r" for a resize ("COLSxROWS").
+// See https://docs.asciinema.org/manual/asciicast/v2/ for the format.
+
+use axum::{
+    body::Body,
+    extract::{Path as AxumPath, Query, State},
+    http::{header, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, LineWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use tokio::fs;
+use tracing::{error, info, warn};
+
+use crate::AppState;
+
+const RECORDING_EXTENSION: &str = "cast";
+const MIN_REPLAY_SPEED: f64 = 0.1;
+const MAX_REPLAY_SPEED: f64 = 100.0;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AsciicastHeader {
+    version: u32,
+    width: u16,
+    height: u16,
+    timestamp: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default)]
+    env: serde_json::Value,
+}
+
+// Writes asciicast v2 events for a single terminal session
+pub struct AsciicastRecorder {
+    writer: LineWriter<File>,
+    started: Instant,
+    path: PathBuf,
+}
+
+impl AsciicastRecorder {
+    pub fn create(
+        recordings_dir: &Path,
+        session_id: &str,
+        shell: &str,
+        cols: u16,
+        rows: u16,
+    ) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(recordings_dir)?;
+
+        let now = chrono::Utc::now();
+        let file_name = format!(
+            "{}_{}.{}",
+            sanitize_file_component(session_id),
+            now.format("%Y%m%dT%H%M%S"),
+            RECORDING_EXTENSION
+        );
+        let path = recordings_dir.join(file_name);
+
+        let header = AsciicastHeader {
+            version: 2,
+            width: cols,
+            height: rows,
+            timestamp: now.timestamp(),
+            title: Some(format!("Rust Tour terminal {}", session_id)),
+            env: serde_json::json!({
+                "SHELL": shell,
+                "TERM": "xterm-color"
+            }),
+        };
+
+        let mut writer = LineWriter::new(File::create(&path)?);
+        writeln!(writer, "{}", serde_json::to_string(&header)?)?;
+
+        info!("Recording terminal session {} to {}", session_id, path.display());
+
+        Ok(Self {
+            writer,
+            started: Instant::now(),
+            path,
+        })
+    }
+
+    pub fn output(&mut self, data: &str) {
+        self.write_event("o", data);
+    }
+
+    pub fn input(&mut self, data: &str) {
+        self.write_event("i", data);
+    }
+
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.write_event("r", &format!("{}x{}", cols, rows));
+    }
+
+    fn write_event(&mut self, code: &str, data: &str) {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let event = serde_json::json!([(elapsed * 1_000_000.0).round() / 1_000_000.0, code, data]);
+        if let Err(e) = writeln!(self.writer, "{}", event) {
+            warn!("Failed to write terminal recording {}: {}", self.path.display(), e);
+        }
+    }
+}
+
+fn sanitize_file_component(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect()
+}
+
+pub fn recordings_dir(state: &AppState) -> PathBuf {
+    state
+        .progress_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."))
+        .join("recordings")
+}
+
+// Resolve a recording id from the URL to a file inside the recordings directory
+fn recording_path(state: &AppState, recording_id: &str) -> Option<PathBuf> {
+    if recording_id.is_empty()
+        || recording_id.contains("..")
+        || recording_id.contains('/')
+        || recording_id.contains('\\')
+    {
+        return None;
+    }
+
+    let file_name = if recording_id.ends_with(&format!(".{}", RECORDING_EXTENSION)) {
+        recording_id.to_string()
+    } else {
+        format!("{}.{}", recording_id, RECORDING_EXTENSION)
+    };
+
+    let path = recordings_dir(state).join(file_name);
+    path.is_file().then_some(path)
+}
+
+#[derive(Debug, Serialize)]
+pub struct RecordingInfo {
+    id: String,
+    file_name: String,
+    size_bytes: u64,
+    created_at: Option<String>,
+    width: Option<u16>,
+    height: Option<u16>,
+    duration_seconds: f64,
+    event_count: usize,
+}
+
+fn read_recording_info(path: &Path) -> anyhow::Result<RecordingInfo> {
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let id = file_name
+        .strip_suffix(&format!(".{}", RECORDING_EXTENSION))
+        .unwrap_or(&file_name)
+        .to_string();
+    let size_bytes = std::fs::metadata(path)?.len();
+
+    let reader = BufReader::new(File::open(path)?);
+    let mut lines = reader.lines();
+
+    let header = lines
+        .next()
+        .transpose()?
+        .and_then(|line| serde_json::from_str::<AsciicastHeader>(&line).ok());
+
+    let mut duration_seconds = 0.0;
+    let mut event_count = 0;
+    for line in lines {
+        let line = line?;
+        if let Ok(serde_json::Value::Array(event)) = serde_json::from_str::<serde_json::Value>(&line) {
+            if let Some(time) = event.first().and_then(|t| t.as_f64()) {
+                duration_seconds = time;
+            }
+            event_count += 1;
+        }
+    }
+
+    let created_at = header
+        .as_ref()
+        .and_then(|h| chrono::DateTime::from_timestamp(h.timestamp, 0))
+        .map(|t| t.to_rfc3339());
+
+    Ok(RecordingInfo {
+        id,
+        file_name,
+        size_bytes,
+        created_at,
+        width: header.as_ref().map(|h| h.width),
+        height: header.as_ref().map(|h| h.height),
+        duration_seconds,
+        event_count,
+    })
+}
+
+// API handlers
+pub async fn list_recordings(State(state): State<AppState>) -> Result<Json<Vec<RecordingInfo>>, StatusCode> {
+    let dir = recordings_dir(&state);
+    let mut recordings = Vec::new();
+
+    if !dir.exists() {
+        return Ok(Json(recordings));
+    }
+
+    let mut entries = fs::read_dir(&dir).await.map_err(|e| {
+        error!("Error reading recordings directory {}: {}", dir.display(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    while let Some(entry) = entries.next_entry().await.ok().flatten() {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(RECORDING_EXTENSION) {
+            continue;
+        }
+
+        match tokio::task::spawn_blocking(move || read_recording_info(&path)).await {
+            Ok(Ok(info)) => recordings.push(info),
+            Ok(Err(e)) => warn!("Skipping unreadable recording: {}", e),
+            Err(e) => warn!("Failed to inspect recording: {}", e),
+        }
+    }
+
+    // Newest recordings first
+    recordings.sort_by(|a, b| b.created_at.cmp(&a.created_at));
+
+    Ok(Json(recordings))
+}
+
+pub async fn download_recording(
+    AxumPath(recording_id): AxumPath<String>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let path = recording_path(&state, &recording_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let content = fs::read(&path).await.map_err(|e| {
+        error!("Error reading recording {}: {}", path.display(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let file_name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("{}.{}", recording_id, RECORDING_EXTENSION));
+    let disposition = HeaderValue::from_str(&format!("attachment; filename=\"{}\"", file_name))
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, HeaderValue::from_static("application/x-asciicast")),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        content,
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReplayParams {
+    // Playback speed multiplier, 1.0 replays in real time
+    speed: Option<f64>,
+    // Cap on pauses between events, in seconds
+    max_idle: Option<f64>,
+}
+
+// The header line and the timed event lines of a recording
+fn parse_recording(content: &str) -> Option<(String, Vec<(f64, String)>)> {
+    let mut lines = content.lines().map(str::to_string);
+    let header = lines.next()?;
+    let events = lines
+        .filter_map(|line| {
+            let time = serde_json::from_str::<serde_json::Value>(&line)
+                .ok()?
+                .get(0)?
+                .as_f64()?;
+            Some((time, line))
+        })
+        .collect();
+    Some((header, events))
+}
+
+// Pause before an event at `time` that follows one at `last_time`
+fn replay_delay(last_time: f64, time: f64, speed: f64, max_idle: Option<f64>) -> Duration {
+    let mut delay = (time - last_time).max(0.0);
+    if let Some(max_idle) = max_idle {
+        delay = delay.min(max_idle);
+    }
+    // Timestamps too large for a `Duration` are replayed without a pause
+    Duration::try_from_secs_f64(delay / speed).unwrap_or(Duration::ZERO)
+}
+
+// Stream a recording back with its original timing, one asciicast line per chunk
+pub async fn replay_recording(
+    AxumPath(recording_id): AxumPath<String>,
+    Query(params): Query<ReplayParams>,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    let path = recording_path(&state, &recording_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    let content = fs::read_to_string(&path).await.map_err(|e| {
+        error!("Error reading recording {}: {}", path.display(), e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    let speed = params
+        .speed
+        .filter(|s| *s > 0.0)
+        .map_or(1.0, |s| s.clamp(MIN_REPLAY_SPEED, MAX_REPLAY_SPEED));
+    let max_idle = params.max_idle.filter(|m| *m > 0.0);
+
+    let (header, events) = parse_recording(&content).ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let stream = futures_util::stream::unfold(
+        (Some(header), events.into_iter(), 0.0_f64),
+        move |(header, mut events, last_time)| async move {
+            if let Some(header) = header {
+                return Some((Ok::<_, std::io::Error>(format!("{}\n", header)), (None, events, last_time)));
+            }
+
+            let (time, line) = events.next()?;
+            tokio::time::sleep(replay_delay(last_time, time, speed, max_idle)).await;
+
+            Some((Ok(format!("{}\n", line)), (None, events, time)))
+        },
+    );
+
+    Ok((
+        [(header::CONTENT_TYPE, HeaderValue::from_static("application/x-asciicast"))],
+        Body::from_stream(stream),
+    )
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_round_trip_through_the_reader() {
+        let dir = tempfile::tempdir().expect("temp dir");
+        let mut recorder = AsciicastRecorder::create(dir.path(), "session/1", "/bin/bash", 100, 30).expect("recorder");
+        recorder.output("hello\r\n");
+        recorder.input("ls\r");
+        recorder.resize(120, 40);
+        let path = recorder.path.clone();
+        drop(recorder);
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let (header, events) = parse_recording(&content).expect("recording parses");
+        let header: AsciicastHeader = serde_json::from_str(&header).unwrap();
+        assert_eq!((header.version, header.width, header.height), (2, 100, 30));
+        assert_eq!(header.title.as_deref(), Some("Rust Tour terminal session/1"));
+        assert_eq!(header.env["SHELL"], "/bin/bash");
+
+        let events: Vec<serde_json::Value> = events
+            .iter()
+            .map(|(_, line)| serde_json::from_str(line).unwrap())
+            .collect();
+        let codes_and_data: Vec<(&str, &str)> = events
+            .iter()
+            .map(|event| (event[1].as_str().unwrap(), event[2].as_str().unwrap()))
+            .collect();
+        assert_eq!(codes_and_data, [("o", "hello\r\n"), ("i", "ls\r"), ("r", "120x40")]);
+
+        let info = read_recording_info(&path).unwrap();
+        assert!(path.file_name().unwrap().to_string_lossy().starts_with("session_1_"));
+        assert_eq!((info.width, info.height, info.event_count), (Some(100), Some(30), 3));
+        assert!(info.created_at.is_some());
+    }
+
+    #[test]
+    fn replay_delay_scales_caps_and_never_panics() {
+        assert_eq!(replay_delay(1.0, 3.0, 2.0, None), Duration::from_secs(1));
+        assert_eq!(replay_delay(1.0, 11.0, 1.0, Some(2.5)), Duration::from_millis(2500));
+        // Out of order events don't wait
+        assert_eq!(replay_delay(3.0, 1.0, 1.0, None), Duration::ZERO);
+        assert_eq!(replay_delay(0.0, 1e300, MIN_REPLAY_SPEED, None), Duration::ZERO);
+    }
+}
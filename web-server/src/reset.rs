@@ -0,0 +1,280 @@
+// Progress reset
+//
+// Clears what a profile has done on one exercise, one chapter or everything,
+// so exercises can be redone from scratch: the history entries go, and with
+// them completion, hint levels, measured time and test runs. Streaks, chapter
+// statistics and time totals are derived from the history and follow. A reset
+// of everything also clears achievements and activity sessions; narrower
+// resets keep achievements, which are never taken away otherwise.
+//
+// Optionally the exercises' original sources are put back as well (see
+// `ProfileRegistry::restore_exercise`). Every reset is kept in `resets` in the
+// profile's progress, so the history shows what was cleared and when.
+//
+// Available as `POST /api/progress/reset` and `rust-tour reset`.
+
+use axum::{extract::State, http::StatusCode, Json};
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tracing::{error, info};
+
+use crate::{
+    chapter_stats::exercise_chapter,
+    collab,
+    event_log::{self, LearnerEventKind},
+    load_exercise_title,
+    profiles::{self, ActiveProfile, ProfileRegistry, DEFAULT_PROFILE},
+    protocol::ServerMessage,
+    scan_exercises, AppState, ExerciseWithPath, ProgressData, SessionStats,
+};
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(tag = "scope", rename_all = "snake_case")]
+pub enum ResetScope {
+    // Metadata id or "chapter/exercise" path
+    Exercise { exercise_id: String },
+    Chapter { chapter: u32 },
+    All,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetRequest {
+    #[serde(flatten)]
+    pub scope: ResetScope,
+    #[serde(default)]
+    pub restore_sources: bool,
+}
+
+// A reset as recorded in `ProgressData.resets`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct ResetRecord {
+    pub reset_at: String,
+    #[serde(flatten)]
+    pub scope: ResetScope,
+    // History entries that were cleared
+    #[serde(default)]
+    pub exercises: Vec<String>,
+    // "chapter/exercise" paths whose original sources were put back
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub restored_sources: Vec<String>,
+}
+
+// Exercises on disk the scope covers; empty when an exercise or chapter doesn't exist
+pub async fn resolve(profiles: &ProfileRegistry, scope: &ResetScope) -> anyhow::Result<Vec<ExerciseWithPath>> {
+    let exercises = scan_exercises(profiles.exercises_path()).await?;
+    Ok(exercises
+        .into_iter()
+        .filter(|exercise| match scope {
+            ResetScope::Exercise { exercise_id } => {
+                exercise.metadata.id == *exercise_id || exercise.path == *exercise_id
+            }
+            ResetScope::Chapter { chapter } => exercise.metadata.chapter == *chapter,
+            ResetScope::All => true,
+        })
+        .collect())
+}
+
+pub struct ResetOutcome {
+    pub record: ResetRecord,
+    pub progress: ProgressData,
+    // (exercise path, file) pairs whose original content was put back
+    pub restored_files: Vec<(String, String)>,
+}
+
+pub async fn apply_reset(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    scope: ResetScope,
+    targets: &[ExerciseWithPath],
+    restore_sources: bool,
+) -> anyhow::Result<ResetOutcome> {
+    let mut restored_sources = Vec::new();
+    let mut restored_files = Vec::new();
+    if restore_sources {
+        for exercise in targets {
+            let files = profiles.restore_exercise(profile, &exercise.path).await?;
+            if !files.is_empty() {
+                restored_sources.push(exercise.path.clone());
+                restored_files.extend(files.into_iter().map(|file| (exercise.path.clone(), file)));
+            }
+        }
+    }
+
+    let ids: Vec<String> = targets
+        .iter()
+        .flat_map(|exercise| [exercise.metadata.id.clone(), exercise.path.clone()])
+        .collect();
+    let mut record = ResetRecord {
+        reset_at: Utc::now().to_rfc3339(),
+        scope,
+        exercises: Vec::new(),
+        restored_sources,
+    };
+
+    let progress = profiles
+        .progress(profile)
+        .await?
+        .update({
+            let record = record.clone();
+            move |progress| {
+                clear_progress(progress, &ids, record);
+                true
+            }
+        })
+        .await?;
+    if let Some(recorded) = progress.resets.last() {
+        record.exercises = recorded.exercises.clone();
+        let event = LearnerEventKind::ProgressReset { reset: recorded.clone() };
+        event_log::append_event(profiles, profile, None, event).await;
+    }
+
+    info!(
+        "Reset {:?} for profile {}: {} exercises cleared, {} restored",
+        record.scope,
+        profile,
+        record.exercises.len(),
+        record.restored_sources.len()
+    );
+    Ok(ResetOutcome {
+        record,
+        progress,
+        restored_files,
+    })
+}
+
+// Drop the history entries in scope and record the reset
+pub fn clear_progress(progress: &mut ProgressData, ids: &[String], mut record: ResetRecord) {
+    let in_scope = |exercise_id: &str| match &record.scope {
+        ResetScope::All => true,
+        // Also entries of exercises that have since been removed from the chapter
+        ResetScope::Chapter { chapter } => {
+            ids.iter().any(|id| id == exercise_id) || exercise_chapter(exercise_id) == Some(*chapter)
+        }
+        ResetScope::Exercise { exercise_id: requested } => {
+            exercise_id == requested || ids.iter().any(|id| id == exercise_id)
+        }
+    };
+
+    let (cleared, kept) = std::mem::take(&mut progress.exercise_history)
+        .into_iter()
+        .partition::<Vec<_>, _>(|entry| in_scope(&entry.exercise_id));
+    progress.exercise_history = kept;
+    record.exercises = cleared.into_iter().map(|entry| entry.exercise_id).collect();
+
+    progress.exercises_completed = progress
+        .exercise_history
+        .iter()
+        .filter(|entry| entry.completed_at.is_some())
+        .count() as u32;
+    if progress.total_exercises > 0 {
+        progress.overall_progress = progress.exercises_completed as f64 / progress.total_exercises as f64;
+    }
+
+    if record.scope == ResetScope::All {
+        progress.achievements.clear();
+        progress.activity_sessions.clear();
+        progress.session_stats = SessionStats::default();
+    }
+    progress.resets.push(record);
+}
+
+// `rust-tour reset`, run instead of the server
+pub async fn reset_command(
+    profiles: &ProfileRegistry,
+    profile: &str,
+    scope: ResetScope,
+    restore_sources: bool,
+) -> anyhow::Result<()> {
+    let targets = resolve(profiles, &scope).await?;
+    match &scope {
+        ResetScope::Exercise { exercise_id } if targets.is_empty() => {
+            anyhow::bail!("Unknown exercise: {}", exercise_id)
+        }
+        ResetScope::Chapter { chapter } if targets.is_empty() => anyhow::bail!("Unknown chapter: {}", chapter),
+        _ => {}
+    }
+
+    let outcome = apply_reset(profiles, profile, scope, &targets, restore_sources).await?;
+    println!(
+        "🔄 Reset {} exercise(s) in profile '{}'",
+        outcome.record.exercises.len(),
+        profile
+    );
+    for exercise in &outcome.record.exercises {
+        println!("   - {}", exercise);
+    }
+    if restore_sources {
+        println!("   Original sources restored for {} exercise(s)", outcome.record.restored_sources.len());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ResetResponse {
+    reset: ResetRecord,
+    progress: ProgressData,
+}
+
+// API handler
+pub async fn reset_progress(
+    State(state): State<AppState>,
+    ActiveProfile(profile): ActiveProfile,
+    Json(request): Json<ResetRequest>,
+) -> Result<Json<ResetResponse>, (StatusCode, String)> {
+    let targets = resolve(&state.profiles, &request.scope).await.map_err(|e| {
+        error!("Error loading exercises for reset: {}", e);
+        (StatusCode::INTERNAL_SERVER_ERROR, "Failed to load exercises".to_string())
+    })?;
+    match &request.scope {
+        ResetScope::Exercise { exercise_id } if targets.is_empty() => {
+            return Err((StatusCode::NOT_FOUND, format!("Unknown exercise: {}", exercise_id)));
+        }
+        ResetScope::Chapter { chapter } if targets.is_empty() => {
+            return Err((StatusCode::NOT_FOUND, format!("Unknown chapter: {}", chapter)));
+        }
+        _ => {}
+    }
+
+    // A shared editing session would write its text back over the restored file
+    if request.restore_sources {
+        for exercise in &targets {
+            let scoped_path = profiles::scoped_exercise_path(&profile, &exercise.path);
+            if collab::has_open_documents(&state, &scoped_path).await {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("{} is open in a collaborative editing session", exercise.path),
+                ));
+            }
+        }
+    }
+
+    let outcome = apply_reset(&state.profiles, &profile, request.scope, &targets, request.restore_sources)
+        .await
+        .map_err(|e| {
+            error!("Error resetting progress for profile {}: {}", profile, e);
+            (StatusCode::INTERNAL_SERVER_ERROR, format!("Failed to reset: {}", e))
+        })?;
+
+    let _ = state.broadcast_tx.send(ServerMessage::ProgressUpdated {
+        profile: profile.clone(),
+        exercise_id: None,
+        progress: Box::new(outcome.progress.clone()),
+    });
+    if profile == DEFAULT_PROFILE {
+        for (exercise, file) in &outcome.restored_files {
+            let exercise_name = load_exercise_title(&state.profiles.exercise_dir(&profile, exercise))
+                .await
+                .unwrap_or_else(|_| exercise.clone());
+            let _ = state.broadcast_tx.send(ServerMessage::FileUpdated {
+                exercise: exercise_name,
+                file: file.clone(),
+            });
+        }
+    }
+
+    Ok(Json(ResetResponse {
+        reset: outcome.record,
+        progress: outcome.progress,
+    }))
+}
@@ -0,0 +1,307 @@
+// Read-only terminal sharing
+//
+// The owner of a terminal session can create a time-limited viewer token. A
+// mentor connecting to `/ws?view=<token>` receives the session's scrollback and
+// live output, while anything they send (input, resize) is ignored. Tokens end
+// when they expire, when the owner revokes them or when the session exits.
+// Viewers only ever see an opaque share id, never the PTY session id, so they
+// can't address the session over a regular connection.
+
+use axum::extract::ws::{Message, WebSocket};
+use chrono::{DateTime, Utc};
+use futures_util::{sink::SinkExt, stream::StreamExt};
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Arc, time::Duration};
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+use crate::{
+    protocol::{ServerMessage, TerminalEvent},
+    send_message, AppState, ConnectionId, Utf8StreamDecoder,
+};
+
+const DEFAULT_SHARE_TTL_SECONDS: u64 = 30 * 60;
+const MAX_SHARE_TTL_SECONDS: u64 = 8 * 60 * 60;
+const SCROLLBACK_BYTES: usize = 128 * 1024;
+pub const VIEWER_QUEUE_SIZE: usize = 256;
+
+pub type ViewerSender = broadcast::Sender<Arc<[u8]>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TerminalShare {
+    pub token: String,
+    #[serde(rename = "sessionId")]
+    pub session_id: String,
+    // Identifies the session towards viewers in place of `session_id`
+    #[serde(rename = "shareId")]
+    pub share_id: String,
+    #[serde(rename = "expiresAt")]
+    pub expires_at: DateTime<Utc>,
+}
+
+impl TerminalShare {
+    fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
+
+// Most recent PTY output of a session, replayed to viewers when they join
+#[derive(Debug, Default)]
+pub struct Scrollback {
+    bytes: VecDeque<u8>,
+}
+
+impl Scrollback {
+    pub fn push(&mut self, data: &[u8]) {
+        self.bytes.extend(data);
+        let overflow = self.bytes.len().saturating_sub(SCROLLBACK_BYTES);
+        self.bytes.drain(..overflow);
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        // Skip UTF-8 continuation bytes left over from trimming the front
+        self.bytes
+            .iter()
+            .copied()
+            .skip_while(|b| (0x80..0xC0).contains(b))
+            .collect()
+    }
+}
+
+// Create a viewer token for a session owned by `connection_id`
+pub async fn create_share(
+    state: &AppState,
+    connection_id: ConnectionId,
+    session_id: String,
+    ttl_seconds: Option<u64>,
+) -> anyhow::Result<()> {
+    let is_owner = state
+        .terminal_sessions
+        .read()
+        .await
+        .get(&session_id)
+        .is_some_and(|session| session.connection_id == connection_id);
+
+    if !is_owner {
+        send_share_response(
+            state,
+            connection_id,
+            TerminalEvent::Error {
+                session_id: Some(session_id),
+                message: "Only the owner of a terminal session can share it".to_string(),
+            },
+        )
+        .await;
+        return Ok(());
+    }
+
+    let ttl = ttl_seconds
+        .unwrap_or(DEFAULT_SHARE_TTL_SECONDS)
+        .clamp(1, MAX_SHARE_TTL_SECONDS);
+    let share = TerminalShare {
+        token: Uuid::new_v4().simple().to_string(),
+        session_id: session_id.clone(),
+        share_id: Uuid::new_v4().to_string(),
+        expires_at: Utc::now() + chrono::Duration::seconds(ttl as i64),
+    };
+
+    {
+        let mut shares = state.terminal_shares.write().await;
+        shares.retain(|_, share| !share.is_expired());
+        shares.insert(share.token.clone(), share.clone());
+    }
+
+    info!("Terminal session {} shared until {}", session_id, share.expires_at);
+
+    send_share_response(
+        state,
+        connection_id,
+        TerminalEvent::Shared {
+            session_id: share.session_id,
+            viewer_path: format!("/ws?view={}", share.token),
+            token: share.token,
+            expires_at: share.expires_at.to_rfc3339(),
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+// Revoke a viewer token; only the owner of the shared session may do so
+pub async fn revoke_share(state: &AppState, connection_id: ConnectionId, token: String) -> anyhow::Result<()> {
+    let session_id = state
+        .terminal_shares
+        .read()
+        .await
+        .get(&token)
+        .map(|share| share.session_id.clone());
+
+    let Some(session_id) = session_id else {
+        send_share_response(
+            state,
+            connection_id,
+            TerminalEvent::Error {
+                session_id: None,
+                message: "Unknown share token".to_string(),
+            },
+        )
+        .await;
+        return Ok(());
+    };
+
+    let is_owner = state
+        .terminal_sessions
+        .read()
+        .await
+        .get(&session_id)
+        .is_none_or(|session| session.connection_id == connection_id);
+
+    if !is_owner {
+        send_share_response(
+            state,
+            connection_id,
+            TerminalEvent::Error {
+                session_id: Some(session_id),
+                message: "Only the owner of a terminal session can revoke its shares".to_string(),
+            },
+        )
+        .await;
+        return Ok(());
+    }
+
+    state.terminal_shares.write().await.remove(&token);
+    info!("Revoked share of terminal session {}", session_id);
+
+    send_share_response(
+        state,
+        connection_id,
+        TerminalEvent::ShareRevoked { session_id, token },
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn remove_session_shares(state: &AppState, session_id: &str) {
+    state
+        .terminal_shares
+        .write()
+        .await
+        .retain(|_, share| share.session_id != session_id);
+}
+
+pub async fn lookup_share(state: &AppState, token: &str) -> Option<TerminalShare> {
+    state
+        .terminal_shares
+        .read()
+        .await
+        .get(token)
+        .filter(|share| !share.is_expired())
+        .cloned()
+}
+
+async fn send_share_response(state: &AppState, connection_id: ConnectionId, event: TerminalEvent) {
+    send_message(state, connection_id, &ServerMessage::Terminal(event)).await;
+}
+
+fn terminal_event(event: TerminalEvent) -> Message {
+    Message::Text(ServerMessage::Terminal(event).to_json())
+}
+
+// Serve a read-only viewer of a shared terminal session
+pub async fn viewer_connection(socket: WebSocket, state: AppState, share: TerminalShare) {
+    let session_id = share.session_id.clone();
+    let view_id = share.share_id.clone();
+
+    // Subscribe before taking the scrollback snapshot so no output is missed in between
+    let subscription = state.pty_handles.read().await.get(&session_id).map(|handle| {
+        let rx = handle.viewer_tx.subscribe();
+        let scrollback = handle.scrollback.lock().map(|s| s.snapshot()).unwrap_or_default();
+        (rx, scrollback)
+    });
+
+    let (mut sender, mut receiver) = socket.split();
+
+    let Some((mut output_rx, scrollback)) = subscription else {
+        let _ = sender
+            .send(terminal_event(TerminalEvent::ViewEnded {
+                session_id: view_id,
+                reason: "session_closed".to_string(),
+            }))
+            .await;
+        return;
+    };
+
+    info!("Viewer joined shared terminal session {}", session_id);
+
+    let mut decoder = Utf8StreamDecoder::default();
+    let mut initial = vec![terminal_event(TerminalEvent::ViewStarted {
+        session_id: view_id.clone(),
+        read_only: true,
+        expires_at: share.expires_at.to_rfc3339(),
+    })];
+    let scrollback_text = decoder.decode(&scrollback);
+    if !scrollback_text.is_empty() {
+        initial.push(terminal_event(TerminalEvent::Output {
+            session_id: view_id.clone(),
+            data: scrollback_text,
+        }));
+    }
+    for message in initial {
+        if sender.send(message).await.is_err() {
+            return;
+        }
+    }
+
+    let mut validity_check = tokio::time::interval(Duration::from_secs(1));
+
+    let end_reason = loop {
+        tokio::select! {
+            output = output_rx.recv() => match output {
+                Ok(data) => {
+                    let text = decoder.decode(&data);
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let message = terminal_event(TerminalEvent::Output {
+                        session_id: view_id.clone(),
+                        data: text,
+                    });
+                    if sender.send(message).await.is_err() {
+                        break None;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Viewer of terminal {} lagged behind, skipped {} chunks", session_id, skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break Some("session_closed"),
+            },
+            incoming = receiver.next() => match incoming {
+                // Viewers are read-only: input, resize and anything else is ignored
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break None,
+                Some(Ok(_)) => {
+                    debug!("Ignoring message from read-only viewer of {}", session_id);
+                }
+            },
+            _ = validity_check.tick() => {
+                if lookup_share(&state, &share.token).await.is_none() {
+                    break Some("share_revoked");
+                }
+            }
+        }
+    };
+
+    if let Some(reason) = end_reason {
+        let _ = sender
+            .send(terminal_event(TerminalEvent::ViewEnded {
+                session_id: view_id.clone(),
+                reason: reason.to_string(),
+            }))
+            .await;
+        let _ = sender.send(Message::Close(None)).await;
+    }
+
+    info!("Viewer left shared terminal session {}", session_id);
+}
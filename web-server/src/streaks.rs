@@ -0,0 +1,176 @@
+// Learning streaks
+//
+// Streaks are derived from the completion dates in the exercise history rather
+// than counted up as completions arrive, so they can't drift from the history
+// and they are recomputed whenever progress is loaded or changed. Dates are
+// taken in the configured time zone, so an exercise finished at 11pm local time
+// counts for that day even when it is already the next day in UTC.
+//
+// A streak is the number of days with at least one completion. With grace days
+// enabled, up to that many days in a row without a completion don't break it.
+// The current streak stays alive until the grace period after the last active
+// day has passed: with no grace days, finishing something yesterday but not yet
+// today still counts.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use chrono_tz::Tz;
+
+use crate::{ExerciseHistoryEntry, ProgressData};
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreakConfig {
+    pub timezone: Tz,
+    pub grace_days: u32,
+}
+
+impl Default for StreakConfig {
+    fn default() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            grace_days: 0,
+        }
+    }
+}
+
+impl StreakConfig {
+    pub fn today(&self) -> NaiveDate {
+        Utc::now().with_timezone(&self.timezone).date_naive()
+    }
+
+    // Local calendar day of an RFC 3339 timestamp
+    pub fn local_date(&self, timestamp: &str) -> Option<NaiveDate> {
+        DateTime::parse_from_rfc3339(timestamp)
+            .ok()
+            .map(|time| time.with_timezone(&self.timezone).date_naive())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Streaks {
+    pub current: u32,
+    pub longest: u32,
+}
+
+pub fn compute_streaks(history: &[ExerciseHistoryEntry], config: &StreakConfig, today: NaiveDate) -> Streaks {
+    let mut days: Vec<NaiveDate> = history
+        .iter()
+        .filter_map(|entry| entry.completed_at.as_deref())
+        .filter_map(|completed_at| config.local_date(completed_at))
+        .collect();
+    days.sort();
+    days.dedup();
+
+    // Active days can be at most this far apart and still be one streak
+    let max_gap = 1 + config.grace_days as i64;
+
+    let mut longest = 0;
+    let mut run = 0;
+    let mut previous: Option<NaiveDate> = None;
+    for day in &days {
+        run = match previous {
+            Some(previous) if (*day - previous).num_days() <= max_gap => run + 1,
+            _ => 1,
+        };
+        longest = longest.max(run);
+        previous = Some(*day);
+    }
+
+    let current = match previous {
+        Some(last) if (today - last).num_days() <= max_gap => run,
+        _ => 0,
+    };
+
+    Streaks { current, longest }
+}
+
+// Overwrite the stored streaks with values derived from the history; returns
+// whether they changed
+pub fn update_streaks(progress: &mut ProgressData, config: &StreakConfig) -> bool {
+    let streaks = compute_streaks(&progress.exercise_history, config, config.today());
+    let changed = progress.current_streak != streaks.current || progress.longest_streak != streaks.longest;
+    progress.current_streak = streaks.current;
+    progress.longest_streak = streaks.longest;
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn completions(timestamps: &[&str]) -> Vec<ExerciseHistoryEntry> {
+        timestamps
+            .iter()
+            .enumerate()
+            .map(|(index, completed_at)| {
+                serde_json::from_value(serde_json::json!({
+                    "exercise_id": format!("exercise-{}", index),
+                    "status": "completed",
+                    "completed_at": completed_at,
+                }))
+                .expect("valid history entry")
+            })
+            .collect()
+    }
+
+    fn date(date: &str) -> NaiveDate {
+        date.parse().expect("valid date")
+    }
+
+    #[test]
+    fn days_are_taken_in_the_configured_time_zone() {
+        // 23:30 on the 17th and 10:00 on the 18th in New York, both on the 18th in UTC
+        let history = completions(&["2026-10-18T03:30:00Z", "2026-10-18T14:00:00Z"]);
+
+        let new_york = StreakConfig {
+            timezone: chrono_tz::America::New_York,
+            grace_days: 0,
+        };
+        let streaks = compute_streaks(&history, &new_york, date("2026-10-18"));
+        assert_eq!(streaks, Streaks { current: 2, longest: 2 });
+
+        let streaks = compute_streaks(&history, &StreakConfig::default(), date("2026-10-18"));
+        assert_eq!(streaks, Streaks { current: 1, longest: 1 });
+    }
+
+    #[test]
+    fn yesterday_keeps_the_current_streak_alive() {
+        let history = completions(&["2026-10-16T12:00:00Z", "2026-10-17T12:00:00Z"]);
+        let config = StreakConfig::default();
+        assert_eq!(compute_streaks(&history, &config, date("2026-10-18")).current, 2);
+        assert_eq!(compute_streaks(&history, &config, date("2026-10-19")).current, 0);
+    }
+
+    #[test]
+    fn gaps_within_the_grace_days_continue_the_streak() {
+        let history = completions(&["2026-10-10T12:00:00Z", "2026-10-12T12:00:00Z", "2026-10-13T12:00:00Z"]);
+        let config = StreakConfig {
+            timezone: Tz::UTC,
+            grace_days: 1,
+        };
+        assert_eq!(
+            compute_streaks(&history, &config, date("2026-10-15")),
+            Streaks { current: 3, longest: 3 }
+        );
+        // Without grace days the missed day splits it
+        assert_eq!(
+            compute_streaks(&history, &StreakConfig::default(), date("2026-10-13")),
+            Streaks { current: 2, longest: 2 }
+        );
+    }
+
+    #[test]
+    fn gaps_past_the_grace_days_end_the_streak() {
+        let history = completions(&["2026-10-10T12:00:00Z", "2026-10-11T12:00:00Z", "2026-10-14T12:00:00Z"]);
+        let config = StreakConfig {
+            timezone: Tz::UTC,
+            grace_days: 1,
+        };
+        assert_eq!(
+            compute_streaks(&history, &config, date("2026-10-14")),
+            Streaks { current: 1, longest: 2 }
+        );
+        // The current streak lapses once the grace period after the last active day is over
+        assert_eq!(compute_streaks(&history, &config, date("2026-10-16")).current, 1);
+        assert_eq!(compute_streaks(&history, &config, date("2026-10-17")).current, 0);
+    }
+}
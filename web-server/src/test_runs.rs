@@ -0,0 +1,217 @@
+// Test runs
+//
+// Every `cargo test` started from the browser (REST or WebSocket) is recorded
+// in the exercise's history entry: when it ran, whether the exercise compiled,
+// how many tests passed and failed and which ones failed. The counts come from
+// libtest's `test result:` summary lines, summed over all test binaries (unit
+// tests, `tests/` and doc tests). Only the last `RECENT_TEST_RUNS` runs are
+// kept in full, so progress doesn't grow with every run; the entry counts all
+// runs and the failed ones. When the exercise is completed, the entry also
+// gets the number of runs it took and how many of them failed.
+//
+// A run that passes every test completes the exercise (`completed_by:
+// "tests"`), with the measured time, and broadcasts `exercise_completed`. The
+// test files must match the original exercise, so editing the tests doesn't
+// count.
+
+use chrono::Utc;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tracing::{info, warn};
+
+use crate::{
+    event_log::{self, LearnerEventKind},
+    exercise_completed_message, find_exercise, history_entry_mut, log_learner_event, record_completion, AppState,
+    CargoResult, CompletionSource, ExerciseHistoryEntry, ProgressData,
+};
+
+// Runs kept per exercise
+pub const RECENT_TEST_RUNS: usize = 20;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+pub struct TestRun {
+    pub ran_at: String,
+    pub compiled: bool,
+    pub passed: u32,
+    pub failed: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failing_tests: Vec<String>,
+    #[serde(default)]
+    pub duration_ms: u64,
+}
+
+impl TestRun {
+    // Compiled and nothing failed
+    pub fn succeeded(&self) -> bool {
+        self.compiled && self.failed == 0
+    }
+
+    // Every test passed, and there was at least one
+    pub fn proves_completion(&self) -> bool {
+        self.succeeded() && self.passed > 0
+    }
+
+    pub fn from_output(result: &CargoResult, duration: Duration) -> Self {
+        let output = strip_ansi(&result.output);
+        let mut passed = 0;
+        let mut failed = 0;
+        let mut summaries = 0;
+        let mut failing_tests = Vec::new();
+
+        for line in output.lines().map(str::trim) {
+            if let Some(summary) = line.strip_prefix("test result: ") {
+                summaries += 1;
+                passed += summary_count(summary, "passed");
+                failed += summary_count(summary, "failed");
+            } else if let Some(name) = line.strip_prefix("test ").and_then(|rest| rest.strip_suffix(" ... FAILED")) {
+                failing_tests.push(name.to_string());
+            }
+        }
+
+        Self {
+            ran_at: Utc::now().to_rfc3339(),
+            // Without any summary the test binaries never ran
+            compiled: summaries > 0 || result.success,
+            passed,
+            failed,
+            failing_tests,
+            duration_ms: duration.as_millis() as u64,
+        }
+    }
+}
+
+// `n` from "...; n <label>; ..." in a libtest summary
+fn summary_count(summary: &str, label: &str) -> u32 {
+    summary
+        .split(['.', ';'])
+        .filter_map(|part| part.trim().strip_suffix(label))
+        .filter_map(|count| count.trim().parse::<u32>().ok())
+        .sum()
+}
+
+// Cargo is asked for colored output; drop the escape sequences before parsing
+pub fn strip_ansi(text: &str) -> String {
+    let mut plain = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\u{1b}' {
+            // CSI sequence: ESC [ parameters final-byte
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+        } else {
+            plain.push(c);
+        }
+    }
+    plain
+}
+
+// Record a test run for the exercise, found by metadata id or path, and
+// complete the exercise when it passed
+pub async fn record_test_run(
+    state: &AppState,
+    profile: &str,
+    exercise: &str,
+    result: &CargoResult,
+    duration: Duration,
+) {
+    // History entries are keyed by the metadata id, like views and completions
+    let (exercise_id, exercise_path) = match find_exercise(state, exercise).await {
+        Some(found) => (found.metadata.id, Some(found.path)),
+        None => (exercise.to_string(), None),
+    };
+    let run = TestRun::from_output(result, duration);
+    info!(
+        "Test run for {}: compiled={}, {} passed, {} failed",
+        exercise_id, run.compiled, run.passed, run.failed
+    );
+
+    let completes = match &exercise_path {
+        Some(exercise_path) if run.proves_completion() => passes_integrity_checks(state, profile, exercise_path).await,
+        _ => false,
+    };
+    // Credit the time worked so far to the completion
+    let session_id = match completes {
+        true => state.activity.flush(&state.profiles, profile).await,
+        false => None,
+    };
+
+    let event = LearnerEventKind::TestResult {
+        exercise_id: exercise_id.clone(),
+        run: run.clone(),
+    };
+    let completed = Arc::new(AtomicBool::new(false));
+    let result = match state.profiles.progress(profile).await {
+        Ok(progress) => {
+            let (exercise_id, completed) = (exercise_id.clone(), completed.clone());
+            progress
+                .update(move |progress| {
+                    add_test_run(progress, exercise_id.clone(), run);
+                    if completes {
+                        let newly = record_completion(progress, &exercise_id, None, session_id, CompletionSource::Tests);
+                        completed.store(newly, Ordering::Relaxed);
+                    }
+                    true
+                })
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    let progress = match result {
+        Ok(progress) => progress,
+        Err(e) => {
+            warn!("Failed to record test run for profile {}: {}", profile, e);
+            return;
+        }
+    };
+    log_learner_event(state, profile, event).await;
+    if completed.load(Ordering::Relaxed) {
+        if let Some(message) = exercise_completed_message(profile, &progress, &exercise_id) {
+            let _ = state.broadcast_tx.send(message);
+        }
+        if let Some(event) = event_log::completion_event(&progress, &exercise_id) {
+            log_learner_event(state, profile, event).await;
+        }
+    }
+}
+
+// The tests the run passed are the exercise's own
+async fn passes_integrity_checks(state: &AppState, profile: &str, exercise_path: &str) -> bool {
+    match state.profiles.modified_tests(profile, exercise_path).await {
+        Ok(modified) if modified.is_empty() => true,
+        Ok(modified) => {
+            warn!("Not completing {}: its tests were changed ({})", exercise_path, modified.join(", "));
+            false
+        }
+        Err(e) => {
+            warn!("Not completing {}: failed to compare its tests: {}", exercise_path, e);
+            false
+        }
+    }
+}
+
+pub fn add_test_run(progress: &mut ProgressData, exercise_id: String, run: TestRun) -> bool {
+    let entry = history_entry_mut(progress, &exercise_id, "in_progress");
+    entry.test_run_count += 1;
+    entry.failed_test_runs += !run.succeeded() as u32;
+    entry.test_runs.push(run);
+    let overflow = entry.test_runs.len().saturating_sub(RECENT_TEST_RUNS);
+    entry.test_runs.drain(..overflow);
+    true
+}
+
+// Attempts and failed attempts it took to complete the exercise
+pub fn attempt_counts(entry: &ExerciseHistoryEntry) -> (u32, u32) {
+    (entry.test_run_count, entry.failed_test_runs)
+}
@@ -0,0 +1,522 @@
+// Collaborative editing over the WebSocket (see web-server/src/collab.rs).
+// Edits are exchanged as operational-transformation operations: lists of
+// {retain: n}, {insert: "text"} and {delete: n} steps measured in UTF-16 code
+// units, which is also how Monaco counts offsets.
+
+export class TextOperation {
+  constructor() {
+    this.ops = [];
+  }
+
+  static fromJSON(ops) {
+    const operation = new TextOperation();
+    ops.forEach(op => {
+      if (op.retain !== undefined) operation.retain(op.retain);
+      else if (op.insert !== undefined) operation.insert(op.insert);
+      else if (op.delete !== undefined) operation.delete(op.delete);
+    });
+    return operation;
+  }
+
+  toJSON() {
+    return this.ops;
+  }
+
+  retain(n) {
+    if (n <= 0) return this;
+    const last = this.ops[this.ops.length - 1];
+    if (last && last.retain !== undefined) {
+      last.retain += n;
+    } else {
+      this.ops.push({ retain: n });
+    }
+    return this;
+  }
+
+  // Inserts go before a delete at the same position, as on the server
+  insert(text) {
+    if (!text) return this;
+    const ops = this.ops;
+    const last = ops[ops.length - 1];
+    const beforeLast = ops[ops.length - 2];
+    if (last && last.insert !== undefined) {
+      last.insert += text;
+    } else if (last && last.delete !== undefined) {
+      if (beforeLast && beforeLast.insert !== undefined) {
+        beforeLast.insert += text;
+      } else {
+        ops.splice(ops.length - 1, 0, { insert: text });
+      }
+    } else {
+      ops.push({ insert: text });
+    }
+    return this;
+  }
+
+  delete(n) {
+    if (n <= 0) return this;
+    const last = this.ops[this.ops.length - 1];
+    if (last && last.delete !== undefined) {
+      last.delete += n;
+    } else {
+      this.ops.push({ delete: n });
+    }
+    return this;
+  }
+
+  isNoop() {
+    return this.ops.every(op => op.retain !== undefined);
+  }
+
+  // Apply one operation after another, as a single operation
+  compose(other) {
+    const result = new TextOperation();
+    const ops1 = this.ops.map(op => ({ ...op }));
+    const ops2 = other.ops.map(op => ({ ...op }));
+    let i1 = 0;
+    let i2 = 0;
+    let op1 = ops1[i1++];
+    let op2 = ops2[i2++];
+
+    while (op1 || op2) {
+      if (op1 && op1.delete !== undefined) {
+        result.delete(op1.delete);
+        op1 = ops1[i1++];
+        continue;
+      }
+      if (op2 && op2.insert !== undefined) {
+        result.insert(op2.insert);
+        op2 = ops2[i2++];
+        continue;
+      }
+      if (!op1 || !op2) {
+        throw new Error('Cannot compose operations of different lengths');
+      }
+
+      if (op1.retain !== undefined && op2.retain !== undefined) {
+        const n = Math.min(op1.retain, op2.retain);
+        result.retain(n);
+        [op1, i1] = shorten(op1, n, 'retain', ops1, i1);
+        [op2, i2] = shorten(op2, n, 'retain', ops2, i2);
+      } else if (op1.insert !== undefined && op2.delete !== undefined) {
+        const n = Math.min(op1.insert.length, op2.delete);
+        if (op1.insert.length > n) {
+          op1 = { insert: op1.insert.slice(n) };
+        } else {
+          op1 = ops1[i1++];
+        }
+        [op2, i2] = shorten(op2, n, 'delete', ops2, i2);
+      } else if (op1.insert !== undefined && op2.retain !== undefined) {
+        const n = Math.min(op1.insert.length, op2.retain);
+        result.insert(op1.insert.slice(0, n));
+        if (op1.insert.length > n) {
+          op1 = { insert: op1.insert.slice(n) };
+        } else {
+          op1 = ops1[i1++];
+        }
+        [op2, i2] = shorten(op2, n, 'retain', ops2, i2);
+      } else if (op1.retain !== undefined && op2.delete !== undefined) {
+        const n = Math.min(op1.retain, op2.delete);
+        result.delete(n);
+        [op1, i1] = shorten(op1, n, 'retain', ops1, i1);
+        [op2, i2] = shorten(op2, n, 'delete', ops2, i2);
+      }
+    }
+    return result;
+  }
+
+  // For concurrent a and b, returns [a', b'] with apply(apply(s, a), b') == apply(apply(s, b), a')
+  static transform(a, b) {
+    const aPrime = new TextOperation();
+    const bPrime = new TextOperation();
+    const ops1 = a.ops.map(op => ({ ...op }));
+    const ops2 = b.ops.map(op => ({ ...op }));
+    let i1 = 0;
+    let i2 = 0;
+    let op1 = ops1[i1++];
+    let op2 = ops2[i2++];
+
+    while (op1 || op2) {
+      if (op1 && op1.insert !== undefined) {
+        aPrime.insert(op1.insert);
+        bPrime.retain(op1.insert.length);
+        op1 = ops1[i1++];
+        continue;
+      }
+      if (op2 && op2.insert !== undefined) {
+        aPrime.retain(op2.insert.length);
+        bPrime.insert(op2.insert);
+        op2 = ops2[i2++];
+        continue;
+      }
+      if (!op1 || !op2) {
+        throw new Error('Cannot transform operations of different lengths');
+      }
+
+      const kind1 = op1.retain !== undefined ? 'retain' : 'delete';
+      const kind2 = op2.retain !== undefined ? 'retain' : 'delete';
+      const n = Math.min(op1[kind1], op2[kind2]);
+      if (kind1 === 'retain' && kind2 === 'retain') {
+        aPrime.retain(n);
+        bPrime.retain(n);
+      } else if (kind1 === 'delete' && kind2 === 'retain') {
+        aPrime.delete(n);
+      } else if (kind1 === 'retain' && kind2 === 'delete') {
+        bPrime.delete(n);
+      }
+      [op1, i1] = shorten(op1, n, kind1, ops1, i1);
+      [op2, i2] = shorten(op2, n, kind2, ops2, i2);
+    }
+    return [aPrime, bPrime];
+  }
+
+  transformIndex(index) {
+    let remaining = index;
+    let newIndex = index;
+    for (const op of this.ops) {
+      if (remaining < 0) break;
+      if (op.retain !== undefined) {
+        remaining -= op.retain;
+      } else if (op.insert !== undefined) {
+        newIndex += op.insert.length;
+      } else {
+        newIndex -= Math.min(Math.max(remaining, 0), op.delete);
+        remaining -= op.delete;
+      }
+    }
+    return newIndex;
+  }
+}
+
+function shorten(op, n, kind, ops, index) {
+  if (op[kind] > n) {
+    return [{ [kind]: op[kind] - n }, index];
+  }
+  return [ops[index], index + 1];
+}
+
+// Monaco change events describe edits against the text before the change
+function operationFromChanges(changes, lengthBefore) {
+  const operation = new TextOperation();
+  let index = 0;
+  [...changes]
+    .sort((a, b) => a.rangeOffset - b.rangeOffset)
+    .forEach(change => {
+      operation.retain(change.rangeOffset - index);
+      operation.delete(change.rangeLength);
+      operation.insert(change.text);
+      index = change.rangeOffset + change.rangeLength;
+    });
+  operation.retain(lengthBefore - index);
+  return operation;
+}
+
+class CollaborativeDocument {
+  constructor(session, documentId, path, model) {
+    this.session = session;
+    this.documentId = documentId;
+    this.path = path;
+    this.model = model;
+    this.revision = 0;
+    this.outstanding = null; // sent, waiting for the server's ack
+    this.buffer = null;      // local edits made while waiting
+    this.applyingRemote = false;
+    this.participants = new Map();
+    this.decorations = [];
+
+    this.changeListener = model.onDidChangeContent(event => {
+      if (this.applyingRemote) return;
+      const lengthAfter = model.getValueLength();
+      const delta = event.changes.reduce((sum, c) => sum + c.text.length - c.rangeLength, 0);
+      this.applyClient(operationFromChanges(event.changes, lengthAfter - delta));
+    });
+  }
+
+  joined(data) {
+    this.revision = data.revision;
+    if (this.model.getValue() !== data.content) {
+      this.applyingRemote = true;
+      this.model.setValue(data.content);
+      this.applyingRemote = false;
+    }
+    data.participants
+      .filter(p => p.connection_id !== this.session.websocket.connectionId)
+      .forEach(p => this.participants.set(p.connection_id, p));
+    this.renderRemoteSelections();
+  }
+
+  applyClient(operation) {
+    if (operation.isNoop()) return;
+    if (!this.outstanding) {
+      this.outstanding = operation;
+      this.sendOperation(operation);
+    } else if (!this.buffer) {
+      this.buffer = operation;
+    } else {
+      this.buffer = this.buffer.compose(operation);
+    }
+  }
+
+  applyServer(data) {
+    let operation = TextOperation.fromJSON(data.operation);
+    if (this.outstanding) {
+      [this.outstanding, operation] = TextOperation.transform(this.outstanding, operation);
+    }
+    if (this.buffer) {
+      [this.buffer, operation] = TextOperation.transform(this.buffer, operation);
+    }
+    this.revision = data.revision;
+    this.applyToModel(operation);
+
+    this.participants.forEach(participant => {
+      participant.ranges = participant.ranges.map(range => ({
+        anchor: operation.transformIndex(range.anchor),
+        head: operation.transformIndex(range.head)
+      }));
+    });
+    this.renderRemoteSelections();
+  }
+
+  serverAck(data) {
+    this.revision = data.revision;
+    this.outstanding = this.buffer;
+    this.buffer = null;
+    if (this.outstanding) {
+      this.sendOperation(this.outstanding);
+    } else if (this.pendingSelection) {
+      this.sendSelection(this.pendingSelection);
+    }
+  }
+
+  sendOperation(operation) {
+    this.session.send({
+      action: 'operation',
+      document_id: this.documentId,
+      revision: this.revision,
+      operation: operation.toJSON()
+    });
+  }
+
+  // Selections are only meaningful once the server has every local edit
+  sendSelection(ranges) {
+    if (this.outstanding) {
+      this.pendingSelection = ranges;
+      return;
+    }
+    this.pendingSelection = null;
+    this.session.send({
+      action: 'selection',
+      document_id: this.documentId,
+      revision: this.revision,
+      ranges
+    });
+  }
+
+  applyToModel(operation) {
+    const edits = [];
+    let index = 0;
+    operation.ops.forEach(op => {
+      if (op.retain !== undefined) {
+        index += op.retain;
+      } else if (op.insert !== undefined) {
+        edits.push({ start: index, end: index, text: op.insert });
+      } else {
+        const last = edits[edits.length - 1];
+        if (last && last.end === index) {
+          last.end += op.delete;
+        } else {
+          edits.push({ start: index, end: index + op.delete, text: '' });
+        }
+        index += op.delete;
+      }
+    });
+
+    const monacoEdits = edits.map(edit => {
+      const start = this.model.getPositionAt(edit.start);
+      const end = this.model.getPositionAt(edit.end);
+      return {
+        range: {
+          startLineNumber: start.lineNumber,
+          startColumn: start.column,
+          endLineNumber: end.lineNumber,
+          endColumn: end.column
+        },
+        text: edit.text,
+        forceMoveMarkers: true
+      };
+    });
+
+    this.applyingRemote = true;
+    try {
+      this.model.applyEdits(monacoEdits);
+    } finally {
+      this.applyingRemote = false;
+    }
+  }
+
+  updateParticipantSelection(data) {
+    let participant = this.participants.get(data.connection_id);
+    if (!participant) {
+      participant = { connection_id: data.connection_id, client: null, ranges: [] };
+      this.participants.set(data.connection_id, participant);
+    }
+    // Bring the server's view of the selection up to date with unacknowledged local edits
+    participant.ranges = data.ranges.map(range => {
+      let { anchor, head } = range;
+      [this.outstanding, this.buffer].forEach(op => {
+        if (op) {
+          anchor = op.transformIndex(anchor);
+          head = op.transformIndex(head);
+        }
+      });
+      return { anchor, head };
+    });
+    this.renderRemoteSelections();
+  }
+
+  renderRemoteSelections() {
+    const decorations = [];
+    this.participants.forEach(participant => {
+      const label = participant.client || participant.connection_id.slice(0, 8);
+      participant.ranges.forEach(range => {
+        const from = this.model.getPositionAt(Math.min(range.anchor, range.head));
+        const to = this.model.getPositionAt(Math.max(range.anchor, range.head));
+        const head = this.model.getPositionAt(range.head);
+        if (range.anchor !== range.head) {
+          decorations.push({
+            range: { startLineNumber: from.lineNumber, startColumn: from.column, endLineNumber: to.lineNumber, endColumn: to.column },
+            options: { className: 'collab-remote-selection', hoverMessage: { value: label } }
+          });
+        }
+        decorations.push({
+          range: { startLineNumber: head.lineNumber, startColumn: head.column, endLineNumber: head.lineNumber, endColumn: head.column },
+          options: { beforeContentClassName: 'collab-remote-cursor', hoverMessage: { value: label } }
+        });
+      });
+    });
+    this.decorations = this.model.deltaDecorations(this.decorations, decorations);
+  }
+
+  dispose() {
+    this.changeListener.dispose();
+    if (!this.model.isDisposed()) {
+      this.decorations = this.model.deltaDecorations(this.decorations, []);
+    }
+  }
+}
+
+// Joins every file of the current exercise and keeps Monaco models in sync
+export class CollaborationSession {
+  constructor(websocket, ui) {
+    this.websocket = websocket;
+    this.ui = ui;
+    this.documents = new Map(); // document id -> CollaborativeDocument
+    this.pendingJoins = new Map(); // file path -> model
+    this.exercisePath = null;
+    this.active = false;
+
+    this.websocket.addMessageHandler('collab', data => this.handleMessage(data));
+    document.addEventListener('websocket-status', event => {
+      // Server-side documents are dropped with the connection
+      if (!event.detail.connected && this.active) {
+        this.leaveAll(false);
+      }
+    });
+  }
+
+  send(message) {
+    return this.websocket.send({ type: 'collab', ...message });
+  }
+
+  joinExercise(exercise) {
+    if (!exercise) return;
+    this.active = true;
+    this.exercisePath = exercise.path;
+    this.ui.fileModels.forEach((model, path) => {
+      this.pendingJoins.set(path, model);
+      this.send({ action: 'join', exercise_id: exercise.path, file: path });
+    });
+
+    if (!this.selectionListener && this.ui.editor) {
+      this.selectionListener = this.ui.editor.onDidChangeCursorSelection(event => {
+        const model = this.ui.editor.getModel();
+        const doc = [...this.documents.values()].find(d => d.model === model);
+        if (!doc) return;
+        const selections = [event.selection, ...event.secondarySelections];
+        doc.sendSelection(selections.map(selection => ({
+          anchor: model.getOffsetAt(selection.getSelectionStart()),
+          head: model.getOffsetAt(selection.getPosition())
+        })));
+      });
+    }
+    this.updateButton();
+  }
+
+  leaveAll(notifyServer = true) {
+    this.documents.forEach((doc, documentId) => {
+      if (notifyServer) {
+        this.send({ action: 'leave', document_id: documentId });
+      }
+      doc.dispose();
+    });
+    this.documents.clear();
+    this.pendingJoins.clear();
+    this.active = false;
+    this.updateButton();
+  }
+
+  handleMessage(data) {
+    if (data.action === 'joined') {
+      const path = data.document_id.slice(this.exercisePath.length + 1);
+      const model = this.pendingJoins.get(path);
+      if (!model || model.isDisposed()) return;
+      this.pendingJoins.delete(path);
+      const doc = new CollaborativeDocument(this, data.document_id, path, model);
+      this.documents.set(data.document_id, doc);
+      doc.joined(data);
+      return;
+    }
+
+    if (data.action === 'error') {
+      console.warn('Collaboration error:', data.message);
+      return;
+    }
+
+    const doc = this.documents.get(data.document_id);
+    if (!doc) return;
+
+    switch (data.action) {
+      case 'ack':
+        doc.serverAck(data);
+        break;
+      case 'operation':
+        doc.applyServer(data);
+        break;
+      case 'selection':
+        doc.updateParticipantSelection(data);
+        break;
+      case 'participant_joined':
+        doc.participants.set(data.participant.connection_id, data.participant);
+        doc.renderRemoteSelections();
+        break;
+      case 'participant_left':
+        doc.participants.delete(data.connection_id);
+        doc.renderRemoteSelections();
+        break;
+      case 'saved':
+        // The server wrote the merged document; nothing left for autosave to do
+        if (!doc.outstanding && !doc.buffer && this.ui.dirtyFiles) {
+          this.ui.dirtyFiles.delete(doc.path);
+          this.ui.updateFileModifiedState(doc.path);
+        }
+        break;
+    }
+  }
+
+  updateButton() {
+    const button = document.getElementById('pair-btn');
+    if (button) {
+      button.classList.toggle('active', this.active);
+    }
+  }
+}
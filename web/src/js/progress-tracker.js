@@ -1,12 +1,36 @@
 export class ProgressTracker {
   constructor() {
     this.progress = null;
+    this.preferences = null;
     this.sessionStartTime = Date.now();
     this.currentExerciseStartTime = null;
+
+    // The server evaluates achievements and announces each one it records
+    document.addEventListener('achievement-unlocked', (event) => {
+      this.unlockAchievement(event.detail.achievement);
+    });
+
+    // Sent for every change, from this tab, another one or the server itself:
+    // show the new numbers right away, then pick up the recorded progress
+    document.addEventListener('progress-changed', (event) => {
+      if (this.progress) {
+        Object.assign(this.progress, event.detail.totals);
+        this.updateProgressDisplay();
+      }
+      if (event.detail.changes.length > 0) {
+        this.loadProgress();
+      }
+    });
+
+    // Changed in this tab or another one of the same profile
+    document.addEventListener('preferences-changed', (event) => {
+      this.preferences = event.detail.preferences;
+    });
   }
 
   async init(exercises = null) {
     await this.loadProgress();
+    await this.loadPreferences();
     
     // Set total exercises based on actual loaded exercises
     if (exercises && Array.isArray(exercises)) {
@@ -39,6 +63,33 @@ export class ProgressTracker {
     }
   }
 
+  // Stored with the profile; the server also applies the hint preference itself
+  async loadPreferences() {
+    try {
+      const response = await fetch('/api/preferences');
+      if (response.ok) {
+        this.preferences = await response.json();
+      }
+    } catch (error) {
+      console.error('Failed to load preferences:', error);
+    }
+  }
+
+  async updatePreferences(changes) {
+    const response = await fetch('/api/preferences', {
+      method: 'PUT',
+      headers: {
+        'Content-Type': 'application/json'
+      },
+      body: JSON.stringify(changes)
+    });
+    if (!response.ok) {
+      throw new Error(await response.text());
+    }
+    this.preferences = await response.json();
+    return this.preferences;
+  }
+
   getDefaultProgress() {
     return {
       user_id: 'default',
@@ -62,7 +113,8 @@ export class ProgressTracker {
     };
   }
 
-  async trackExerciseViewed(exerciseId) {
+  // connectionId ties the view to this tab's WebSocket for the presence dashboard
+  async trackExerciseViewed(exerciseId, connectionId = null) {
     // Ensure progress is loaded
     if (!this.progress) {
       await this.loadProgress();
@@ -77,7 +129,8 @@ export class ProgressTracker {
           'Content-Type': 'application/json'
         },
         body: JSON.stringify({
-          exercise_id: exerciseId
+          exercise_id: exerciseId,
+          connection_id: connectionId
         })
       });
       
@@ -188,9 +241,6 @@ export class ProgressTracker {
         console.error('Failed to update progress on backend');
       }
 
-      // Check for achievements
-      this.checkForAchievements();
-      
       // Update UI
       this.updateProgressDisplay();
       
@@ -199,61 +249,12 @@ export class ProgressTracker {
     }
   }
 
-  checkForAchievements() {
-    if (!this.progress) return;
-    
-    // Ensure achievements array exists
-    if (!this.progress.achievements) {
-      this.progress.achievements = [];
-    }
-    
-    const achievements = this.progress.achievements;
-    const completed = this.progress.exercises_completed;
-    
-    // First exercise achievement
-    if (completed === 1 && !achievements.find(a => a.id === 'first_exercise')) {
-      this.unlockAchievement({
-        id: 'first_exercise',
-        title: 'Hello, Rust! 🦀',
-        description: 'Completed your first exercise',
-        icon: '🎉',
-        points: 10,
-        unlocked_at: new Date().toISOString()
-      });
-    }
-
-    // Chapter completion achievements
-    if (!this.progress.chapters) {
-      this.progress.chapters = {};
-    }
-    const chaptersCompleted = Object.keys(this.progress.chapters).length;
-    if (chaptersCompleted === 1 && !achievements.find(a => a.id === 'first_chapter')) {
-      this.unlockAchievement({
-        id: 'first_chapter',
-        title: 'Chapter Master',
-        description: 'Completed your first chapter',
-        icon: '<i class="fas fa-graduation-cap"></i>',
-        points: 25,
-        unlocked_at: new Date().toISOString()
-      });
-    }
-
-    // Session achievements
-    if (this.progress.session_stats.exercises_completed === 3 && 
-        !achievements.find(a => a.id === 'session_streak')) {
-      this.unlockAchievement({
-        id: 'session_streak',
-        title: 'Momentum Builder',
-        description: 'Completed 3 exercises in one session',
-        icon: '🔥',
-        points: 15,
-        unlocked_at: new Date().toISOString()
-      });
-    }
-  }
-
   unlockAchievement(achievement) {
-    this.progress.achievements.push(achievement);
+    if (this.progress) {
+      this.progress.achievements = this.progress.achievements || [];
+      if (this.progress.achievements.some(a => a.id === achievement.id)) return;
+      this.progress.achievements.push(achievement);
+    }
     
     // Show achievement notification
     this.showAchievementNotification(achievement);
@@ -384,7 +385,7 @@ export class ProgressTracker {
     if (!this.progress) return [];
     
     return this.progress.achievements
-      .sort((a, b) => new Date(b.unlocked_at) - new Date(a.unlocked_at))
+      .sort((a, b) => new Date(b.earned_at) - new Date(a.earned_at))
       .slice(0, limit);
   }
 
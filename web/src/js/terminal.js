@@ -127,7 +127,8 @@ export class TerminalManager {
         this.ws.send({
           type: 'terminal',
           action: 'check',
-          sessionId: this.sessionId
+          sessionId: this.sessionId,
+          binary: true
         });
       });
       return;
@@ -148,7 +149,9 @@ export class TerminalManager {
         action: 'create',
         sessionId: this.sessionId,
         cols: cols,
-        rows: rows
+        rows: rows,
+        // Receive raw PTY bytes as binary frames; xterm decodes UTF-8 across writes
+        binary: true
       });
     });
   }
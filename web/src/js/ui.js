@@ -160,6 +160,9 @@ export class UI {
               <button class="btn btn-secondary" id="terminal-btn" title="Terminal (Ctrl+~)">
                 <i class="fas fa-terminal"></i> Terminal
               </button>
+              <button class="btn btn-secondary" id="pair-btn" title="Pair programming: edit this exercise together with other open browsers">
+                <i class="fas fa-user-friends"></i> Pair
+              </button>
             </div>
           </div>
           
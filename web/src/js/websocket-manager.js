@@ -7,6 +7,11 @@ export class WebSocketManager {
     this.messageHandlers = [];
     this.typedMessageHandlers = new Map();
     this.isConnecting = false;
+    this.protocolVersion = 1;
+    this.serverInfo = null;
+    this.connectionId = null;
+    this.protocolRejected = false;
+    this.eventSource = null;
     this.debug = localStorage.getItem('DEBUG_WEBSOCKET') === 'true' || window.location.search.includes('debug=true');
   }
 
@@ -44,6 +49,7 @@ export class WebSocketManager {
         console.log(`Connecting to WebSocket: ${wsUrl}`);
       }
       this.ws = new WebSocket(wsUrl);
+      this.ws.binaryType = 'arraybuffer';
       
       this.ws.onopen = () => {
         console.log('✅ WebSocket connected');
@@ -51,13 +57,18 @@ export class WebSocketManager {
         this.reconnectDelay = 1000;
         this.isConnecting = false;
         
+        this.sendHello();
         this.notifyConnectionStatus(true);
       };
 
       this.ws.onmessage = (event) => {
         try {
-          const data = JSON.parse(event.data);
-          this.handleMessage(data);
+          const data = event.data instanceof ArrayBuffer
+            ? this.decodeBinaryFrame(event.data)
+            : JSON.parse(event.data);
+          if (data) {
+            this.handleMessage(data);
+          }
         } catch (error) {
           console.error('Failed to parse WebSocket message:', error);
         }
@@ -69,7 +80,7 @@ export class WebSocketManager {
         this.notifyConnectionStatus(false);
         
         // Attempt to reconnect unless it was a clean close
-        if (event.code !== 1000 && this.reconnectAttempts < this.maxReconnectAttempts) {
+        if (event.code !== 1000 && !this.protocolRejected && this.reconnectAttempts < this.maxReconnectAttempts) {
           this.scheduleReconnect();
         }
       };
@@ -96,8 +107,8 @@ export class WebSocketManager {
       if (this.reconnectAttempts <= this.maxReconnectAttempts) {
         this.connect();
       } else {
-        console.warn('Max reconnection attempts reached. WebSocket functionality disabled.');
-        this.showConnectionError();
+        console.warn('Max reconnection attempts reached. Falling back to the event stream.');
+        this.startEventStream();
       }
     }, delay);
   }
@@ -126,6 +137,35 @@ export class WebSocketManager {
 
     // Handle built-in message types
     switch (data.type) {
+      case 'hello':
+        this.handleHello(data);
+        break;
+      case 'hello_ack':
+        if (this.debug) {
+          console.log(`WebSocket protocol v${data.protocol_version} accepted`);
+        }
+        break;
+      case 'error':
+        this.handleProtocolError(data);
+        break;
+      case 'progress_updated':
+        document.dispatchEvent(new CustomEvent('progress-updated', { detail: data }));
+        break;
+      case 'progress_changed':
+        document.dispatchEvent(new CustomEvent('progress-changed', { detail: data }));
+        break;
+      case 'preferences_changed':
+        document.dispatchEvent(new CustomEvent('preferences-changed', { detail: data }));
+        break;
+      case 'exercise_completed':
+        document.dispatchEvent(new CustomEvent('exercise-completed', { detail: data }));
+        break;
+      case 'achievement_unlocked':
+        document.dispatchEvent(new CustomEvent('achievement-unlocked', { detail: data }));
+        break;
+      case 'code_execution_result':
+        document.dispatchEvent(new CustomEvent('code-execution-result', { detail: data }));
+        break;
       case 'file_updated':
         this.handleFileUpdated(data);
         break;
@@ -146,6 +186,79 @@ export class WebSocketManager {
     }
   }
 
+  // Server-Sent Events fallback for networks that block WebSocket upgrades.
+  // Receives the same broadcasts; requests go through the REST API instead.
+  startEventStream() {
+    if (this.eventSource || typeof EventSource === 'undefined') {
+      this.showConnectionError();
+      return;
+    }
+
+    this.eventSource = new EventSource('/api/events');
+
+    this.eventSource.onopen = () => {
+      console.log('✅ Event stream connected');
+      this.notifyConnectionStatus(true);
+      // Heartbeats need a WebSocket to travel over
+      this.stopHeartbeat();
+    };
+
+    this.eventSource.onmessage = (event) => {
+      try {
+        const data = JSON.parse(event.data);
+        if (data.type === 'resync') {
+          document.dispatchEvent(new CustomEvent('websocket-resync', { detail: data }));
+          return;
+        }
+        this.handleMessage(data);
+      } catch (error) {
+        console.error('Failed to parse event stream message:', error);
+      }
+    };
+
+    // EventSource reconnects on its own and resumes with Last-Event-ID
+    this.eventSource.onerror = () => {
+      this.notifyConnectionStatus(false);
+    };
+  }
+
+  // Binary frames: [kind][session id length][session id][payload]
+  decodeBinaryFrame(buffer) {
+    const bytes = new Uint8Array(buffer);
+    if (bytes.length < 2) {
+      return null;
+    }
+
+    const kind = bytes[0];
+    const idLength = bytes[1];
+    const sessionId = new TextDecoder().decode(bytes.subarray(2, 2 + idLength));
+    const payload = bytes.subarray(2 + idLength);
+
+    switch (kind) {
+      case 0x01:
+        return { type: 'terminal', action: 'output', sessionId, data: payload };
+      default:
+        console.warn('Unknown binary frame kind:', kind);
+        return null;
+    }
+  }
+
+  handleHello(data) {
+    this.serverInfo = data;
+    this.connectionId = data.connection_id;
+    if (this.protocolVersion < data.min_protocol_version || this.protocolVersion > data.protocol_version) {
+      console.warn(`Server speaks WebSocket protocol v${data.min_protocol_version}-v${data.protocol_version}, client speaks v${this.protocolVersion}`);
+    }
+  }
+
+  handleProtocolError(data) {
+    console.warn(`WebSocket error (${data.code}${data.request_type ? `, ${data.request_type}` : ''}): ${data.message}`);
+    if (data.code === 'unsupported_protocol_version') {
+      this.protocolRejected = true;
+      this.showConnectionError();
+    }
+  }
+
   handleFileUpdated(data) {
     // Show notification about file update with exercise name
     const displayName = data.exercise || data.file;
@@ -216,29 +329,35 @@ export class WebSocketManager {
     }
   }
 
+  sendHello() {
+    return this.send({
+      type: 'hello',
+      protocol_version: this.protocolVersion,
+      client: 'rust-tour-web'
+    });
+  }
+
   sendExerciseView(exerciseId) {
     return this.send({
       type: 'exercise_view',
-      exercise_id: exerciseId,
-      timestamp: Date.now()
+      exercise_id: exerciseId
     });
   }
 
-  sendCodeExecution(exerciseId, action, result) {
+  // The result arrives as a 'code-execution-result' event
+  sendCodeExecution(exerciseId, action) {
     return this.send({
       type: 'code_execution',
       exercise_id: exerciseId,
-      action: action, // 'run', 'test', 'check'
-      result: result,
-      timestamp: Date.now()
+      action: action // 'run', 'test', 'check'
     });
   }
 
-  sendProgressUpdate(progressData) {
+  // Ask the server to push current progress to every open tab
+  sendProgressUpdate(exerciseId) {
     return this.send({
       type: 'progress_update',
-      progress: progressData,
-      timestamp: Date.now()
+      exercise_id: exerciseId
     });
   }
 
@@ -439,6 +558,11 @@ export class WebSocketManager {
       this.ws.close(1000, 'User initiated disconnect');
       this.ws = null;
     }
+
+    if (this.eventSource) {
+      this.eventSource.close();
+      this.eventSource = null;
+    }
   }
 
   // Add typed message handler
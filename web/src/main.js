@@ -7,6 +7,7 @@ import { BookIntegration } from './js/book-integration.js';
 import { WebSocketManager } from './js/websocket-manager.js';
 import { UI } from './js/ui.js';
 import { TerminalManager } from './js/terminal.js';
+import { CollaborationSession } from './js/collaboration.js';
 
 class RustTour {
   constructor() {
@@ -47,6 +48,8 @@ class RustTour {
       // Connect UI and exercise manager
       this.exerciseManager.setUI(this.ui);
       
+      this.collaboration = new CollaborationSession(this.websocket, this.ui);
+      
       // Initialize terminal
       this.terminal.init('terminal');
       
@@ -123,6 +126,38 @@ class RustTour {
       this.completeExercise();
     });
 
+    // The server completed an exercise after a passing test run
+    document.addEventListener('exercise-completed', (e) => {
+      if (!this.currentExercise) return;
+      const currentId = this.currentExercise.id || this.currentExercise.metadata.id;
+      if (e.detail.exercise_id === currentId) {
+        this.ui.showCompletionCelebration();
+        const nextExercise = this.getNextExercise();
+        if (this.progressTracker.preferences?.auto_advance && nextExercise) {
+          // Give the celebration a moment before moving on
+          setTimeout(() => {
+            document.dispatchEvent(new CustomEvent('exercise-selected', {
+              detail: { path: nextExercise.path }
+            }));
+          }, 2000);
+        } else {
+          this.ui.showExerciseCompletion(this.currentExercise.metadata);
+        }
+      }
+    });
+
+    // The server withholds hints by preference; fetch the ones now allowed
+    document.addEventListener('preferences-changed', async () => {
+      if (!this.currentExercise) return;
+      try {
+        const exercise = await this.exerciseManager.loadExercise(this.currentExercise.path);
+        this.currentExercise.hints = exercise.hints;
+        this.ui.resetHints();
+      } catch (error) {
+        console.error('Failed to reload hints:', error);
+      }
+    });
+
     // Auto-save on code change
     let saveTimeout;
     document.addEventListener('code-changed', () => {
@@ -136,6 +171,8 @@ class RustTour {
     document.addEventListener('click', (e) => {
       if (e.target.id === 'terminal-btn') {
         this.toggleTerminal();
+      } else if (e.target.closest('#pair-btn')) {
+        this.togglePairing();
       } else if (e.target.id === 'terminal-close-btn') {
         this.hideTerminal();
       }
@@ -157,12 +194,21 @@ class RustTour {
       const exercise = await this.exerciseManager.loadExercise(exercisePath);
       this.currentExercise = exercise;
       
+      // Leave the previous exercise's documents before their editor models are replaced
+      const wasPairing = this.collaboration?.active;
+      if (wasPairing) {
+        this.collaboration.leaveAll();
+      }
+      
       // Update UI
       this.ui.updateExercise(exercise);
+      if (wasPairing) {
+        this.collaboration.joinExercise(exercise);
+      }
       await this.ui.updateBookPanel(exercise.metadata.rust_book_refs);
       
       // Update progress
-      await this.progressTracker.trackExerciseViewed(exercise.metadata.id);
+      await this.progressTracker.trackExerciseViewed(exercise.metadata.id, this.websocket.connectionId);
       
       // Save last exercise
       localStorage.setItem('lastExercise', exercisePath);
@@ -215,12 +261,7 @@ class RustTour {
       if (result.success) {
         this.ui.setExecutionStatus('success', 'All tests passed! 🎉');
         this.ui.updateTestResults(result.stdout);
-        
-        // Check if this completes the exercise
-        if (this.isExerciseComplete(result)) {
-          this.ui.showCompletionCelebration();
-          await this.completeExercise();
-        }
+        // The server completes the exercise and announces it with exercise_completed
       } else {
         this.ui.setExecutionStatus('warning', 'Some tests failed');
         this.ui.updateTestResults(result.output);
@@ -360,10 +401,6 @@ class RustTour {
     return null;
   }
 
-  isExerciseComplete(testResult) {
-    // Exercise is complete when all functional tests pass
-    return testResult.success;
-  }
 
   handleWebSocketMessage(data) {
     switch (data.type) {
@@ -378,6 +415,15 @@ class RustTour {
     }
   }
 
+  // Pair programming: share edits to the current exercise with other browsers
+  togglePairing() {
+    if (this.collaboration.active) {
+      this.collaboration.leaveAll();
+    } else {
+      this.collaboration.joinExercise(this.currentExercise);
+    }
+  }
+
   // Terminal control methods
   toggleTerminal() {
     const terminalBtn = document.getElementById('terminal-btn');